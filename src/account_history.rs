@@ -0,0 +1,734 @@
+//! Reconstruction of the balance history of an account.
+//!
+//! The node only provides the state of an account at a given block. The
+//! functionality in this module combines the transaction outcomes and special
+//! outcomes of all finalized blocks since the account was created to obtain
+//! every change of the account's balance together with its cause.
+//!
+//! The reconstructed balance is the total public balance of the account, i.e.,
+//! the `account_amount` field of [`AccountInfo`]. This includes the staked
+//! amount and any amounts that are locked in a release schedule. Releases
+//! according to a schedule thus do not change the balance, but transfers with
+//! a schedule do.
+use crate::{
+    types::{
+        hashes::{BlockHash, TransactionHash},
+        queries::BlockInfo,
+        AbsoluteBlockHeight, AccountInfo, AccountStakingInfo, AccountTransactionEffects, Address,
+        BakerEvent, BlockItemSummary, BlockItemSummaryDetails, ContractAddress,
+        ContractTraceElement, DelegationEvent, Memo, SpecialTransactionOutcome,
+    },
+    v2,
+};
+use concordium_base::{
+    common::types::{Amount, Timestamp},
+    contracts_common::AccountAddress,
+};
+use futures::TryStreamExt;
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when reconstructing the history of an account.
+pub enum AccountHistoryError {
+    #[error("Error querying the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("The account does not exist in the queried range of blocks.")]
+    AccountNotFound,
+    #[error(
+        "Reconstructed balance {computed} does not match the balance {actual} reported by the \
+         node in block {block_hash}."
+    )]
+    BalanceMismatch {
+        block_hash: BlockHash,
+        computed:   Amount,
+        actual:     Amount,
+    },
+    #[error("A debit of {debit} in block {block_hash} exceeds the tracked balance {balance}.")]
+    InsufficientBalance {
+        block_hash: BlockHash,
+        balance:    Amount,
+        debit:      Amount,
+    },
+}
+
+impl From<tonic::Status> for AccountHistoryError {
+    fn from(s: tonic::Status) -> Self { Self::Query(s.into()) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The role of an account in staking, used to classify rewards.
+pub enum StakerRole {
+    Baker,
+    Delegator,
+}
+
+impl StakerRole {
+    /// The staking role of the account, if the account is staking.
+    pub fn of_account(info: &AccountInfo) -> Option<Self> {
+        match info.account_stake {
+            Some(AccountStakingInfo::Baker { .. }) => Some(Self::Baker),
+            Some(AccountStakingInfo::Delegated { .. }) => Some(Self::Delegator),
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The cause of a change in the balance of an account.
+pub enum BalanceChangeKind {
+    /// The fee for a transaction sent from the account.
+    TransactionFee,
+    /// A plain transfer to or from another account.
+    Transfer { counterparty: AccountAddress },
+    /// A transfer with a release schedule to or from another account.
+    ScheduledTransfer {
+        counterparty: AccountAddress,
+        schedule:     Vec<(Timestamp, Amount)>,
+    },
+    /// A transfer between the public and the shielded balance of the account.
+    ShieldedTransfer,
+    /// An amount sent to a smart contract instance, either when initializing
+    /// or when updating it.
+    ContractPayment { contract: ContractAddress },
+    /// An amount transferred to the account by a smart contract instance.
+    ContractPayout { contract: ContractAddress },
+    /// Baking rewards and transaction fee rewards of a baker.
+    BakingReward,
+    /// Rewards for delegating stake to a pool.
+    DelegationReward,
+    /// Rewards for participating in finalization.
+    FinalizationReward,
+    /// The platform development charge paid to the foundation account.
+    FoundationReward,
+}
+
+#[derive(Debug, Clone)]
+/// A single change of the balance of an account.
+pub struct BalanceChange {
+    /// Hash of the block in which the change happened.
+    pub block_hash:   BlockHash,
+    /// Height of the block in which the change happened.
+    pub block_height: AbsoluteBlockHeight,
+    /// Slot time of the block in which the change happened.
+    pub block_time:   chrono::DateTime<chrono::Utc>,
+    /// The transaction that caused the change. This is [`None`] for rewards
+    /// and other changes that are not caused by transactions.
+    pub transaction:  Option<TransactionHash>,
+    /// The cause of the change.
+    pub kind:         BalanceChangeKind,
+    /// The amount added to the balance.
+    pub credit:       Amount,
+    /// The amount removed from the balance.
+    pub debit:        Amount,
+    /// The memo of the transfer, if any.
+    pub memo:         Option<Memo>,
+    /// The balance after the change was applied.
+    pub balance:      Amount,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A block at which the reconstructed balance was compared to the balance
+/// reported by the node.
+pub struct Checkpoint {
+    pub block_hash:   BlockHash,
+    pub block_height: AbsoluteBlockHeight,
+    pub balance:      Amount,
+}
+
+#[derive(Debug, Clone)]
+/// The history of an account over a range of blocks.
+pub struct AccountHistory {
+    pub address:         AccountAddress,
+    /// Balance of the account before the first change.
+    pub initial_balance: Amount,
+    /// All changes of the balance, in the order they happened.
+    pub changes:         Vec<BalanceChange>,
+    /// Blocks at which the balance was checked against the node.
+    pub checkpoints:     Vec<Checkpoint>,
+}
+
+impl AccountHistory {
+    /// The balance after all the changes.
+    pub fn final_balance(&self) -> Amount {
+        self.changes
+            .last()
+            .map_or(self.initial_balance, |change| change.balance)
+    }
+}
+
+/// Incrementally computes balance changes of an account from block outcomes.
+#[derive(Debug, Clone)]
+pub struct BalanceTracker {
+    address: AccountAddress,
+    balance: Amount,
+    role:    Option<StakerRole>,
+}
+
+impl BalanceTracker {
+    /// Start tracking the account with the given balance and staking role.
+    /// Addresses that are aliases of `address` are treated as the same
+    /// account.
+    pub fn new(address: AccountAddress, balance: Amount, role: Option<StakerRole>) -> Self {
+        Self {
+            address,
+            balance,
+            role,
+        }
+    }
+
+    /// The current balance of the account.
+    pub fn balance(&self) -> Amount { self.balance }
+
+    /// The staking role of the account, as last observed. The role is kept
+    /// when the account stops baking or delegating, since the account is
+    /// still rewarded in that role until its stake is released after the
+    /// cooldown period.
+    pub fn role(&self) -> Option<StakerRole> { self.role }
+
+    fn is_own(&self, addr: &AccountAddress) -> bool { self.address.is_alias(addr) }
+
+    fn is_affected(&self, addresses: Vec<AccountAddress>) -> bool {
+        addresses.iter().any(|addr| self.is_own(addr))
+    }
+
+    /// Compute the balance changes in the given block, and update the tracked
+    /// balance accordingly. The `summaries` and `special` should be all the
+    /// transaction outcomes and special outcomes of the block, in order.
+    ///
+    /// If a change would make the balance negative an
+    /// [`AccountHistoryError::InsufficientBalance`] is returned and the
+    /// balance is not updated. This means that the tracked balance is wrong,
+    /// e.g., because the tracker was started with the wrong balance.
+    pub fn process_block<'a>(
+        &mut self,
+        block: &BlockInfo,
+        summaries: impl IntoIterator<Item = &'a BlockItemSummary>,
+        special: impl IntoIterator<Item = &'a SpecialTransactionOutcome>,
+    ) -> Result<Vec<BalanceChange>, AccountHistoryError> {
+        let mut raw = Vec::new();
+        for summary in summaries {
+            if !self.is_affected(summary.affected_addresses()) {
+                continue;
+            }
+            self.summary_changes(summary, &mut raw);
+            self.update_role(summary);
+        }
+        for outcome in special {
+            if !self.is_affected(outcome.affected_addresses()) {
+                continue;
+            }
+            self.special_changes(outcome, &mut raw);
+        }
+        let mut balance = self.balance;
+        let mut changes = Vec::with_capacity(raw.len());
+        for (transaction, kind, credit, debit, memo) in raw {
+            let micro = balance.micro_ccd() + credit.micro_ccd();
+            let Some(micro) = micro.checked_sub(debit.micro_ccd()) else {
+                return Err(AccountHistoryError::InsufficientBalance {
+                    block_hash: block.block_hash,
+                    balance,
+                    debit,
+                });
+            };
+            balance = Amount::from_micro_ccd(micro);
+            changes.push(BalanceChange {
+                block_hash: block.block_hash,
+                block_height: block.block_height,
+                block_time: block.block_slot_time,
+                transaction,
+                kind,
+                credit,
+                debit,
+                memo,
+                balance,
+            });
+        }
+        self.balance = balance;
+        Ok(changes)
+    }
+
+    fn update_role(&mut self, summary: &BlockItemSummary) {
+        let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+            return;
+        };
+        if !self.is_own(&at.sender) {
+            return;
+        }
+        match &at.effects {
+            AccountTransactionEffects::BakerAdded { .. } => self.role = Some(StakerRole::Baker),
+            AccountTransactionEffects::BakerConfigured { data } => {
+                for event in data {
+                    match event {
+                        BakerEvent::BakerAdded { .. } => self.role = Some(StakerRole::Baker),
+                        _ => (),
+                    }
+                }
+            }
+            AccountTransactionEffects::DelegationConfigured { data } => {
+                for event in data {
+                    match event {
+                        DelegationEvent::DelegationAdded { .. } => {
+                            self.role = Some(StakerRole::Delegator)
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn summary_changes(&self, summary: &BlockItemSummary, out: &mut Vec<RawChange>) {
+        let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+            return;
+        };
+        let tx = Some(summary.hash);
+        let zero = Amount::from_micro_ccd(0);
+        let own_sender = self.is_own(&at.sender);
+        if own_sender && at.cost.micro_ccd() > 0 {
+            out.push((tx, BalanceChangeKind::TransactionFee, zero, at.cost, None));
+        }
+        // Record a transfer between the sender and `to`, unless both are the
+        // tracked account, in which case the balance does not change.
+        let mut transfer = |to: &AccountAddress,
+                            amount: Amount,
+                            kind: &dyn Fn(AccountAddress) -> BalanceChangeKind,
+                            memo: Option<&Memo>| {
+            let own_receiver = self.is_own(to);
+            if own_sender && !own_receiver {
+                out.push((tx, kind(*to), zero, amount, memo.cloned()));
+            } else if own_receiver && !own_sender {
+                out.push((tx, kind(at.sender), amount, zero, memo.cloned()));
+            }
+        };
+        match &at.effects {
+            AccountTransactionEffects::AccountTransfer { amount, to } => transfer(
+                to,
+                *amount,
+                &|counterparty| BalanceChangeKind::Transfer { counterparty },
+                None,
+            ),
+            AccountTransactionEffects::AccountTransferWithMemo { amount, to, memo } => transfer(
+                to,
+                *amount,
+                &|counterparty| BalanceChangeKind::Transfer { counterparty },
+                Some(memo),
+            ),
+            AccountTransactionEffects::TransferredWithSchedule { to, amount } => transfer(
+                to,
+                schedule_total(amount),
+                &|counterparty| BalanceChangeKind::ScheduledTransfer {
+                    counterparty,
+                    schedule: amount.clone(),
+                },
+                None,
+            ),
+            AccountTransactionEffects::TransferredWithScheduleAndMemo { to, amount, memo } => {
+                transfer(
+                    to,
+                    schedule_total(amount),
+                    &|counterparty| BalanceChangeKind::ScheduledTransfer {
+                        counterparty,
+                        schedule: amount.clone(),
+                    },
+                    Some(memo),
+                )
+            }
+            AccountTransactionEffects::TransferredToEncrypted { data } if own_sender => {
+                out.push((tx, BalanceChangeKind::ShieldedTransfer, zero, data.amount, None));
+            }
+            AccountTransactionEffects::TransferredToPublic { amount, .. } if own_sender => {
+                out.push((tx, BalanceChangeKind::ShieldedTransfer, *amount, zero, None));
+            }
+            AccountTransactionEffects::ContractInitialized { data }
+                if own_sender && data.amount.micro_ccd() > 0 =>
+            {
+                out.push((
+                    tx,
+                    BalanceChangeKind::ContractPayment {
+                        contract: data.address,
+                    },
+                    zero,
+                    data.amount,
+                    None,
+                ));
+            }
+            AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                for effect in effects {
+                    match effect {
+                        ContractTraceElement::Updated { data } => {
+                            if let Address::Account(instigator) = &data.instigator {
+                                if self.is_own(instigator) && data.amount.micro_ccd() > 0 {
+                                    out.push((
+                                        tx,
+                                        BalanceChangeKind::ContractPayment {
+                                            contract: data.address,
+                                        },
+                                        zero,
+                                        data.amount,
+                                        None,
+                                    ));
+                                }
+                            }
+                        }
+                        ContractTraceElement::Transferred { from, amount, to } => {
+                            if self.is_own(to) {
+                                out.push((
+                                    tx,
+                                    BalanceChangeKind::ContractPayout { contract: *from },
+                                    *amount,
+                                    zero,
+                                    None,
+                                ));
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn special_changes(&self, outcome: &SpecialTransactionOutcome, out: &mut Vec<RawChange>) {
+        let zero = Amount::from_micro_ccd(0);
+        let mut credit = |kind: BalanceChangeKind, amount: Amount| {
+            if amount.micro_ccd() > 0 {
+                out.push((None, kind, amount, zero, None));
+            }
+        };
+        match outcome {
+            SpecialTransactionOutcome::BakingRewards { baker_rewards, .. } => {
+                for (addr, amount) in baker_rewards {
+                    if self.is_own(addr) {
+                        credit(BalanceChangeKind::BakingReward, *amount);
+                    }
+                }
+            }
+            SpecialTransactionOutcome::Mint {
+                mint_platform_development_charge,
+                foundation_account,
+                ..
+            } => {
+                if self.is_own(foundation_account) {
+                    credit(
+                        BalanceChangeKind::FoundationReward,
+                        *mint_platform_development_charge,
+                    );
+                }
+            }
+            SpecialTransactionOutcome::FinalizationRewards {
+                finalization_rewards,
+                ..
+            } => {
+                for (addr, amount) in finalization_rewards {
+                    if self.is_own(addr) {
+                        credit(BalanceChangeKind::FinalizationReward, *amount);
+                    }
+                }
+            }
+            SpecialTransactionOutcome::BlockReward {
+                baker_reward,
+                foundation_charge,
+                baker,
+                foundation_account,
+                ..
+            } => {
+                if self.is_own(baker) {
+                    credit(BalanceChangeKind::BakingReward, *baker_reward);
+                }
+                if self.is_own(foundation_account) {
+                    credit(BalanceChangeKind::FoundationReward, *foundation_charge);
+                }
+            }
+            SpecialTransactionOutcome::PaydayFoundationReward {
+                foundation_account,
+                development_charge,
+            } => {
+                if self.is_own(foundation_account) {
+                    credit(BalanceChangeKind::FoundationReward, *development_charge);
+                }
+            }
+            SpecialTransactionOutcome::PaydayAccountReward {
+                account,
+                transaction_fees,
+                baker_reward,
+                finalization_reward,
+            } => {
+                if !self.is_own(account) {
+                    return;
+                }
+                if self.role == Some(StakerRole::Delegator) {
+                    let total = transaction_fees.micro_ccd()
+                        + baker_reward.micro_ccd()
+                        + finalization_reward.micro_ccd();
+                    credit(
+                        BalanceChangeKind::DelegationReward,
+                        Amount::from_micro_ccd(total),
+                    );
+                } else {
+                    let total = transaction_fees.micro_ccd() + baker_reward.micro_ccd();
+                    credit(
+                        BalanceChangeKind::BakingReward,
+                        Amount::from_micro_ccd(total),
+                    );
+                    credit(BalanceChangeKind::FinalizationReward, *finalization_reward);
+                }
+            }
+            SpecialTransactionOutcome::BlockAccrueReward { .. } => (),
+            SpecialTransactionOutcome::PaydayPoolReward { .. } => (),
+        }
+    }
+}
+
+type RawChange = (
+    Option<TransactionHash>,
+    BalanceChangeKind,
+    Amount,
+    Amount,
+    Option<Memo>,
+);
+
+fn schedule_total(schedule: &[(Timestamp, Amount)]) -> Amount {
+    Amount::from_micro_ccd(schedule.iter().map(|(_, amount)| amount.micro_ccd()).sum())
+}
+
+/// Query the outcomes of the given block and compute the balance changes of
+/// the tracked account in it.
+pub async fn query_block_changes(
+    client: &mut v2::Client,
+    tracker: &mut BalanceTracker,
+    block_hash: BlockHash,
+) -> Result<Vec<BalanceChange>, AccountHistoryError> {
+    let info = client.get_block_info(block_hash).await?.response;
+    let summaries = client
+        .get_block_transaction_events(block_hash)
+        .await?
+        .response
+        .try_collect::<Vec<_>>()
+        .await?;
+    let special = client
+        .get_block_special_events(block_hash)
+        .await?
+        .response
+        .try_collect::<Vec<_>>()
+        .await?;
+    tracker.process_block(&info, &summaries, &special)
+}
+
+/// Reconstruct the history of the account from its creation until the
+/// `end` height (inclusive), or until the last finalized block if `end` is
+/// [`None`].
+///
+/// Every `checkpoint_interval` blocks, and at the last block, the
+/// reconstructed balance is compared to the balance reported by the node. If
+/// they differ an [`AccountHistoryError::BalanceMismatch`] is returned. A
+/// `checkpoint_interval` of `0` only checks the last block.
+pub async fn reconstruct_history(
+    client: &mut v2::Client,
+    address: AccountAddress,
+    end: Option<AbsoluteBlockHeight>,
+    checkpoint_interval: u64,
+) -> Result<AccountHistory, AccountHistoryError> {
+    let end = match end {
+        Some(end) => end,
+        None => client.get_consensus_info().await?.last_finalized_block_height,
+    };
+    let (created, _, info) = match client.find_account_creation(..=end, address).await {
+        Ok(r) => r,
+        Err(e) if e.is_not_found() => return Err(AccountHistoryError::AccountNotFound),
+        Err(e) => return Err(e.into()),
+    };
+    // Accounts created in the genesis block start with a non-zero balance.
+    // Other accounts are created with a zero balance, but the creation block
+    // can already contain transfers to the account.
+    let (initial_balance, start) = if created.height == 0 {
+        (info.account_amount, created.next())
+    } else {
+        (Amount::from_micro_ccd(0), created)
+    };
+    let mut tracker =
+        BalanceTracker::new(address, initial_balance, StakerRole::of_account(&info));
+    let mut history = AccountHistory {
+        address,
+        initial_balance,
+        changes: Vec::new(),
+        checkpoints: Vec::new(),
+    };
+    if start > end {
+        return Ok(history);
+    }
+    let mut blocks = client.get_finalized_blocks_from(start).await?;
+    while let Some(block) = blocks.next().await {
+        if block.height > end {
+            break;
+        }
+        let changes = query_block_changes(client, &mut tracker, block.block_hash).await?;
+        history.changes.extend(changes);
+        let at_checkpoint = checkpoint_interval > 0
+            && (block.height.height - start.height + 1) % checkpoint_interval == 0;
+        if at_checkpoint || block.height == end {
+            let actual = client
+                .get_account_info(&address.into(), block.block_hash)
+                .await?
+                .response
+                .account_amount;
+            if actual != tracker.balance() {
+                return Err(AccountHistoryError::BalanceMismatch {
+                    block_hash: block.block_hash,
+                    computed: tracker.balance(),
+                    actual,
+                });
+            }
+            history.checkpoints.push(Checkpoint {
+                block_hash:   block.block_hash,
+                block_height: block.height,
+                balance:      actual,
+            });
+        }
+        if block.height == end {
+            break;
+        }
+    }
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        hashes::HashBytes, AccountTransactionDetails, BakerId, BlockHeight, DelegatorId, Energy,
+        GenesisIndex, ProtocolVersion, TransactionIndex,
+    };
+
+    fn block() -> BlockInfo {
+        let now = chrono::Utc::now();
+        BlockInfo {
+            transactions_size:       0,
+            block_parent:            HashBytes::new([0u8; 32]),
+            block_hash:              HashBytes::new([1u8; 32]),
+            finalized:               true,
+            block_state_hash:        HashBytes::new([0u8; 32]),
+            block_arrive_time:       now,
+            block_receive_time:      now,
+            transaction_count:       0,
+            transaction_energy_cost: Energy { energy: 0 },
+            block_slot:              None,
+            block_last_finalized:    HashBytes::new([0u8; 32]),
+            block_slot_time:         now,
+            block_height:            AbsoluteBlockHeight { height: 1 },
+            era_block_height:        BlockHeight { height: 1 },
+            genesis_index:           GenesisIndex { height: 0 },
+            block_baker:             None,
+            protocol_version:        ProtocolVersion::P6,
+            round:                   None,
+            epoch:                   None,
+        }
+    }
+
+    fn summary(
+        index: u64,
+        sender: AccountAddress,
+        cost: u64,
+        effects: AccountTransactionEffects,
+    ) -> BlockItemSummary {
+        BlockItemSummary {
+            index:       TransactionIndex { index },
+            energy_cost: Energy { energy: 0 },
+            hash:        HashBytes::new([index as u8; 32]),
+            details:     BlockItemSummaryDetails::AccountTransaction(AccountTransactionDetails {
+                cost: Amount::from_micro_ccd(cost),
+                sender,
+                effects,
+            }),
+        }
+    }
+
+    fn transfer(to: AccountAddress, amount: u64) -> AccountTransactionEffects {
+        AccountTransactionEffects::AccountTransfer {
+            amount: Amount::from_micro_ccd(amount),
+            to,
+        }
+    }
+
+    const OWN: AccountAddress = AccountAddress([1u8; 32]);
+    const OTHER: AccountAddress = AccountAddress([2u8; 32]);
+    const THIRD: AccountAddress = AccountAddress([3u8; 32]);
+
+    #[test]
+    fn test_process_block_transfers() {
+        let mut tracker = BalanceTracker::new(OWN, Amount::from_micro_ccd(1000), None);
+        let summaries = [
+            summary(0, OTHER, 10, transfer(OWN, 500)),
+            // Transfers between other accounts do not affect the balance.
+            summary(1, OTHER, 10, transfer(THIRD, 100)),
+            summary(2, OWN, 10, transfer(OTHER, 300)),
+        ];
+        let changes = tracker.process_block(&block(), &summaries, std::iter::empty()).unwrap();
+        let balances = changes
+            .iter()
+            .map(|change| change.balance.micro_ccd())
+            .collect::<Vec<_>>();
+        assert_eq!(balances, [1500, 1490, 1190]);
+        assert_eq!(changes[0].kind, BalanceChangeKind::Transfer {
+            counterparty: OTHER,
+        });
+        assert_eq!(changes[1].kind, BalanceChangeKind::TransactionFee);
+        assert_eq!(tracker.balance(), Amount::from_micro_ccd(1190));
+    }
+
+    #[test]
+    fn test_process_block_insufficient_balance() {
+        let mut tracker = BalanceTracker::new(OWN, Amount::from_micro_ccd(100), None);
+        let summaries = [summary(0, OWN, 10, transfer(OTHER, 100))];
+        assert!(matches!(
+            tracker.process_block(&block(), &summaries, std::iter::empty()),
+            Err(AccountHistoryError::InsufficientBalance { .. })
+        ));
+        // The balance is not changed by a failed block.
+        assert_eq!(tracker.balance(), Amount::from_micro_ccd(100));
+    }
+
+    #[test]
+    fn test_process_block_role() {
+        let reward = SpecialTransactionOutcome::PaydayAccountReward {
+            account:             OWN,
+            transaction_fees:    Amount::from_micro_ccd(1),
+            baker_reward:        Amount::from_micro_ccd(2),
+            finalization_reward: Amount::from_micro_ccd(3),
+        };
+        let mut tracker =
+            BalanceTracker::new(OWN, Amount::from_micro_ccd(100), Some(StakerRole::Delegator));
+        let changes = tracker.process_block(&block(), std::iter::empty(), [&reward]).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, BalanceChangeKind::DelegationReward);
+        assert_eq!(changes[0].credit, Amount::from_micro_ccd(6));
+
+        let removed = AccountTransactionEffects::DelegationConfigured {
+            data: vec![DelegationEvent::DelegationRemoved {
+                delegator_id: DelegatorId { id: 1.into() },
+            }],
+        };
+        let summaries = [summary(0, OWN, 0, removed)];
+        tracker.process_block(&block(), &summaries, std::iter::empty()).unwrap();
+        // Rewards during the cooldown are still delegation rewards.
+        assert_eq!(tracker.role(), Some(StakerRole::Delegator));
+        let changes = tracker.process_block(&block(), std::iter::empty(), [&reward]).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, BalanceChangeKind::DelegationReward);
+
+        let mut tracker =
+            BalanceTracker::new(OWN, Amount::from_micro_ccd(100), Some(StakerRole::Baker));
+        let removed = AccountTransactionEffects::BakerRemoved {
+            baker_id: BakerId { id: 1.into() },
+        };
+        let summaries = [summary(0, OWN, 0, removed)];
+        tracker.process_block(&block(), &summaries, std::iter::empty()).unwrap();
+        assert_eq!(tracker.role(), Some(StakerRole::Baker));
+        let changes = tracker.process_block(&block(), std::iter::empty(), [&reward]).unwrap();
+        let kinds = changes.into_iter().map(|change| change.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [
+            BalanceChangeKind::BakingReward,
+            BalanceChangeKind::FinalizationReward
+        ]);
+    }
+}
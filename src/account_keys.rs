@@ -0,0 +1,511 @@
+//! High-level support for rotating the keys of an account and for managing the
+//! set of credentials on it.
+//!
+//! The main entrypoint is [`KeyRotationPlan`]. A plan is constructed from the
+//! current [`AccountInfo`] of an account, modified with the desired changes,
+//! and then turned into a list of `UpdateCredentials` and
+//! `UpdateCredentialKeys` transactions. After the transactions are finalized
+//! the plan can be used to [verify](verify_plan) that the on-chain access
+//! structure is as expected.
+use crate::{
+    id::{
+        constants::{ArCurve, AttributeKind, IpPairing},
+        types::{AccountCredentialWithoutProofs, CredentialDeploymentInfo, CredentialPublicKeys},
+    },
+    types::{
+        hashes::{BlockHash, TransactionHash},
+        transactions::{self, send, AccountTransaction, EncodedPayload},
+        AccountInfo, AccountThreshold, BlockItemSummary, CredentialIndex,
+        CredentialRegistrationID, Nonce, RejectReason, WalletAccount,
+    },
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::{common::types::TransactionTime, contracts_common::AccountAddress};
+use std::collections::BTreeMap;
+
+/// A credential that is to be deployed onto an existing account.
+pub type NewCredential = CredentialDeploymentInfo<IpPairing, ArCurve, AttributeKind>;
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when constructing a [`KeyRotationPlan`].
+pub enum KeyRotationError {
+    #[error("Credential with index {} does not exist on the account.", .0.index)]
+    UnknownCredential(CredentialIndex),
+    #[error("Credential index {} is already in use.", .0.index)]
+    CredentialIndexInUse(CredentialIndex),
+    #[error("The credential with index 0 cannot be removed from an account.")]
+    CannotRemoveInitialCredential,
+    #[error(
+        "The account threshold {} exceeds the number of credentials {num_credentials}.",
+        u8::from(*.threshold)
+    )]
+    ThresholdTooLarge {
+        threshold:       AccountThreshold,
+        num_credentials: usize,
+    },
+    #[error(
+        "The keys for credential {} have a threshold larger than the number of keys.",
+        .0.index
+    )]
+    InvalidCredentialKeys(CredentialIndex),
+    #[error("The plan does not contain any changes.")]
+    EmptyPlan,
+}
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when [executing](execute_plan) or
+/// [verifying](verify_plan) a [`KeyRotationPlan`].
+pub enum KeyRotationExecutionError {
+    #[error("The plan is not valid: {0}")]
+    InvalidPlan(#[from] KeyRotationError),
+    #[error("Unable to query the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Unable to send transaction: {0}")]
+    Send(#[from] v2::RPCError),
+    #[error("Transaction {hash} was rejected: {reason:?}")]
+    Rejected {
+        hash:   TransactionHash,
+        reason: RejectReason,
+    },
+    #[error("The on-chain state of the account differs from the plan: {0}")]
+    Mismatch(String),
+}
+
+#[derive(Debug, Clone)]
+/// A planned change to the access structure of an account.
+///
+/// The plan records the state of the account at the time it was created.
+/// Changes are validated against that state when they are added to the plan.
+pub struct KeyRotationPlan {
+    /// Address of the account the plan applies to.
+    pub address:          AccountAddress,
+    /// Registration IDs of the credentials currently on the account.
+    current_cred_ids:     BTreeMap<CredentialIndex, CredentialRegistrationID>,
+    /// Public keys of the credentials currently on the account.
+    current_keys:         BTreeMap<CredentialIndex, CredentialPublicKeys>,
+    /// Current account threshold.
+    current_threshold:    AccountThreshold,
+    /// Credentials whose keys are to be replaced, and their new keys.
+    key_updates:          BTreeMap<CredentialIndex, CredentialPublicKeys>,
+    /// Credentials to deploy onto the account.
+    new_credentials:      BTreeMap<CredentialIndex, NewCredential>,
+    /// Credentials to remove from the account.
+    removed_credentials:  Vec<CredentialIndex>,
+    /// The new threshold, if it is to change.
+    new_threshold:        Option<AccountThreshold>,
+}
+
+impl KeyRotationPlan {
+    /// Start an empty plan for the account with the given information.
+    pub fn new(account: &AccountInfo) -> Self {
+        let mut current_cred_ids = BTreeMap::new();
+        let mut current_keys = BTreeMap::new();
+        for (&idx, cred) in account.account_credentials.iter() {
+            current_cred_ids.insert(idx, CredentialRegistrationID::new(*cred.value.cred_id()));
+            let keys = match &cred.value {
+                AccountCredentialWithoutProofs::Initial { icdv } => icdv.cred_account.clone(),
+                AccountCredentialWithoutProofs::Normal { cdv, .. } => cdv.cred_key_info.clone(),
+            };
+            current_keys.insert(idx, keys);
+        }
+        Self {
+            address: account.account_address,
+            current_cred_ids,
+            current_keys,
+            current_threshold: account.account_threshold,
+            key_updates: BTreeMap::new(),
+            new_credentials: BTreeMap::new(),
+            removed_credentials: Vec::new(),
+            new_threshold: None,
+        }
+    }
+
+    /// Replace the keys of an existing credential with the given keys.
+    pub fn replace_keys(
+        &mut self,
+        index: CredentialIndex,
+        keys: CredentialPublicKeys,
+    ) -> Result<&mut Self, KeyRotationError> {
+        if !self.current_cred_ids.contains_key(&index) || self.removed_credentials.contains(&index)
+        {
+            return Err(KeyRotationError::UnknownCredential(index));
+        }
+        if usize::from(u8::from(keys.threshold)) > keys.keys.len() {
+            return Err(KeyRotationError::InvalidCredentialKeys(index));
+        }
+        self.key_updates.insert(index, keys);
+        Ok(self)
+    }
+
+    /// Deploy a new credential onto the account at the given index.
+    pub fn add_credential(
+        &mut self,
+        index: CredentialIndex,
+        credential: NewCredential,
+    ) -> Result<&mut Self, KeyRotationError> {
+        let in_use = self.current_cred_ids.contains_key(&index)
+            && !self.removed_credentials.contains(&index);
+        if in_use || self.new_credentials.contains_key(&index) {
+            return Err(KeyRotationError::CredentialIndexInUse(index));
+        }
+        self.new_credentials.insert(index, credential);
+        Ok(self)
+    }
+
+    /// Remove the credential with the given index from the account. The
+    /// credential with index 0 cannot be removed.
+    pub fn remove_credential(
+        &mut self,
+        index: CredentialIndex,
+    ) -> Result<&mut Self, KeyRotationError> {
+        if index.index == 0 {
+            return Err(KeyRotationError::CannotRemoveInitialCredential);
+        }
+        if !self.current_cred_ids.contains_key(&index) {
+            return Err(KeyRotationError::UnknownCredential(index));
+        }
+        // There is no point in updating keys of a credential that is removed.
+        self.key_updates.remove(&index);
+        if !self.removed_credentials.contains(&index) {
+            self.removed_credentials.push(index);
+        }
+        Ok(self)
+    }
+
+    /// Set the account threshold, i.e., the number of credentials that must
+    /// sign a transaction. Setting the current threshold is not a change.
+    pub fn set_threshold(&mut self, threshold: AccountThreshold) -> &mut Self {
+        self.new_threshold = Some(threshold);
+        self
+    }
+
+    /// The threshold of the account after the plan is executed.
+    pub fn expected_threshold(&self) -> AccountThreshold {
+        self.new_threshold.unwrap_or(self.current_threshold)
+    }
+
+    /// The credential keys of the account after the plan is executed.
+    pub fn expected_keys(&self) -> BTreeMap<CredentialIndex, CredentialPublicKeys> {
+        let mut keys = self.current_keys.clone();
+        for idx in &self.removed_credentials {
+            keys.remove(idx);
+        }
+        for (&idx, cred) in self.new_credentials.iter() {
+            keys.insert(idx, cred.values.cred_key_info.clone());
+        }
+        for (&idx, new_keys) in self.key_updates.iter() {
+            keys.insert(idx, new_keys.clone());
+        }
+        keys
+    }
+
+    /// Whether the plan changes the account threshold.
+    fn changes_threshold(&self) -> bool {
+        self.new_threshold.map_or(false, |t| t != self.current_threshold)
+    }
+
+    /// Check that the plan is consistent and results in a usable account.
+    pub fn validate(&self) -> Result<(), KeyRotationError> {
+        if self.key_updates.is_empty()
+            && self.new_credentials.is_empty()
+            && self.removed_credentials.is_empty()
+            && !self.changes_threshold()
+        {
+            return Err(KeyRotationError::EmptyPlan);
+        }
+        let num_credentials = self.expected_keys().len();
+        let threshold = self.expected_threshold();
+        if usize::from(u8::from(threshold)) > num_credentials {
+            return Err(KeyRotationError::ThresholdTooLarge {
+                threshold,
+                num_credentials,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the plan requires an `UpdateCredentials` transaction.
+    fn updates_credentials(&self) -> bool {
+        !self.new_credentials.is_empty()
+            || !self.removed_credentials.is_empty()
+            || self.changes_threshold()
+    }
+
+    /// Construct the transactions that implement the plan. The transactions
+    /// use consecutive nonces starting with the supplied one.
+    ///
+    /// If credentials are added, removed, or the threshold changes then the
+    /// first transaction is an `UpdateCredentials` transaction. This is
+    /// followed by one `UpdateCredentialKeys` transaction per credential
+    /// whose keys are replaced. Since these transactions are signed with the
+    /// keys of the `signer` they must be sent in order, and the key updates
+    /// should be sent last since the `signer` might not have the new keys.
+    pub fn transactions(
+        &self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        nonce: Nonce,
+        expiry: TransactionTime,
+    ) -> Result<Vec<AccountTransaction<EncodedPayload>>, KeyRotationError> {
+        self.validate()?;
+        let mut nonce = nonce;
+        let mut out = Vec::new();
+        let mut num_existing = self.current_cred_ids.len() as u16;
+        if self.updates_credentials() {
+            let remove_cred_ids = self
+                .removed_credentials
+                .iter()
+                .filter_map(|idx| self.current_cred_ids.get(idx).copied())
+                .collect::<Vec<_>>();
+            out.push(send::update_credentials(
+                signer,
+                self.address,
+                nonce,
+                expiry,
+                num_existing,
+                self.new_credentials.clone(),
+                remove_cred_ids,
+                self.expected_threshold(),
+            ));
+            nonce.next_mut();
+            num_existing = self.expected_keys().len() as u16;
+        }
+        for (idx, keys) in self.key_updates.iter() {
+            // Key updates are only allowed for credentials that exist and are not
+            // removed, which is checked when they are added to the plan.
+            let Some(&cred_id) = self.current_cred_ids.get(idx) else {
+                return Err(KeyRotationError::UnknownCredential(*idx));
+            };
+            out.push(send::update_credential_keys(
+                signer,
+                self.address,
+                nonce,
+                expiry,
+                num_existing,
+                cred_id,
+                keys.clone(),
+            ));
+            nonce.next_mut();
+        }
+        Ok(out)
+    }
+}
+
+/// Check that the access structure of the account in the given block matches
+/// what is expected after executing the plan.
+pub async fn verify_plan(
+    client: &mut v2::Client,
+    plan: &KeyRotationPlan,
+    bi: impl IntoBlockIdentifier,
+) -> Result<BlockHash, KeyRotationExecutionError> {
+    let ai = client.get_account_info(&plan.address.into(), bi).await?;
+    let info = ai.response;
+    if info.account_threshold != plan.expected_threshold() {
+        return Err(KeyRotationExecutionError::Mismatch(format!(
+            "expected threshold {}, but the account has threshold {}",
+            u8::from(plan.expected_threshold()),
+            u8::from(info.account_threshold)
+        )));
+    }
+    let expected = plan.expected_keys();
+    let actual = KeyRotationPlan::new(&info).current_keys;
+    if expected.len() != actual.len() {
+        return Err(KeyRotationExecutionError::Mismatch(format!(
+            "expected {} credentials, but the account has {}",
+            expected.len(),
+            actual.len()
+        )));
+    }
+    for (idx, keys) in expected.iter() {
+        match actual.get(idx) {
+            Some(actual_keys) if actual_keys == keys => (),
+            Some(_) => {
+                return Err(KeyRotationExecutionError::Mismatch(format!(
+                    "keys of credential {} differ from the expected keys",
+                    idx.index
+                )))
+            }
+            None => {
+                return Err(KeyRotationExecutionError::Mismatch(format!(
+                    "credential {} is not present on the account",
+                    idx.index
+                )))
+            }
+        }
+    }
+    Ok(ai.block_hash)
+}
+
+/// Execute the plan using the keys of the given wallet. The transactions are
+/// sent one by one, and each is awaited until it is finalized before the next
+/// one is sent. After all transactions are finalized the resulting state of
+/// the account is [verified](verify_plan).
+///
+/// The current nonce of the account is looked up from the node. The return
+/// value is the list of outcomes of the transactions, in the order they were
+/// sent.
+///
+/// Note that if the keys of a credential in the `wallet` are replaced then the
+/// wallet must be updated with the new keys before it can be used to sign
+/// further transactions.
+pub async fn execute_plan(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    plan: &KeyRotationPlan,
+    expiry: TransactionTime,
+) -> Result<Vec<(BlockHash, BlockItemSummary)>, KeyRotationExecutionError> {
+    let nonce = client
+        .get_next_account_sequence_number(&wallet.address)
+        .await?
+        .nonce;
+    let txs = plan.transactions(wallet, nonce, expiry)?;
+    let mut outcomes = Vec::with_capacity(txs.len());
+    let mut last_block = None;
+    for tx in txs {
+        let hash = client.send_account_transaction(tx).await?;
+        let (bh, summary) = client.wait_until_finalized(&hash).await?;
+        if let Some(reason) = summary.is_rejected_account_transaction() {
+            return Err(KeyRotationExecutionError::Rejected {
+                hash,
+                reason: reason.clone(),
+            });
+        }
+        last_block = Some(bh);
+        outcomes.push((bh, summary));
+    }
+    if let Some(bh) = last_block {
+        verify_plan(client, plan, bh).await?;
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::types::{KeyIndex, KeyPair},
+        id::{curve_arithmetic::Curve, types::SignatureThreshold},
+    };
+
+    fn keys(csprng: &mut rand::rngs::ThreadRng) -> CredentialPublicKeys {
+        let kp = KeyPair::generate(csprng);
+        CredentialPublicKeys {
+            keys:      [(KeyIndex(0), (&kp).into())].into_iter().collect(),
+            threshold: SignatureThreshold::try_from(1).unwrap(),
+        }
+    }
+
+    /// A plan for an account with the given number of credentials and
+    /// account threshold 1.
+    fn plan(num_credentials: u8) -> KeyRotationPlan {
+        let mut csprng = rand::thread_rng();
+        let mut current_cred_ids = BTreeMap::new();
+        let mut current_keys = BTreeMap::new();
+        for index in 0..num_credentials {
+            let idx = CredentialIndex { index };
+            let cred_id = CredentialRegistrationID::new(ArCurve::generate(&mut csprng));
+            current_cred_ids.insert(idx, cred_id);
+            current_keys.insert(idx, keys(&mut csprng));
+        }
+        KeyRotationPlan {
+            address: AccountAddress([0u8; 32]),
+            current_cred_ids,
+            current_keys,
+            current_threshold: AccountThreshold::try_from(1).unwrap(),
+            key_updates: BTreeMap::new(),
+            new_credentials: BTreeMap::new(),
+            removed_credentials: Vec::new(),
+            new_threshold: None,
+        }
+    }
+
+    fn threshold(t: u8) -> AccountThreshold { AccountThreshold::try_from(t).unwrap() }
+
+    #[test]
+    fn test_validate() {
+        let mut p = plan(2);
+        assert!(matches!(p.validate(), Err(KeyRotationError::EmptyPlan)));
+        // Setting the current threshold is not a change.
+        p.set_threshold(threshold(1));
+        assert!(matches!(p.validate(), Err(KeyRotationError::EmptyPlan)));
+        p.set_threshold(threshold(2));
+        assert!(p.validate().is_ok());
+        p.set_threshold(threshold(3));
+        assert!(matches!(p.validate(), Err(KeyRotationError::ThresholdTooLarge {
+            num_credentials: 2,
+            ..
+        })));
+        // Removing a credential lowers the number of credentials.
+        let mut p = plan(2);
+        p.set_threshold(threshold(2));
+        p.remove_credential(CredentialIndex { index: 1 }).unwrap();
+        assert!(matches!(p.validate(), Err(KeyRotationError::ThresholdTooLarge {
+            num_credentials: 1,
+            ..
+        })));
+        p.set_threshold(threshold(1));
+        assert!(p.validate().is_ok());
+    }
+
+    #[test]
+    fn test_plan_changes() {
+        let mut csprng = rand::thread_rng();
+        let mut p = plan(2);
+        assert!(matches!(
+            p.remove_credential(CredentialIndex { index: 0 }),
+            Err(KeyRotationError::CannotRemoveInitialCredential)
+        ));
+        assert!(matches!(
+            p.replace_keys(CredentialIndex { index: 2 }, keys(&mut csprng)),
+            Err(KeyRotationError::UnknownCredential(_))
+        ));
+        p.remove_credential(CredentialIndex { index: 1 }).unwrap();
+        assert!(matches!(
+            p.replace_keys(CredentialIndex { index: 1 }, keys(&mut csprng)),
+            Err(KeyRotationError::UnknownCredential(_))
+        ));
+        let mut bad_keys = keys(&mut csprng);
+        bad_keys.threshold = SignatureThreshold::try_from(2).unwrap();
+        assert!(matches!(
+            p.replace_keys(CredentialIndex { index: 0 }, bad_keys),
+            Err(KeyRotationError::InvalidCredentialKeys(_))
+        ));
+    }
+
+    #[test]
+    fn test_transactions() {
+        let mut csprng = rand::thread_rng();
+        let signer = crate::id::types::AccountKeys::singleton(&mut csprng);
+        let expiry = TransactionTime::from_seconds(0);
+        let nonce = Nonce { nonce: 5 };
+
+        // Only key updates do not need an `UpdateCredentials` transaction.
+        let mut p = plan(2);
+        p.replace_keys(CredentialIndex { index: 0 }, keys(&mut csprng))
+            .unwrap()
+            .replace_keys(CredentialIndex { index: 1 }, keys(&mut csprng))
+            .unwrap();
+        let txs = p.transactions(&signer, nonce, expiry).unwrap();
+        assert_eq!(txs.len(), 2);
+
+        // Removing a credential and updating keys uses consecutive nonces, with
+        // the credential update first.
+        let mut p = plan(2);
+        p.remove_credential(CredentialIndex { index: 1 })
+            .unwrap()
+            .replace_keys(CredentialIndex { index: 0 }, keys(&mut csprng))
+            .unwrap();
+        let txs = p.transactions(&signer, nonce, expiry).unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].header.nonce, nonce);
+        assert_eq!(txs[1].header.nonce, Nonce { nonce: 6 });
+        assert_eq!(p.expected_keys().len(), 1);
+
+        // A no-op plan yields an error rather than no transactions.
+        let mut p = plan(1);
+        p.set_threshold(threshold(1));
+        assert!(matches!(
+            p.transactions(&signer, nonce, expiry),
+            Err(KeyRotationError::EmptyPlan)
+        ));
+    }
+}
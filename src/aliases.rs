@@ -0,0 +1,143 @@
+//! Utilities for working with account aliases.
+//!
+//! Every account has `2^24` addresses that all refer to it. They share the
+//! first 29 bytes, and differ in the last 3 bytes which are the alias counter.
+//! Aliases are commonly used to give each user of a service, e.g., an
+//! exchange, a separate deposit address that still refers to the same
+//! account.
+//!
+//! The address with which the account was created is its canonical address.
+//! The [`AliasMap`] maps aliases of known accounts back to their canonical
+//! address without querying the node, and [`canonical_address`] looks it up
+//! on the chain.
+use crate::{
+    endpoints::QueryResult,
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::contracts_common::AccountAddress;
+use std::collections::BTreeMap;
+
+/// The number of bytes that all aliases of an account have in common.
+pub const ALIAS_PREFIX_LENGTH: usize = 29;
+
+/// The largest alias counter.
+pub const MAX_ALIAS_COUNTER: u32 = (1 << 24) - 1;
+
+/// The common prefix of all aliases of the account.
+pub fn alias_prefix(address: &AccountAddress) -> [u8; ALIAS_PREFIX_LENGTH] {
+    let mut prefix = [0u8; ALIAS_PREFIX_LENGTH];
+    prefix.copy_from_slice(&address.0[..ALIAS_PREFIX_LENGTH]);
+    prefix
+}
+
+/// The alias counter of the address, i.e., its last 3 bytes interpreted as a
+/// big endian integer.
+pub fn alias_counter(address: &AccountAddress) -> u32 {
+    let [a, b, c] = [address.0[29], address.0[30], address.0[31]];
+    u32::from_be_bytes([0, a, b, c])
+}
+
+/// Get the alias of the account with the given counter. Returns [`None`] if
+/// the counter exceeds [`MAX_ALIAS_COUNTER`].
+pub fn alias(address: &AccountAddress, counter: u32) -> Option<AccountAddress> {
+    if counter > MAX_ALIAS_COUNTER {
+        return None;
+    }
+    let mut bytes = address.0;
+    bytes[ALIAS_PREFIX_LENGTH..].copy_from_slice(&counter.to_be_bytes()[1..]);
+    Some(AccountAddress(bytes))
+}
+
+/// Iterate over the aliases of the account with counters in the given range,
+/// e.g., to generate deposit addresses. Counters that exceed
+/// [`MAX_ALIAS_COUNTER`] are skipped.
+pub fn aliases(
+    address: &AccountAddress,
+    counters: impl IntoIterator<Item = u32>,
+) -> impl Iterator<Item = (u32, AccountAddress)> + '_ {
+    counters
+        .into_iter()
+        .filter_map(move |counter| Some((counter, alias(address, counter)?)))
+}
+
+/// The smallest and the largest alias of the account, in the lexicographic
+/// ordering of addresses. All aliases of the account lie between these two.
+pub fn alias_range(address: &AccountAddress) -> (AccountAddress, AccountAddress) {
+    let mut lower = address.0;
+    let mut upper = address.0;
+    lower[ALIAS_PREFIX_LENGTH..].fill(0);
+    upper[ALIAS_PREFIX_LENGTH..].fill(0xff);
+    (AccountAddress(lower), AccountAddress(upper))
+}
+
+/// A map from aliases of known accounts to the canonical addresses of the
+/// accounts.
+#[derive(Debug, Clone, Default)]
+pub struct AliasMap {
+    accounts: BTreeMap<[u8; ALIAS_PREFIX_LENGTH], AccountAddress>,
+}
+
+impl AliasMap {
+    /// Construct an empty map.
+    pub fn new() -> Self { Self::default() }
+
+    /// Add the account with the given canonical address. If an alias of the
+    /// account was already present it is replaced, and the previous canonical
+    /// address is returned.
+    pub fn insert(&mut self, canonical: AccountAddress) -> Option<AccountAddress> {
+        self.accounts.insert(alias_prefix(&canonical), canonical)
+    }
+
+    /// Get the canonical address of the account that the given address is an
+    /// alias of, if the account is known.
+    pub fn canonical(&self, address: &AccountAddress) -> Option<AccountAddress> {
+        self.accounts.get(&alias_prefix(address)).copied()
+    }
+
+    /// Return whether the given address is an alias of any known account.
+    pub fn contains(&self, address: &AccountAddress) -> bool {
+        self.accounts.contains_key(&alias_prefix(address))
+    }
+
+    /// Iterate over the canonical addresses of all known accounts.
+    pub fn iter(&self) -> impl Iterator<Item = &AccountAddress> { self.accounts.values() }
+}
+
+impl FromIterator<AccountAddress> for AliasMap {
+    fn from_iter<T: IntoIterator<Item = AccountAddress>>(iter: T) -> Self {
+        let mut map = Self::new();
+        for address in iter {
+            map.insert(address);
+        }
+        map
+    }
+}
+
+/// Look up the canonical address of the account that the given address is an
+/// alias of, as of the given block.
+pub async fn canonical_address(
+    client: &mut v2::Client,
+    address: AccountAddress,
+    bi: impl IntoBlockIdentifier,
+) -> QueryResult<AccountAddress> {
+    let info = client.get_account_info(&address.into(), bi).await?;
+    Ok(info.response.account_address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias_roundtrip() {
+        let address = AccountAddress([7u8; 32]);
+        let a = alias(&address, 0x01_02_03).expect("Counter is in range.");
+        assert!(a.is_alias(&address));
+        assert_eq!(alias_counter(&a), 0x01_02_03);
+        assert_eq!(alias(&address, MAX_ALIAS_COUNTER + 1), None);
+        let (lower, upper) = alias_range(&address);
+        assert!(lower <= a && a <= upper);
+        let map: AliasMap = std::iter::once(address).collect();
+        assert_eq!(map.canonical(&a), Some(address));
+    }
+}
@@ -1,8 +1,15 @@
 //! This module contains a generic client that provides conveniences for
 //! interacting with any smart contract instance.
+//!
+//! If the [`ContractClient`] has a schema for the contract, either embedded in
+//! the module or loaded from a file, the [`view_json`](ContractClient::view_json)
+//! and [`update_json`](ContractClient::update_json) functions can be used to
+//! interact with the contract using JSON parameters and return values.
 use crate::{
+    smart_contracts::{engine, events},
     types::{
-        smart_contracts::{self, ContractContext, InvokeContractResult},
+        hashes::BlockHash,
+        smart_contracts::{ContractContext, InvokeContractResult, WasmModule, WasmVersion},
         transactions, RejectReason,
     },
     v2::{self, BlockIdentifier, Client},
@@ -11,14 +18,19 @@ use concordium_base::{
     base::Nonce,
     common::types,
     contracts_common::{
-        self, AccountAddress, Address, Amount, ContractAddress, NewReceiveNameError,
+        self,
+        schema::{self, VersionedModuleSchema},
+        AccountAddress, Address, Amount, ContractAddress, NewReceiveNameError,
     },
     hashes::TransactionHash,
-    smart_contracts::{ExceedsParameterSize, OwnedContractName, OwnedParameter, OwnedReceiveName},
+    smart_contracts::{
+        ContractEvent, ExceedsParameterSize, OwnedContractName, OwnedParameter, OwnedReceiveName,
+    },
     transactions::UpdateContractPayload,
 };
 pub use concordium_base::{cis2_types::MetadataUrl, cis4_types::*};
-use std::{marker::PhantomData, sync::Arc};
+use futures::StreamExt;
+use std::{marker::PhantomData, path::Path, sync::Arc};
 
 /// A contract client that handles some of the boilerplate such as serialization
 /// and parsing of responses when sending transactions, or invoking smart
@@ -34,6 +46,8 @@ pub struct ContractClient<Type> {
     pub address:       ContractAddress,
     /// The name of the contract at the address.
     pub contract_name: Arc<contracts_common::OwnedContractName>,
+    /// The schema of the module the contract is in, if available.
+    schema:            Option<Arc<VersionedModuleSchema>>,
     phantom:           PhantomData<Type>,
 }
 
@@ -43,6 +57,7 @@ impl<Type> Clone for ContractClient<Type> {
             client:        self.client.clone(),
             address:       self.address,
             contract_name: self.contract_name.clone(),
+            schema:        self.schema.clone(),
             phantom:       PhantomData,
         }
     }
@@ -83,6 +98,131 @@ impl From<RejectReason> for ViewError {
     fn from(value: RejectReason) -> Self { Self::QueryFailed(value) }
 }
 
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when interacting with a contract through its
+/// schema, such as in [`view_json`](ContractClient::view_json) and
+/// [`update_json`](ContractClient::update_json).
+pub enum SchemaError {
+    #[error("No schema is available for the contract.")]
+    NoSchema,
+    #[error("The module does not contain an embedded schema: {0}")]
+    NoEmbeddedSchema(String),
+    #[error("The schema does not describe the requested value: {0:?}")]
+    Lookup(schema::VersionedSchemaError),
+    #[error("Unable to encode the parameter: {0:?}")]
+    Json(schema::JsonError),
+    #[error("Unable to decode the value: {0}")]
+    Parse(#[from] contracts_common::ParseError),
+    #[error("Node rejected with reason {reason:#?} and error {error:?}")]
+    Rejected {
+        reason: RejectReason,
+        /// The error returned by the contract, decoded using the error schema
+        /// of the entrypoint if it has one.
+        error:  Option<serde_json::Value>,
+    },
+    #[error("Invalid receive name: {0}")]
+    InvalidName(#[from] NewReceiveNameError),
+    #[error("Parameter is too large: {0}")]
+    ParameterError(#[from] ExceedsParameterSize),
+    #[error("Network error: {0}")]
+    NetworkError(#[from] v2::QueryError),
+    #[error("Unable to send the transaction: {0}")]
+    RPCError(#[from] v2::RPCError),
+    #[error("Unable to read the schema file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<schema::VersionedSchemaError> for SchemaError {
+    fn from(value: schema::VersionedSchemaError) -> Self { Self::Lookup(value) }
+}
+
+impl From<schema::JsonError> for SchemaError {
+    fn from(value: schema::JsonError) -> Self { Self::Json(value) }
+}
+
+impl From<RejectReason> for SchemaError {
+    fn from(reason: RejectReason) -> Self {
+        Self::Rejected {
+            reason,
+            error: None,
+        }
+    }
+}
+
+/// Extract the schema embedded in the custom section of a smart contract
+/// module.
+pub fn embedded_schema(module: &WasmModule) -> Result<VersionedModuleSchema, SchemaError> {
+    let source = module.source.as_ref();
+    match module.version {
+        WasmVersion::V0 => engine::utils::get_embedded_schema_v0(source)
+            .map(VersionedModuleSchema::V0)
+            .map_err(|e| SchemaError::NoEmbeddedSchema(e.to_string())),
+        WasmVersion::V1 => engine::utils::get_embedded_schema_v1(source)
+            .map_err(|e| SchemaError::NoEmbeddedSchema(e.to_string())),
+    }
+}
+
+/// Parse the return value of a successful invocation, such as one of the
+/// results of a [`batch_invoke`].
+pub fn parse_view_result<A: contracts_common::Deserial, E>(
+    result: InvokeContractResult,
+) -> Result<A, E>
+where
+    E: From<RejectReason> + From<contracts_common::ParseError>, {
+    match result {
+        InvokeContractResult::Success { return_value, .. } => {
+            let Some(bytes) = return_value else {
+                return Err(contracts_common::ParseError {}.into());
+            };
+            let response: A = contracts_common::from_bytes(&bytes.value)?;
+            Ok(response)
+        }
+        InvokeContractResult::Failure { reason, .. } => Err(reason.into()),
+    }
+}
+
+#[derive(Debug)]
+/// The results of a [`batch_invoke`].
+pub struct BatchInvokeResult {
+    /// The block in which all invocations were evaluated.
+    pub block_hash: BlockHash,
+    /// The result of each invocation, in the order of the contexts.
+    pub results:    Vec<v2::QueryResult<InvokeContractResult>>,
+}
+
+/// Invoke a list of contract entrypoints, possibly of different contracts,
+/// in the same block. The block identifier is resolved to a block hash once,
+/// and at most `concurrency` invocations are in flight at any time.
+///
+/// Contexts for view functions of a [`ContractClient`] can be constructed
+/// with [`view_context`](ContractClient::view_context), and the results
+/// parsed with [`parse_view_result`].
+pub async fn batch_invoke(
+    client: &Client,
+    bi: impl v2::IntoBlockIdentifier,
+    contexts: impl IntoIterator<Item = ContractContext>,
+    concurrency: usize,
+) -> v2::QueryResult<BatchInvokeResult> {
+    let block_hash = client.clone().get_block_info(bi).await?.block_hash;
+    let results = futures::stream::iter(contexts)
+        .map(|context| {
+            let mut client = client.clone();
+            async move {
+                client
+                    .invoke_instance(block_hash, &context)
+                    .await
+                    .map(|result| result.response)
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await;
+    Ok(BatchInvokeResult {
+        block_hash,
+        results,
+    })
+}
+
 impl<Type> ContractClient<Type> {
     /// Construct a [`ContractClient`] by looking up metadata from the chain.
     ///
@@ -114,10 +254,164 @@ impl<Type> ContractClient<Type> {
             client,
             address,
             contract_name: Arc::new(contract_name),
+            schema: None,
             phantom: PhantomData,
         }
     }
 
+    /// Use the given schema for the JSON functions of the client.
+    pub fn with_schema(mut self, schema: VersionedModuleSchema) -> Self {
+        self.schema = Some(Arc::new(schema));
+        self
+    }
+
+    /// The schema used by the JSON functions of the client, if any.
+    pub fn schema(&self) -> Option<&VersionedModuleSchema> { self.schema.as_deref() }
+
+    /// Load the schema embedded in the module of the contract instance, as
+    /// it is deployed in the given block.
+    pub async fn load_embedded_schema(
+        &mut self,
+        bi: impl v2::IntoBlockIdentifier,
+    ) -> Result<&VersionedModuleSchema, SchemaError> {
+        let info = self.client.get_instance_info(self.address, bi).await?;
+        let module = self
+            .client
+            .get_module_source(&info.response.source_module(), info.block_hash)
+            .await?
+            .response;
+        let schema = Arc::new(embedded_schema(&module)?);
+        Ok(self.schema.insert(schema))
+    }
+
+    /// Load the schema from a file, such as the one produced by
+    /// `cargo concordium build --schema-out`.
+    pub fn load_schema_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<&VersionedModuleSchema, SchemaError> {
+        let bytes = std::fs::read(path)?;
+        let schema: VersionedModuleSchema = contracts_common::from_bytes(&bytes)?;
+        Ok(self.schema.insert(Arc::new(schema)))
+    }
+
+    fn require_schema(&self) -> Result<Arc<VersionedModuleSchema>, SchemaError> {
+        self.schema.clone().ok_or(SchemaError::NoSchema)
+    }
+
+    /// Encode a JSON parameter for the given entrypoint using the parameter
+    /// schema of the entrypoint.
+    pub fn encode_parameter(
+        &self,
+        entrypoint: &str,
+        parameter: &serde_json::Value,
+    ) -> Result<OwnedParameter, SchemaError> {
+        let schema = self.require_schema()?;
+        let contract = self.contract_name.as_contract_name().contract_name();
+        let param_schema = schema.get_receive_param_schema(contract, entrypoint)?;
+        let bytes = param_schema.serial_value(parameter)?;
+        Ok(OwnedParameter::try_from(bytes)?)
+    }
+
+    /// Decode the return value of the given entrypoint using its return value
+    /// schema.
+    pub fn decode_return_value(
+        &self,
+        entrypoint: &str,
+        return_value: &[u8],
+    ) -> Result<serde_json::Value, SchemaError> {
+        let schema = self.require_schema()?;
+        let contract = self.contract_name.as_contract_name().contract_name();
+        let rv_schema = schema.get_receive_return_value_schema(contract, entrypoint)?;
+        Ok(rv_schema.to_json(&mut contracts_common::Cursor::new(return_value))?)
+    }
+
+    /// Decode the error returned when the given entrypoint rejects, using
+    /// its error schema.
+    pub fn decode_error(
+        &self,
+        entrypoint: &str,
+        return_value: &[u8],
+    ) -> Result<serde_json::Value, SchemaError> {
+        let schema = self.require_schema()?;
+        let contract = self.contract_name.as_contract_name().contract_name();
+        let error_schema = schema.get_receive_error_schema(contract, entrypoint)?;
+        Ok(error_schema.to_json(&mut contracts_common::Cursor::new(return_value))?)
+    }
+
+    /// Decode events logged by the contract using its event schema.
+    pub fn decode_events(
+        &self,
+        events: &[ContractEvent],
+    ) -> Result<Vec<serde_json::Value>, SchemaError> {
+        let schema = self.require_schema()?;
+        let contract = self.contract_name.as_contract_name().contract_name();
+        events
+            .iter()
+            .map(|event| events::decode_event(&schema, contract, event))
+            .collect()
+    }
+
+    /// Invoke the entrypoint with a JSON parameter and decode the return value
+    /// as JSON. Both use the schema of the client, which must have been set
+    /// using [`with_schema`](Self::with_schema) or one of the `load_*`
+    /// functions.
+    ///
+    /// If the contract rejects, the error it returned is decoded using the
+    /// error schema of the entrypoint, if it has one, and returned in
+    /// [`SchemaError::Rejected`].
+    pub async fn view_json(
+        &mut self,
+        entrypoint: &str,
+        parameter: &serde_json::Value,
+        bi: impl v2::IntoBlockIdentifier,
+    ) -> Result<serde_json::Value, SchemaError> {
+        let parameter = self.encode_parameter(entrypoint, parameter)?;
+        let ir = self
+            .invoke_raw::<SchemaError>(entrypoint, Amount::zero(), None, parameter, bi)
+            .await?;
+        self.parse_view_result_json(entrypoint, ir)
+    }
+
+    /// Decode the return value of a successful invocation of the entrypoint
+    /// as JSON, like [`view_json`](Self::view_json) does.
+    pub fn parse_view_result_json(
+        &self,
+        entrypoint: &str,
+        result: InvokeContractResult,
+    ) -> Result<serde_json::Value, SchemaError> {
+        match result {
+            InvokeContractResult::Success { return_value, .. } => {
+                let Some(bytes) = return_value else {
+                    return Err(contracts_common::ParseError {}.into());
+                };
+                self.decode_return_value(entrypoint, &bytes.value)
+            }
+            InvokeContractResult::Failure {
+                return_value,
+                reason,
+                ..
+            } => Err(SchemaError::Rejected {
+                reason,
+                error: return_value.and_then(|rv| self.decode_error(entrypoint, &rv.value).ok()),
+            }),
+        }
+    }
+
+    /// Send a transaction to the entrypoint with a JSON parameter that is
+    /// encoded using the schema of the client.
+    pub async fn update_json(
+        &mut self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        metadata: &ContractTransactionMetadata,
+        entrypoint: &str,
+        parameter: &serde_json::Value,
+    ) -> Result<TransactionHash, SchemaError> {
+        let message = self.encode_parameter(entrypoint, parameter)?;
+        self.update_raw::<SchemaError>(signer, metadata, entrypoint, message)
+            .await
+    }
+
     /// Invoke a contract and return the response.
     ///
     /// This will always fail for a V0 contract, and for V1 contracts it will
@@ -160,40 +454,56 @@ impl<Type> ContractClient<Type> {
         let ir = self
             .invoke_raw::<E>(entrypoint, Amount::zero(), None, parameter, bi)
             .await?;
-        match ir {
-            smart_contracts::InvokeContractResult::Success { return_value, .. } => {
-                let Some(bytes) = return_value else {return Err(contracts_common::ParseError {}.into()
-            )};
-                let response: A = contracts_common::from_bytes(&bytes.value)?;
-                Ok(response)
-            }
-            smart_contracts::InvokeContractResult::Failure { reason, .. } => Err(reason.into()),
-        }
+        parse_view_result(ir)
     }
 
-    /// Invoke a contract instance and return the response without any
-    /// processing.
-    pub async fn invoke_raw<E>(
-        &mut self,
+    /// Construct the context for invoking the entrypoint with the given
+    /// parameter, without an amount or an invoker. The context can be used in
+    /// a [`batch_invoke`] together with invocations of other contracts.
+    pub fn view_context<P: contracts_common::Serial, E>(
+        &self,
+        entrypoint: &str,
+        parameter: &P,
+    ) -> Result<ContractContext, E>
+    where
+        E: From<NewReceiveNameError> + From<ExceedsParameterSize>, {
+        let parameter = OwnedParameter::from_serial(parameter)?;
+        Ok(self.context(entrypoint, Amount::zero(), None, parameter)?)
+    }
+
+    fn context(
+        &self,
         entrypoint: &str,
         amount: Amount,
         invoker: Option<Address>,
         parameter: OwnedParameter,
-        bi: impl v2::IntoBlockIdentifier,
-    ) -> Result<InvokeContractResult, E>
-    where
-        E: From<NewReceiveNameError> + From<RejectReason> + From<v2::QueryError>, {
+    ) -> Result<ContractContext, NewReceiveNameError> {
         let contract_name = self.contract_name.as_contract_name().contract_name();
         let method = OwnedReceiveName::try_from(format!("{contract_name}.{entrypoint}"))?;
 
-        let context = ContractContext {
+        Ok(ContractContext {
             invoker,
             contract: self.address,
             amount,
             method,
             parameter,
             energy: 1_000_000.into(),
-        };
+        })
+    }
+
+    /// Invoke a contract instance and return the response without any
+    /// processing.
+    pub async fn invoke_raw<E>(
+        &mut self,
+        entrypoint: &str,
+        amount: Amount,
+        invoker: Option<Address>,
+        parameter: OwnedParameter,
+        bi: impl v2::IntoBlockIdentifier,
+    ) -> Result<InvokeContractResult, E>
+    where
+        E: From<NewReceiveNameError> + From<RejectReason> + From<v2::QueryError>, {
+        let context = self.context(entrypoint, amount, invoker, parameter)?;
 
         let invoke_result = self.client.invoke_instance(bi, &context).await?.response;
         Ok(invoke_result)
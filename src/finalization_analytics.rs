@@ -0,0 +1,332 @@
+//! Analytics of finalization over a range of blocks.
+//!
+//! Blocks produced before protocol version 6 may contain a finalization
+//! record, which is summarized by the node as a [`FinalizationSummary`]. The
+//! [`analyze_finalization`] function collects these summaries over a range of
+//! heights and aggregates them into [`FinalizationAnalytics`], consisting of
+//!
+//! - a [`FinalizationRecord`] for each record, including the time it took to
+//!   finalize the block,
+//! - a [`FinalizerParticipation`] for each finalizer, counting the records it
+//!   signed, and
+//! - a [`CommitteeChange`] whenever a finalizer joins or leaves the committee,
+//!   or its weight changes.
+//!
+//! All of these are flat structures that can be written in CSV format using
+//! `write_csv` if the `csv` feature is enabled.
+use crate::{
+    endpoints::QueryResult,
+    types::{
+        hashes::BlockHash, AbsoluteBlockHeight, BakerId, BlockHeight, FinalizationIndex,
+        FinalizationSummary,
+    },
+    v2,
+};
+use concordium_base::common::SerdeSerialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+/// A finalization record included in a block.
+pub struct FinalizationRecord {
+    /// The block that contains the record.
+    pub block_hash:              BlockHash,
+    pub block_height:            AbsoluteBlockHeight,
+    pub block_time:              chrono::DateTime<chrono::Utc>,
+    /// The block that was finalized by the record.
+    pub finalized_block:         BlockHash,
+    pub finalized_height:        AbsoluteBlockHeight,
+    pub finalized_time:          chrono::DateTime<chrono::Utc>,
+    pub index:                   FinalizationIndex,
+    /// The finalization delay used by the committee.
+    pub delay:                   BlockHeight,
+    /// The time between the slot time of the finalized block and the slot
+    /// time of the block containing the record, in milliseconds. This is an
+    /// upper bound on the time it took to finalize the block.
+    pub time_to_finalization_ms: i64,
+    /// The number of finalizers in the committee.
+    pub committee_size:          usize,
+    /// The number of finalizers that signed the record.
+    pub signers:                 usize,
+    /// The total weight of the committee.
+    pub total_weight:            u64,
+    /// The weight of the finalizers that signed the record.
+    pub signed_weight:           u64,
+}
+
+impl FinalizationRecord {
+    /// The time it took to finalize the block. See
+    /// [`time_to_finalization_ms`](Self::time_to_finalization_ms).
+    pub fn time_to_finalization(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds(self.time_to_finalization_ms)
+    }
+}
+
+#[derive(Debug, Clone, Copy, SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+/// The participation of a single finalizer.
+pub struct FinalizerParticipation {
+    pub baker_id: BakerId,
+    /// The number of records in which the baker was a finalizer.
+    pub records:  u64,
+    /// The number of those records that the baker signed.
+    pub signed:   u64,
+    /// The weight of the baker in the most recent record it was part of.
+    pub weight:   u64,
+}
+
+impl FinalizerParticipation {
+    /// The fraction of records the finalizer signed.
+    pub fn rate(&self) -> f64 {
+        if self.records == 0 {
+            0.0
+        } else {
+            self.signed as f64 / self.records as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+/// A change to the weight of a single finalizer in the committee. A finalizer
+/// that joins the committee has no old weight, and a finalizer that leaves
+/// has no new weight.
+pub struct CommitteeChange {
+    /// The height of the block containing the first record with the new
+    /// committee.
+    pub block_height: AbsoluteBlockHeight,
+    pub index:        FinalizationIndex,
+    pub baker_id:     BakerId,
+    pub old_weight:   Option<u64>,
+    pub new_weight:   Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Finalization analytics aggregated over a range of blocks.
+pub struct FinalizationAnalytics {
+    /// The finalization records in increasing order of height.
+    pub records:           Vec<FinalizationRecord>,
+    /// Changes to the committee in increasing order of height. The committee
+    /// of the first record is not reported as a change.
+    pub committee_changes: Vec<CommitteeChange>,
+    participation:         BTreeMap<BakerId, FinalizerParticipation>,
+    committee:             Option<BTreeMap<BakerId, u64>>,
+}
+
+impl FinalizationAnalytics {
+    /// Add a finalization summary to the analytics. The summary is contained
+    /// in the block at `block_height` with hash `block_hash` and slot time
+    /// `block_time`, and it finalizes the block at `finalized_height` with
+    /// slot time `finalized_time`. Summaries must be added in increasing
+    /// order of height.
+    pub fn add(
+        &mut self,
+        block_hash: BlockHash,
+        block_height: AbsoluteBlockHeight,
+        block_time: chrono::DateTime<chrono::Utc>,
+        finalized_height: AbsoluteBlockHeight,
+        finalized_time: chrono::DateTime<chrono::Utc>,
+        summary: &FinalizationSummary,
+    ) {
+        let committee = summary
+            .finalizers
+            .iter()
+            .map(|party| (party.baker_id, party.weight))
+            .collect::<BTreeMap<_, _>>();
+        if let Some(previous) = &self.committee {
+            let bakers = previous.keys().chain(committee.keys()).collect::<BTreeSet<_>>();
+            for baker_id in bakers {
+                let old_weight = previous.get(baker_id).copied();
+                let new_weight = committee.get(baker_id).copied();
+                if old_weight != new_weight {
+                    self.committee_changes.push(CommitteeChange {
+                        block_height,
+                        index: summary.index,
+                        baker_id: *baker_id,
+                        old_weight,
+                        new_weight,
+                    });
+                }
+            }
+        }
+        for party in &summary.finalizers {
+            let participation = self
+                .participation
+                .entry(party.baker_id)
+                .or_insert(FinalizerParticipation {
+                    baker_id: party.baker_id,
+                    records:  0,
+                    signed:   0,
+                    weight:   0,
+                });
+            participation.records += 1;
+            participation.weight = party.weight;
+            if party.signed {
+                participation.signed += 1;
+            }
+        }
+        let signers = summary.finalizers.iter().filter(|party| party.signed);
+        self.records.push(FinalizationRecord {
+            block_hash,
+            block_height,
+            block_time,
+            finalized_block: summary.block_pointer,
+            finalized_height,
+            finalized_time,
+            index: summary.index,
+            delay: summary.delay,
+            time_to_finalization_ms: (block_time - finalized_time).num_milliseconds(),
+            committee_size: summary.finalizers.len(),
+            signers: signers.clone().count(),
+            total_weight: committee.values().sum(),
+            signed_weight: signers.map(|party| party.weight).sum(),
+        });
+        self.committee = Some(committee);
+    }
+
+    /// The participation of each finalizer that was part of the committee in
+    /// any of the records, ordered by baker id.
+    pub fn participation(&self) -> Vec<FinalizerParticipation> {
+        self.participation.values().copied().collect()
+    }
+
+    /// The average time to finalization over all the records, if there are
+    /// any.
+    pub fn average_time_to_finalization(&self) -> Option<chrono::Duration> {
+        let count = self.records.len() as i64;
+        let total: i64 = self.records.iter().map(|r| r.time_to_finalization_ms).sum();
+        (count > 0).then(|| chrono::Duration::milliseconds(total / count))
+    }
+
+    /// The longest time to finalization over all the records, if there are
+    /// any.
+    pub fn max_time_to_finalization(&self) -> Option<chrono::Duration> {
+        self.records
+            .iter()
+            .map(FinalizationRecord::time_to_finalization)
+            .max()
+    }
+}
+
+/// Collect the finalization records of all blocks in the given range of
+/// heights (inclusive) and aggregate them. Blocks from protocol version 6
+/// onwards do not contain finalization records and do not contribute to the
+/// analytics.
+pub async fn analyze_finalization(
+    client: &mut v2::Client,
+    start: AbsoluteBlockHeight,
+    end: AbsoluteBlockHeight,
+) -> QueryResult<FinalizationAnalytics> {
+    let mut analytics = FinalizationAnalytics::default();
+    for height in start.height..=end.height {
+        let height = AbsoluteBlockHeight::from(height);
+        let summary = client.get_block_finalization_summary(height).await?;
+        let Some(record) = summary.response else {
+            continue;
+        };
+        let block_time = client.get_block_info(height).await?.response.block_slot_time;
+        let finalized = client.get_block_info(record.block_pointer).await?.response;
+        analytics.add(
+            summary.block_hash,
+            height,
+            block_time,
+            finalized.block_height,
+            finalized.block_slot_time,
+            &record,
+        );
+    }
+    Ok(analytics)
+}
+
+/// Write the rows in CSV format, with a header. This can be used with
+/// [`FinalizationRecord`], [`FinalizerParticipation`] and [`CommitteeChange`].
+#[cfg(feature = "csv")]
+pub fn write_csv<T: SerdeSerialize, W: std::io::Write>(
+    rows: &[T],
+    writer: W,
+) -> Result<(), csv::Error> {
+    crate::internal::write_csv(rows, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{hashes::HashBytes, FinalizationSummaryParty};
+
+    fn baker(id: u64) -> BakerId { BakerId { id: id.into() } }
+
+    fn summary(index: u64, finalizers: &[(u64, u64, bool)]) -> FinalizationSummary {
+        FinalizationSummary {
+            block_pointer: HashBytes::new([index as u8; 32]),
+            index:         FinalizationIndex { index },
+            delay:         BlockHeight { height: 0 },
+            finalizers:    finalizers
+                .iter()
+                .map(|&(id, weight, signed)| FinalizationSummaryParty {
+                    baker_id: baker(id),
+                    weight,
+                    signed,
+                })
+                .collect(),
+        }
+    }
+
+    fn add(analytics: &mut FinalizationAnalytics, height: u64, summary: &FinalizationSummary) {
+        let time = chrono::DateTime::<chrono::Utc>::from(std::time::UNIX_EPOCH);
+        analytics.add(
+            HashBytes::new([0u8; 32]),
+            AbsoluteBlockHeight { height },
+            time + chrono::Duration::seconds(height as i64 * 2),
+            AbsoluteBlockHeight { height: height - 1 },
+            time + chrono::Duration::seconds(height as i64 * 2 - 3),
+            summary,
+        );
+    }
+
+    #[test]
+    fn test_add() {
+        let mut analytics = FinalizationAnalytics::default();
+        add(&mut analytics, 10, &summary(1, &[(1, 10, true), (2, 20, false)]));
+        // Baker 2 changes weight, baker 1 leaves and baker 3 joins.
+        add(&mut analytics, 20, &summary(2, &[(2, 30, true), (3, 5, true)]));
+
+        assert_eq!(analytics.records.len(), 2);
+        let record = &analytics.records[0];
+        assert_eq!(record.committee_size, 2);
+        assert_eq!(record.signers, 1);
+        assert_eq!(record.total_weight, 30);
+        assert_eq!(record.signed_weight, 10);
+        assert_eq!(record.time_to_finalization(), chrono::Duration::seconds(3));
+        assert_eq!(analytics.records[1].signed_weight, 35);
+
+        let changes = analytics
+            .committee_changes
+            .iter()
+            .map(|c| (c.baker_id, c.old_weight, c.new_weight))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [
+            (baker(1), Some(10), None),
+            (baker(2), Some(20), Some(30)),
+            (baker(3), None, Some(5)),
+        ]);
+        assert!(analytics
+            .committee_changes
+            .iter()
+            .all(|c| c.block_height == AbsoluteBlockHeight { height: 20 }));
+
+        let participation = analytics
+            .participation()
+            .iter()
+            .map(|p| (p.baker_id, p.records, p.signed, p.weight))
+            .collect::<Vec<_>>();
+        assert_eq!(participation, [
+            (baker(1), 1, 1, 10),
+            (baker(2), 2, 1, 30),
+            (baker(3), 1, 1, 5),
+        ]);
+        assert_eq!(
+            analytics.average_time_to_finalization(),
+            Some(chrono::Duration::seconds(3))
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! History of chain parameter values.
+//!
+//! A [`ParameterTimeline`] records when the values of chain parameters
+//! change. It is constructed by [`parameter_history`], which starts from the
+//! chain parameters in effect and the updates pending at the start of a range
+//! of blocks, adds each update enqueued by an update instruction in the range,
+//! and finally adds the updates still pending at the end of the range. An
+//! update that is enqueued cancels the pending updates of the same parameter
+//! that would take effect at the same time or later, so these are removed from
+//! the timeline. The timeline can then be queried for the value of a parameter
+//! at any time with [`ParameterTimeline::value_at`].
+//!
+//! Updates of keys and additions of identity providers and anonymity revokers
+//! are not parameter values and are not included.
+use crate::{
+    endpoints::QueryResult,
+    types::{
+        hashes::{BlockHash, TransactionHash},
+        queries::{PendingUpdate, PendingUpdateEffect},
+        AbsoluteBlockHeight, BlockItemSummaryDetails, CooldownParameters, ElectionDifficulty,
+        Energy, ExchangeRate, FinalizationCommitteeParameters, GASRewards, GASRewardsV1,
+        MintDistributionV0, MintDistributionV1, PoolParameters, ProtocolUpdate, TimeParameters,
+        TimeoutParameters, TransactionFeeDistribution, UpdatePayload,
+    },
+    v2::{self, ChainParameters},
+};
+use chrono::TimeZone;
+use concordium_base::{
+    common::types::{Amount, TransactionTime},
+    contracts_common::{AccountAddress, Duration},
+};
+use futures::TryStreamExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// The chain parameters tracked by a [`ParameterTimeline`].
+pub enum ParameterKind {
+    Protocol,
+    ElectionDifficulty,
+    EuroPerEnergy,
+    MicroCcdPerEuro,
+    FoundationAccount,
+    MintDistribution,
+    TransactionFeeDistribution,
+    GasRewards,
+    BakerStakeThreshold,
+    PoolParameters,
+    CooldownParameters,
+    TimeParameters,
+    TimeoutParameters,
+    MinBlockTime,
+    BlockEnergyLimit,
+    FinalizationCommitteeParameters,
+}
+
+#[derive(Debug, Clone)]
+/// The value of a chain parameter.
+pub enum ParameterValue {
+    /// A protocol update. This is not a parameter as such, but is included so
+    /// that the timeline shows when the protocol changes.
+    Protocol(ProtocolUpdate),
+    ElectionDifficulty(ElectionDifficulty),
+    EuroPerEnergy(ExchangeRate),
+    MicroCcdPerEuro(ExchangeRate),
+    FoundationAccount(AccountAddress),
+    MintDistributionV0(MintDistributionV0),
+    MintDistributionV1(MintDistributionV1),
+    TransactionFeeDistribution(TransactionFeeDistribution),
+    GasRewards(GASRewards),
+    GasRewardsV1(GASRewardsV1),
+    BakerStakeThreshold(Amount),
+    PoolParameters(PoolParameters),
+    CooldownParameters(CooldownParameters),
+    TimeParameters(TimeParameters),
+    TimeoutParameters(TimeoutParameters),
+    MinBlockTime(Duration),
+    BlockEnergyLimit(Energy),
+    FinalizationCommitteeParameters(FinalizationCommitteeParameters),
+}
+
+impl ParameterValue {
+    /// The parameter the value is for.
+    pub fn kind(&self) -> ParameterKind {
+        match self {
+            ParameterValue::Protocol(_) => ParameterKind::Protocol,
+            ParameterValue::ElectionDifficulty(_) => ParameterKind::ElectionDifficulty,
+            ParameterValue::EuroPerEnergy(_) => ParameterKind::EuroPerEnergy,
+            ParameterValue::MicroCcdPerEuro(_) => ParameterKind::MicroCcdPerEuro,
+            ParameterValue::FoundationAccount(_) => ParameterKind::FoundationAccount,
+            ParameterValue::MintDistributionV0(_) => ParameterKind::MintDistribution,
+            ParameterValue::MintDistributionV1(_) => ParameterKind::MintDistribution,
+            ParameterValue::TransactionFeeDistribution(_) => {
+                ParameterKind::TransactionFeeDistribution
+            }
+            ParameterValue::GasRewards(_) => ParameterKind::GasRewards,
+            ParameterValue::GasRewardsV1(_) => ParameterKind::GasRewards,
+            ParameterValue::BakerStakeThreshold(_) => ParameterKind::BakerStakeThreshold,
+            ParameterValue::PoolParameters(_) => ParameterKind::PoolParameters,
+            ParameterValue::CooldownParameters(_) => ParameterKind::CooldownParameters,
+            ParameterValue::TimeParameters(_) => ParameterKind::TimeParameters,
+            ParameterValue::TimeoutParameters(_) => ParameterKind::TimeoutParameters,
+            ParameterValue::MinBlockTime(_) => ParameterKind::MinBlockTime,
+            ParameterValue::BlockEnergyLimit(_) => ParameterKind::BlockEnergyLimit,
+            ParameterValue::FinalizationCommitteeParameters(_) => {
+                ParameterKind::FinalizationCommitteeParameters
+            }
+        }
+    }
+
+    /// The value set by an update instruction, if the update sets a tracked
+    /// parameter.
+    pub fn from_payload(payload: &UpdatePayload) -> Option<Self> {
+        let value = match payload {
+            UpdatePayload::Protocol(p) => Self::Protocol(p.clone()),
+            UpdatePayload::ElectionDifficulty(d) => Self::ElectionDifficulty(*d),
+            UpdatePayload::EuroPerEnergy(r) => Self::EuroPerEnergy(*r),
+            UpdatePayload::MicroGTUPerEuro(r) => Self::MicroCcdPerEuro(*r),
+            UpdatePayload::FoundationAccount(a) => Self::FoundationAccount(*a),
+            UpdatePayload::MintDistribution(md) => Self::MintDistributionV0(md.clone()),
+            UpdatePayload::MintDistributionCPV1(md) => Self::MintDistributionV1(md.clone()),
+            UpdatePayload::TransactionFeeDistribution(d) => {
+                Self::TransactionFeeDistribution(d.clone())
+            }
+            UpdatePayload::GASRewards(r) => Self::GasRewards(r.clone()),
+            UpdatePayload::GASRewardsCPV2(r) => Self::GasRewardsV1(r.clone()),
+            UpdatePayload::BakerStakeThreshold(p) => {
+                Self::BakerStakeThreshold(p.minimum_threshold_for_baking)
+            }
+            UpdatePayload::PoolParametersCPV1(p) => Self::PoolParameters(p.clone()),
+            UpdatePayload::CooldownParametersCPV1(p) => Self::CooldownParameters(p.clone()),
+            UpdatePayload::TimeParametersCPV1(p) => Self::TimeParameters(p.clone()),
+            UpdatePayload::TimeoutParametersCPV2(p) => Self::TimeoutParameters(p.clone()),
+            UpdatePayload::MinBlockTimeCPV2(d) => Self::MinBlockTime(*d),
+            UpdatePayload::BlockEnergyLimitCPV2(e) => Self::BlockEnergyLimit(*e),
+            UpdatePayload::FinalizationCommitteeParametersCPV2(p) => {
+                Self::FinalizationCommitteeParameters(p.clone())
+            }
+            UpdatePayload::Root(_)
+            | UpdatePayload::Level1(_)
+            | UpdatePayload::AddAnonymityRevoker(_)
+            | UpdatePayload::AddIdentityProvider(_) => return None,
+        };
+        Some(value)
+    }
+
+    /// The value set by a pending update, if the update sets a tracked
+    /// parameter.
+    pub fn from_pending(effect: &PendingUpdateEffect) -> Option<Self> {
+        let value = match effect {
+            PendingUpdateEffect::Protocol(p) => Self::Protocol(p.clone()),
+            PendingUpdateEffect::ElectionDifficulty(d) => Self::ElectionDifficulty(*d),
+            PendingUpdateEffect::EuroPerEnergy(r) => Self::EuroPerEnergy(*r),
+            PendingUpdateEffect::MicroCcdPerEnergy(r) => Self::MicroCcdPerEuro(*r),
+            PendingUpdateEffect::FoundationAccount(a) => Self::FoundationAccount(*a),
+            PendingUpdateEffect::MintDistributionV0(md) => Self::MintDistributionV0(md.clone()),
+            PendingUpdateEffect::MintDistributionV1(md) => Self::MintDistributionV1(md.clone()),
+            PendingUpdateEffect::TransactionFeeDistribution(d) => {
+                Self::TransactionFeeDistribution(d.clone())
+            }
+            PendingUpdateEffect::GasRewards(r) => Self::GasRewards(r.clone()),
+            PendingUpdateEffect::GasRewardsV1(r) => Self::GasRewardsV1(r.clone()),
+            PendingUpdateEffect::PoolParametersV0(p) => {
+                Self::BakerStakeThreshold(p.minimum_threshold_for_baking)
+            }
+            PendingUpdateEffect::PoolParametersV1(p) => Self::PoolParameters(p.clone()),
+            PendingUpdateEffect::CooldownParameters(p) => Self::CooldownParameters(p.clone()),
+            PendingUpdateEffect::TimeParameters(p) => Self::TimeParameters(p.clone()),
+            PendingUpdateEffect::TimeoutParameters(p) => Self::TimeoutParameters(p.clone()),
+            PendingUpdateEffect::MinBlockTime(d) => Self::MinBlockTime(*d),
+            PendingUpdateEffect::BlockEnergyLimit(e) => Self::BlockEnergyLimit(*e),
+            PendingUpdateEffect::FinalizationCommitteeParameters(p) => {
+                Self::FinalizationCommitteeParameters(p.clone())
+            }
+            PendingUpdateEffect::RootKeys(_)
+            | PendingUpdateEffect::Level1Keys(_)
+            | PendingUpdateEffect::Level2KeysCPV0(_)
+            | PendingUpdateEffect::Level2KeysCPV1(_)
+            | PendingUpdateEffect::AddAnonymityRevoker(_)
+            | PendingUpdateEffect::AddIdentityProvider(_) => return None,
+        };
+        Some(value)
+    }
+
+    /// The values of all tracked parameters in the given chain parameters.
+    pub fn from_chain_parameters(params: &ChainParameters) -> Vec<Self> {
+        match params {
+            ChainParameters::V0(p) => vec![
+                Self::ElectionDifficulty(p.election_difficulty),
+                Self::EuroPerEnergy(p.euro_per_energy),
+                Self::MicroCcdPerEuro(p.micro_ccd_per_euro),
+                Self::FoundationAccount(p.foundation_account),
+                Self::MintDistributionV0(p.mint_distribution.clone()),
+                Self::TransactionFeeDistribution(p.transaction_fee_distribution.clone()),
+                Self::GasRewards(p.gas_rewards.clone()),
+                Self::BakerStakeThreshold(p.minimum_threshold_for_baking),
+            ],
+            ChainParameters::V1(p) => vec![
+                Self::ElectionDifficulty(p.election_difficulty),
+                Self::EuroPerEnergy(p.euro_per_energy),
+                Self::MicroCcdPerEuro(p.micro_ccd_per_euro),
+                Self::FoundationAccount(p.foundation_account),
+                Self::MintDistributionV1(p.mint_distribution.clone()),
+                Self::TransactionFeeDistribution(p.transaction_fee_distribution.clone()),
+                Self::GasRewards(p.gas_rewards.clone()),
+                Self::PoolParameters(p.pool_parameters.clone()),
+                Self::CooldownParameters(p.cooldown_parameters.clone()),
+                Self::TimeParameters(p.time_parameters.clone()),
+            ],
+            ChainParameters::V2(p) => vec![
+                Self::EuroPerEnergy(p.euro_per_energy),
+                Self::MicroCcdPerEuro(p.micro_ccd_per_euro),
+                Self::FoundationAccount(p.foundation_account),
+                Self::MintDistributionV1(p.mint_distribution.clone()),
+                Self::TransactionFeeDistribution(p.transaction_fee_distribution.clone()),
+                Self::GasRewardsV1(p.gas_rewards.clone()),
+                Self::PoolParameters(p.pool_parameters.clone()),
+                Self::CooldownParameters(p.cooldown_parameters.clone()),
+                Self::TimeParameters(p.time_parameters.clone()),
+                Self::TimeoutParameters(p.timeout_parameters.clone()),
+                Self::MinBlockTime(p.min_block_time),
+                Self::BlockEnergyLimit(p.block_energy_limit),
+                Self::FinalizationCommitteeParameters(
+                    p.finalization_committee_parameters.clone(),
+                ),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Where a change of a parameter was found.
+pub enum ChangeSource {
+    /// The value in effect at the start of the timeline.
+    Initial { block_hash: BlockHash },
+    /// The change was enqueued by an update instruction.
+    Enqueued {
+        block_hash:  BlockHash,
+        transaction: TransactionHash,
+    },
+    /// The change was pending at the start or the end of the timeline, and
+    /// was enqueued before its start.
+    Pending,
+}
+
+#[derive(Debug, Clone)]
+/// A change of the value of a parameter.
+pub struct ParameterChange {
+    /// The time at which the value takes effect.
+    pub effective_time: chrono::DateTime<chrono::Utc>,
+    pub value:          ParameterValue,
+    pub source:         ChangeSource,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Changes of chain parameters ordered by the time they take effect.
+pub struct ParameterTimeline {
+    changes: Vec<ParameterChange>,
+}
+
+impl ParameterTimeline {
+    /// Add a change to the timeline. Changes with the same effective time are
+    /// kept in the order they are added.
+    pub fn insert(&mut self, change: ParameterChange) {
+        let position = self
+            .changes
+            .partition_point(|c| c.effective_time <= change.effective_time);
+        self.changes.insert(position, change);
+    }
+
+    /// All changes, ordered by effective time.
+    pub fn changes(&self) -> &[ParameterChange] { &self.changes }
+
+    /// The changes of a single parameter, ordered by effective time.
+    pub fn history(&self, kind: ParameterKind) -> impl Iterator<Item = &ParameterChange> {
+        self.changes
+            .iter()
+            .filter(move |change| change.value.kind() == kind)
+    }
+
+    /// The value of the parameter in effect at the given time, if the
+    /// timeline contains any change of the parameter at or before that time.
+    pub fn value_at(
+        &self,
+        kind: ParameterKind,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Option<&ParameterValue> {
+        self.history(kind)
+            .take_while(|change| change.effective_time <= time)
+            .last()
+            .map(|change| &change.value)
+    }
+
+    /// The changes that take effect after the given time.
+    pub fn upcoming(
+        &self,
+        after: chrono::DateTime<chrono::Utc>,
+    ) -> impl Iterator<Item = &ParameterChange> {
+        let start = self
+            .changes
+            .partition_point(|change| change.effective_time <= after);
+        self.changes[start..].iter()
+    }
+
+    /// Add a change enqueued by an update instruction in a block with the
+    /// given time. As on the chain, this cancels the changes of the same
+    /// parameter that have not taken effect at that time and would take
+    /// effect at or after the new change.
+    pub fn enqueue(&mut self, change: ParameterChange, block_time: chrono::DateTime<chrono::Utc>) {
+        let kind = change.value.kind();
+        self.changes.retain(|c| {
+            c.value.kind() != kind
+                || matches!(c.source, ChangeSource::Initial { .. })
+                || c.effective_time <= block_time
+                || c.effective_time < change.effective_time
+        });
+        self.insert(change);
+    }
+
+    fn contains(&self, effective_time: chrono::DateTime<chrono::Utc>, kind: ParameterKind) -> bool {
+        self.changes
+            .iter()
+            .any(|c| c.effective_time == effective_time && c.value.kind() == kind)
+    }
+}
+
+/// Convert the effective time of an update. An effective time of 0 means that
+/// the update takes effect immediately, i.e., at the given block time.
+fn effective_time(
+    time: TransactionTime,
+    block_time: chrono::DateTime<chrono::Utc>,
+) -> chrono::DateTime<chrono::Utc> {
+    if time.seconds == 0 {
+        return block_time;
+    }
+    chrono::Utc
+        .timestamp_opt(time.seconds as i64, 0)
+        .single()
+        .unwrap_or(block_time)
+}
+
+/// Add the updates pending in the given block to the timeline, unless the
+/// timeline already contains a change of the same parameter at the same time.
+async fn add_pending(
+    client: &mut v2::Client,
+    timeline: &mut ParameterTimeline,
+    height: AbsoluteBlockHeight,
+    block_time: chrono::DateTime<chrono::Utc>,
+) -> QueryResult<()> {
+    let pending = client
+        .get_block_pending_updates(height)
+        .await?
+        .response
+        .try_collect::<Vec<PendingUpdate>>()
+        .await?;
+    for update in pending {
+        let Some(value) = ParameterValue::from_pending(&update.effect) else {
+            continue;
+        };
+        let time = effective_time(update.effective_time, block_time);
+        if !timeline.contains(time, value.kind()) {
+            timeline.insert(ParameterChange {
+                effective_time: time,
+                value,
+                source: ChangeSource::Pending,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Construct the timeline of parameter values over the given range of
+/// heights (inclusive). The timeline contains the values in effect at the
+/// start of the range, the updates pending at the start or enqueued in the
+/// range that were not cancelled by a later update of the same parameter, and
+/// the updates pending at the end of the range.
+pub async fn parameter_history(
+    client: &mut v2::Client,
+    start: AbsoluteBlockHeight,
+    end: AbsoluteBlockHeight,
+) -> QueryResult<ParameterTimeline> {
+    let mut timeline = ParameterTimeline::default();
+    let params = client.get_block_chain_parameters(start).await?;
+    let start_time = client.get_block_info(start).await?.response.block_slot_time;
+    for value in ParameterValue::from_chain_parameters(&params.response) {
+        timeline.insert(ParameterChange {
+            effective_time: start_time,
+            value,
+            source: ChangeSource::Initial {
+                block_hash: params.block_hash,
+            },
+        });
+    }
+    add_pending(client, &mut timeline, start, start_time).await?;
+    let mut end_time = start_time;
+    for height in start.height..=end.height {
+        let info = client
+            .get_block_info(AbsoluteBlockHeight::from(height))
+            .await?
+            .response;
+        end_time = info.block_slot_time;
+        if info.transaction_count == 0 {
+            continue;
+        }
+        let mut events = client
+            .get_block_transaction_events(info.block_hash)
+            .await?
+            .response;
+        while let Some(summary) = events.try_next().await? {
+            let BlockItemSummaryDetails::Update(details) = &summary.details else {
+                continue;
+            };
+            if let Some(value) = ParameterValue::from_payload(&details.payload) {
+                let change = ParameterChange {
+                    effective_time: effective_time(details.effective_time, info.block_slot_time),
+                    value,
+                    source: ChangeSource::Enqueued {
+                        block_hash:  info.block_hash,
+                        transaction: summary.hash,
+                    },
+                };
+                timeline.enqueue(change, info.block_slot_time);
+            }
+        }
+    }
+    add_pending(client, &mut timeline, end, end_time).await?;
+    Ok(timeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(seconds: i64) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.timestamp_opt(seconds, 0).unwrap()
+    }
+
+    fn limit(energy: u64) -> ParameterValue { ParameterValue::BlockEnergyLimit(Energy { energy }) }
+
+    fn change(effective: i64, energy: u64) -> ParameterChange {
+        ParameterChange {
+            effective_time: time(effective),
+            value:          limit(energy),
+            source:         ChangeSource::Enqueued {
+                block_hash:  BlockHash::new([0u8; 32]),
+                transaction: TransactionHash::new([0u8; 32]),
+            },
+        }
+    }
+
+    fn value_at(timeline: &ParameterTimeline, seconds: i64) -> Option<u64> {
+        match timeline.value_at(ParameterKind::BlockEnergyLimit, time(seconds)) {
+            Some(ParameterValue::BlockEnergyLimit(energy)) => Some(energy.energy),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_value_at_superseded() {
+        let mut timeline = ParameterTimeline::default();
+        timeline.insert(ParameterChange {
+            effective_time: time(0),
+            value:          limit(1),
+            source:         ChangeSource::Initial {
+                block_hash: BlockHash::new([0u8; 32]),
+            },
+        });
+        // Enqueued at 100 to take effect at 300.
+        timeline.enqueue(change(300, 2), time(100));
+        assert_eq!(value_at(&timeline, 350), Some(2));
+        // Enqueued at 200 to take effect at 250, which cancels the update at 300.
+        timeline.enqueue(change(250, 3), time(200));
+        assert_eq!(value_at(&timeline, 100), Some(1));
+        assert_eq!(value_at(&timeline, 260), Some(3));
+        assert_eq!(value_at(&timeline, 350), Some(3));
+        // An update that takes effect later does not cancel the earlier one.
+        timeline.enqueue(change(500, 4), time(210));
+        assert_eq!(value_at(&timeline, 350), Some(3));
+        assert_eq!(value_at(&timeline, 500), Some(4));
+        // Updates that already took effect are kept.
+        timeline.enqueue(change(600, 5), time(550));
+        assert_eq!(value_at(&timeline, 520), Some(4));
+        assert_eq!(value_at(&timeline, 600), Some(5));
+        assert_eq!(timeline.history(ParameterKind::BlockEnergyLimit).count(), 4);
+    }
+
+    #[test]
+    fn test_enqueue_cancels_pending() {
+        let mut timeline = ParameterTimeline::default();
+        // Pending at the start of the timeline to take effect at 300.
+        timeline.insert(ParameterChange {
+            effective_time: time(300),
+            value:          limit(2),
+            source:         ChangeSource::Pending,
+        });
+        timeline.enqueue(change(400, 3), time(100));
+        assert_eq!(value_at(&timeline, 350), Some(2));
+        timeline.enqueue(change(200, 4), time(150));
+        assert_eq!(value_at(&timeline, 350), Some(4));
+        assert_eq!(timeline.history(ParameterKind::BlockEnergyLimit).count(), 1);
+    }
+}
@@ -0,0 +1,51 @@
+//! Support for chain governance through update instructions.
+//!
+//! The [`update`] module prepares update instructions for any
+//! [`UpdatePayload`](crate::types::UpdatePayload), collects the signatures of
+//! the key holders independently of each other, and submits the instruction
+//! once enough of the authorized keys have signed it. The [`history`] module
+//! tracks how the chain parameters change over time, including updates that
+//! are still pending.
+use crate::{
+    types::{UpdateKeysIndex, UpdateSequenceNumber},
+    v2,
+};
+
+pub mod history;
+pub mod update;
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when preparing or submitting a chain update.
+pub enum GovernanceError {
+    #[error("Unable to query the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Unable to send the update: {0}")]
+    Send(#[from] v2::RPCError),
+    #[error("The update is not supported by the chain parameters in effect: {0}")]
+    UnsupportedUpdate(&'static str),
+    #[error("None of the supplied keys are authorized for the update.")]
+    NoAuthorizedKeys,
+    #[error("Key index {} is not authorized for the update.", .0.index)]
+    UnauthorizedKey(UpdateKeysIndex),
+    #[error("The signature of key index {} is not valid for the update.", .0.index)]
+    InvalidSignature(UpdateKeysIndex),
+    #[error("Only {signatures} authorized keys signed the update, but {threshold} are required.")]
+    InsufficientSignatures { signatures: usize, threshold: u16 },
+    #[error(
+        "The update has sequence number {}, but the next sequence number is {}.",
+        .draft.number,
+        .expected.number
+    )]
+    StaleSequenceNumber {
+        draft:    UpdateSequenceNumber,
+        expected: UpdateSequenceNumber,
+    },
+    #[error("The drafts being merged are for different updates.")]
+    MismatchedDraft,
+    #[error("The drafts have different signatures for key index {}.", .0.index)]
+    ConflictingSignature(UpdateKeysIndex),
+    #[error("Unable to parse the draft: {0}")]
+    InvalidDraft(String),
+    #[error("Unable to access the draft file: {0}")]
+    Io(#[from] std::io::Error),
+}
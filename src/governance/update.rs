@@ -0,0 +1,542 @@
+//! Preparation, signing and submission of chain updates.
+//!
+//! A chain update goes through the following steps.
+//!
+//! 1. A [`ChainUpdateBuilder`] is constructed from the [`UpdatePayload`] and
+//!    [`prepare`](ChainUpdateBuilder::prepare)d, which looks up the next
+//!    sequence number of the update queue and produces an unsigned
+//!    [`UpdateDraft`].
+//! 2. The draft is exported with [`UpdateDraft::to_hex`] or
+//!    [`UpdateDraft::write`] and handed to each key holder, who looks up the
+//!    [`UpdateAuthorization`] of the update, signs the draft with their keys,
+//!    and exports it again. The signatures of independently signed drafts are
+//!    combined with [`UpdateDraft::merge`].
+//! 3. The draft is submitted with [`submit_update`], which checks that the
+//!    signatures are valid and meet the threshold of the access structure on
+//!    the chain.
+use super::GovernanceError;
+use crate::{
+    id::types::VerifyKey,
+    types::{
+        hashes::{BlockHash, TransactionHash, UpdateSignHash},
+        transactions::{update, BlockItem, Payload, UpdateSigner},
+        AccessStructure, BlockItemSummary, Level1Update, NextUpdateSequenceNumbers, RootUpdate,
+        UpdateInstruction, UpdateKeyPair, UpdateKeysIndex, UpdateKeysThreshold, UpdatePayload,
+        UpdatePublicKey, UpdateSequenceNumber,
+    },
+    v2::{self, BlockIdentifier, ChainParameters, IntoBlockIdentifier},
+};
+use concordium_base::common::{
+    self,
+    types::{Signature, TransactionTime},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+/// The sequence number that the next update with the given payload must
+/// have.
+pub fn sequence_number(
+    numbers: &NextUpdateSequenceNumbers,
+    payload: &UpdatePayload,
+) -> UpdateSequenceNumber {
+    match payload {
+        UpdatePayload::Protocol(_) => numbers.protocol,
+        UpdatePayload::ElectionDifficulty(_) => numbers.election_difficulty,
+        UpdatePayload::EuroPerEnergy(_) => numbers.euro_per_energy,
+        UpdatePayload::MicroGTUPerEuro(_) => numbers.micro_ccd_per_euro,
+        UpdatePayload::FoundationAccount(_) => numbers.foundation_account,
+        UpdatePayload::MintDistribution(_) => numbers.mint_distribution,
+        UpdatePayload::MintDistributionCPV1(_) => numbers.mint_distribution,
+        UpdatePayload::TransactionFeeDistribution(_) => numbers.transaction_fee_distribution,
+        UpdatePayload::GASRewards(_) => numbers.gas_rewards,
+        UpdatePayload::GASRewardsCPV2(_) => numbers.gas_rewards,
+        UpdatePayload::BakerStakeThreshold(_) => numbers.pool_parameters,
+        UpdatePayload::PoolParametersCPV1(_) => numbers.pool_parameters,
+        UpdatePayload::Root(RootUpdate::RootKeysUpdate(_)) => numbers.root_keys,
+        UpdatePayload::Root(RootUpdate::Level1KeysUpdate(_)) => numbers.level_1_keys,
+        UpdatePayload::Root(RootUpdate::Level2KeysUpdate(_)) => numbers.level_2_keys,
+        UpdatePayload::Root(RootUpdate::Level2KeysUpdateV1(_)) => numbers.level_2_keys,
+        UpdatePayload::Level1(Level1Update::Level1KeysUpdate(_)) => numbers.level_1_keys,
+        UpdatePayload::Level1(Level1Update::Level2KeysUpdate(_)) => numbers.level_2_keys,
+        UpdatePayload::Level1(Level1Update::Level2KeysUpdateV1(_)) => numbers.level_2_keys,
+        UpdatePayload::AddAnonymityRevoker(_) => numbers.add_anonymity_revoker,
+        UpdatePayload::AddIdentityProvider(_) => numbers.add_identity_provider,
+        UpdatePayload::CooldownParametersCPV1(_) => numbers.cooldown_parameters,
+        UpdatePayload::TimeParametersCPV1(_) => numbers.time_parameters,
+        UpdatePayload::TimeoutParametersCPV2(_) => numbers.timeout_parameters,
+        UpdatePayload::MinBlockTimeCPV2(_) => numbers.min_block_time,
+        UpdatePayload::BlockEnergyLimitCPV2(_) => numbers.block_energy_limit,
+        UpdatePayload::FinalizationCommitteeParametersCPV2(_) => {
+            numbers.finalization_committee_parameters
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The keys that are authorized to sign an update, and how many of them must
+/// sign it.
+pub struct UpdateAuthorization {
+    /// All keys of the level the update belongs to. Signatures refer to keys
+    /// by their index in this list.
+    pub keys:       Vec<UpdatePublicKey>,
+    /// The indices of the keys that are authorized to sign the update.
+    pub authorized: BTreeSet<UpdateKeysIndex>,
+    pub threshold:  UpdateKeysThreshold,
+}
+
+impl UpdateAuthorization {
+    fn all_keys(keys: &[UpdatePublicKey], threshold: UpdateKeysThreshold) -> Self {
+        Self {
+            keys: keys.to_vec(),
+            authorized: (0..keys.len())
+                .map(|index| UpdateKeysIndex {
+                    index: index as u16,
+                })
+                .collect(),
+            threshold,
+        }
+    }
+
+    fn level_2(keys: &[UpdatePublicKey], access: &AccessStructure) -> Self {
+        Self {
+            keys:       keys.to_vec(),
+            authorized: access.authorized_keys.clone(),
+            threshold:  access.threshold,
+        }
+    }
+
+    /// Look up the authorization of the update with the given payload in the
+    /// given chain parameters.
+    pub fn new(params: &ChainParameters, payload: &UpdatePayload) -> Result<Self, GovernanceError> {
+        let (root, level_1) = match params {
+            ChainParameters::V0(p) => (&p.keys.root_keys, &p.keys.level_1_keys),
+            ChainParameters::V1(p) => (&p.keys.root_keys, &p.keys.level_1_keys),
+            ChainParameters::V2(p) => (&p.keys.root_keys, &p.keys.level_1_keys),
+        };
+        let level_2 = params.common_update_keys();
+        let keys = &level_2.keys;
+        let access = match payload {
+            UpdatePayload::Root(_) => return Ok(Self::all_keys(&root.keys, root.threshold)),
+            UpdatePayload::Level1(_) => {
+                return Ok(Self::all_keys(&level_1.keys, level_1.threshold))
+            }
+            UpdatePayload::Protocol(_) => &level_2.protocol,
+            UpdatePayload::ElectionDifficulty(_)
+            | UpdatePayload::TimeoutParametersCPV2(_)
+            | UpdatePayload::MinBlockTimeCPV2(_)
+            | UpdatePayload::BlockEnergyLimitCPV2(_) => &level_2.election_difficulty,
+            UpdatePayload::EuroPerEnergy(_) => &level_2.euro_per_energy,
+            UpdatePayload::MicroGTUPerEuro(_) => &level_2.micro_gtu_per_euro,
+            UpdatePayload::FoundationAccount(_) => &level_2.foundation_account,
+            UpdatePayload::MintDistribution(_) | UpdatePayload::MintDistributionCPV1(_) => {
+                &level_2.mint_distribution
+            }
+            UpdatePayload::TransactionFeeDistribution(_) => &level_2.transaction_fee_distribution,
+            UpdatePayload::GASRewards(_) | UpdatePayload::GASRewardsCPV2(_) => {
+                &level_2.param_gas_rewards
+            }
+            UpdatePayload::BakerStakeThreshold(_)
+            | UpdatePayload::PoolParametersCPV1(_)
+            | UpdatePayload::FinalizationCommitteeParametersCPV2(_) => &level_2.pool_parameters,
+            UpdatePayload::AddAnonymityRevoker(_) => &level_2.add_anonymity_revoker,
+            UpdatePayload::AddIdentityProvider(_) => &level_2.add_identity_provider,
+            UpdatePayload::CooldownParametersCPV1(_) => match params {
+                ChainParameters::V0(_) => {
+                    return Err(GovernanceError::UnsupportedUpdate("cooldown parameters"))
+                }
+                ChainParameters::V1(p) => &p.keys.level_2_keys.cooldown_parameters,
+                ChainParameters::V2(p) => &p.keys.level_2_keys.cooldown_parameters,
+            },
+            UpdatePayload::TimeParametersCPV1(_) => match params {
+                ChainParameters::V0(_) => {
+                    return Err(GovernanceError::UnsupportedUpdate("time parameters"))
+                }
+                ChainParameters::V1(p) => &p.keys.level_2_keys.time_parameters,
+                ChainParameters::V2(p) => &p.keys.level_2_keys.time_parameters,
+            },
+        };
+        Ok(Self::level_2(keys, access))
+    }
+
+    /// Look up the authorization of the update with the given payload in the
+    /// given block.
+    pub async fn query(
+        client: &mut v2::Client,
+        payload: &UpdatePayload,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Self, GovernanceError> {
+        let params = client.get_block_chain_parameters(bi).await?.response;
+        Self::new(&params, payload)
+    }
+
+    /// Assign the given key pairs to their indices. Keys that are not
+    /// authorized for the update are ignored, and an error is returned if
+    /// none of them are authorized.
+    pub fn signer(
+        &self,
+        key_pairs: impl IntoIterator<Item = UpdateKeyPair>,
+    ) -> Result<BTreeMap<UpdateKeysIndex, UpdateKeyPair>, GovernanceError> {
+        let mut signer = BTreeMap::new();
+        for kp in key_pairs {
+            let position = self.keys.iter().position(|key| key.public == kp.public.into());
+            if let Some(position) = position {
+                let index = UpdateKeysIndex {
+                    index: position as u16,
+                };
+                if self.authorized.contains(&index) {
+                    signer.insert(index, kp);
+                }
+            }
+        }
+        if signer.is_empty() {
+            return Err(GovernanceError::NoAuthorizedKeys);
+        }
+        Ok(signer)
+    }
+
+    /// Check that the draft is signed by at least the threshold of
+    /// authorized keys, only by authorized keys, and that each signature is
+    /// a valid signature of the update by the key at its index.
+    pub fn check(&self, draft: &UpdateDraft) -> Result<(), GovernanceError> {
+        let hash = draft.sign_hash();
+        let signatures = &draft.instruction.signatures.signatures;
+        for (index, signature) in signatures {
+            if !self.authorized.contains(index) {
+                return Err(GovernanceError::UnauthorizedKey(*index));
+            }
+            let valid = self
+                .keys
+                .get(usize::from(index.index))
+                .map_or(false, |key| verify_signature(key, &hash, signature));
+            if !valid {
+                return Err(GovernanceError::InvalidSignature(*index));
+            }
+        }
+        let threshold = u16::from(self.threshold);
+        if signatures.len() < usize::from(threshold) {
+            return Err(GovernanceError::InsufficientSignatures {
+                signatures: signatures.len(),
+                threshold,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Whether the signature is a valid signature of the hash by the key.
+fn verify_signature(key: &UpdatePublicKey, hash: &UpdateSignHash, signature: &Signature) -> bool {
+    let VerifyKey::Ed25519VerifyKey(public) = &key.public;
+    ed25519_dalek::Signature::try_from(signature.sig.as_slice())
+        .map_or(false, |signature| public.verify_strict(hash.as_ref(), &signature).is_ok())
+}
+
+#[derive(Debug, Clone)]
+/// Builder for a chain update.
+pub struct ChainUpdateBuilder {
+    payload:        UpdatePayload,
+    effective_time: TransactionTime,
+    expiry:         TransactionTime,
+}
+
+impl ChainUpdateBuilder {
+    /// Construct a builder for an update with the given payload that must be
+    /// submitted before `expiry`. By default the update takes effect
+    /// immediately.
+    pub fn new(payload: UpdatePayload, expiry: TransactionTime) -> Self {
+        Self {
+            payload,
+            effective_time: TransactionTime::from_seconds(0),
+            expiry,
+        }
+    }
+
+    /// Set the time at which the update takes effect.
+    pub fn effective_time(mut self, effective_time: TransactionTime) -> Self {
+        self.effective_time = effective_time;
+        self
+    }
+
+    /// Construct an unsigned draft of the update, using the next sequence
+    /// number of its update queue in the last finalized block. This also
+    /// checks that the update is supported by the current chain parameters.
+    pub async fn prepare(self, client: &mut v2::Client) -> Result<UpdateDraft, GovernanceError> {
+        let params = client
+            .get_block_chain_parameters(BlockIdentifier::LastFinal)
+            .await?;
+        UpdateAuthorization::new(&params.response, &self.payload)?;
+        let numbers = client
+            .get_next_update_sequence_numbers(params.block_hash)
+            .await?
+            .response;
+        let seq_number = sequence_number(&numbers, &self.payload);
+        let no_signatures = BTreeMap::<UpdateKeysIndex, UpdateKeyPair>::new();
+        let instruction = update::update(
+            &no_signatures,
+            seq_number,
+            self.effective_time,
+            self.expiry,
+            self.payload,
+        );
+        Ok(UpdateDraft { instruction })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// An update instruction that is being signed.
+pub struct UpdateDraft {
+    instruction: UpdateInstruction,
+}
+
+impl UpdateDraft {
+    /// The payload of the update.
+    pub fn payload(&self) -> &UpdatePayload { &self.instruction.payload }
+
+    /// The sequence number of the update.
+    pub fn sequence_number(&self) -> UpdateSequenceNumber { self.instruction.header.seq_number }
+
+    /// The indices of the keys that have signed the draft.
+    pub fn signers(&self) -> BTreeSet<UpdateKeysIndex> {
+        self.instruction
+            .signatures
+            .signatures
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    /// The hash of the update that the keys sign.
+    fn sign_hash(&self) -> UpdateSignHash {
+        let payload = common::to_bytes(&self.instruction.payload);
+        update::compute_sign_hash(&self.instruction.header, &payload)
+    }
+
+    /// Sign the draft with the given keys. Use
+    /// [`UpdateAuthorization::signer`] to construct the signer from key
+    /// pairs.
+    pub fn sign(&mut self, signer: &impl UpdateSigner) {
+        let hash = self.sign_hash();
+        self.instruction
+            .signatures
+            .signatures
+            .extend(signer.sign_update_hash(&hash));
+    }
+
+    /// Add the signatures of another draft of the same update. The drafts
+    /// must not have different signatures for the same key index. Nothing is
+    /// added if an error is returned.
+    pub fn merge(&mut self, other: &UpdateDraft) -> Result<(), GovernanceError> {
+        let same_header = common::to_bytes(&self.instruction.header)
+            == common::to_bytes(&other.instruction.header);
+        let same_payload = common::to_bytes(&self.instruction.payload)
+            == common::to_bytes(&other.instruction.payload);
+        if !same_header || !same_payload {
+            return Err(GovernanceError::MismatchedDraft);
+        }
+        let signatures = &self.instruction.signatures.signatures;
+        for (index, signature) in &other.instruction.signatures.signatures {
+            if signatures.get(index).map_or(false, |s| s.sig != signature.sig) {
+                return Err(GovernanceError::ConflictingSignature(*index));
+            }
+        }
+        self.instruction.signatures.signatures.extend(
+            other
+                .instruction
+                .signatures
+                .signatures
+                .iter()
+                .map(|(index, signature)| (*index, signature.clone())),
+        );
+        Ok(())
+    }
+
+    /// Export the draft, including the signatures collected so far, as a hex
+    /// string.
+    pub fn to_hex(&self) -> String { hex::encode(common::to_bytes(&self.instruction)) }
+
+    /// Import a draft exported with [`to_hex`](Self::to_hex).
+    pub fn from_hex(data: &str) -> Result<Self, GovernanceError> {
+        let bytes = hex::decode(data.trim())
+            .map_err(|e| GovernanceError::InvalidDraft(e.to_string()))?;
+        let instruction = common::from_bytes(&mut std::io::Cursor::new(bytes))
+            .map_err(|e| GovernanceError::InvalidDraft(e.to_string()))?;
+        Ok(Self { instruction })
+    }
+
+    /// Write the draft to a file.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), GovernanceError> {
+        std::fs::write(path, self.to_hex())?;
+        Ok(())
+    }
+
+    /// Read a draft from a file written by [`write`](Self::write).
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, GovernanceError> {
+        Self::from_hex(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Submit the signed update after checking that it is signed by enough of the
+/// keys authorized in the last finalized block, and that its sequence number
+/// is still the next one in its queue.
+pub async fn submit_update(
+    client: &mut v2::Client,
+    draft: &UpdateDraft,
+) -> Result<TransactionHash, GovernanceError> {
+    let params = client
+        .get_block_chain_parameters(BlockIdentifier::LastFinal)
+        .await?;
+    UpdateAuthorization::new(&params.response, draft.payload())?.check(draft)?;
+    let numbers = client
+        .get_next_update_sequence_numbers(params.block_hash)
+        .await?
+        .response;
+    let expected = sequence_number(&numbers, draft.payload());
+    if expected != draft.sequence_number() {
+        return Err(GovernanceError::StaleSequenceNumber {
+            draft: draft.sequence_number(),
+            expected,
+        });
+    }
+    let block_item: BlockItem<Payload> = draft.instruction.clone().into();
+    Ok(client.send_block_item(&block_item).await?)
+}
+
+/// Submit the signed update as [`submit_update`] does and wait until it is
+/// finalized.
+pub async fn submit_update_and_wait(
+    client: &mut v2::Client,
+    draft: &UpdateDraft,
+) -> Result<(BlockHash, BlockItemSummary), GovernanceError> {
+    let hash = submit_update(client, draft).await?;
+    Ok(client.wait_until_finalized(&hash).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ExchangeRate;
+
+    fn key_pairs(n: usize) -> Vec<UpdateKeyPair> {
+        let mut csprng = rand::thread_rng();
+        (0..n).map(|_| UpdateKeyPair::generate(&mut csprng)).collect()
+    }
+
+    fn authorization(
+        key_pairs: &[UpdateKeyPair],
+        authorized: &[u16],
+        threshold: u16,
+    ) -> UpdateAuthorization {
+        UpdateAuthorization {
+            keys:       key_pairs
+                .iter()
+                .map(|kp| UpdatePublicKey {
+                    public: VerifyKey::Ed25519VerifyKey(kp.public),
+                })
+                .collect(),
+            authorized: authorized.iter().map(|&index| UpdateKeysIndex { index }).collect(),
+            threshold:  UpdateKeysThreshold::try_from(threshold).expect("Threshold is not 0."),
+        }
+    }
+
+    fn signer(
+        key_pairs: &[UpdateKeyPair],
+        indices: &[u16],
+    ) -> BTreeMap<UpdateKeysIndex, UpdateKeyPair> {
+        indices
+            .iter()
+            .map(|&index| (UpdateKeysIndex { index }, key_pairs[usize::from(index)].clone()))
+            .collect()
+    }
+
+    fn draft() -> UpdateDraft {
+        let no_signatures = BTreeMap::<UpdateKeysIndex, UpdateKeyPair>::new();
+        let instruction = update::update(
+            &no_signatures,
+            UpdateSequenceNumber { number: 1 },
+            TransactionTime::from_seconds(0),
+            TransactionTime::from_seconds(100),
+            UpdatePayload::MicroGTUPerEuro(ExchangeRate::new_unchecked(1, 1)),
+        );
+        UpdateDraft { instruction }
+    }
+
+    #[test]
+    fn test_check_threshold() {
+        let kps = key_pairs(3);
+        let auth = authorization(&kps, &[0, 1, 2], 2);
+        let mut draft = draft();
+        draft.sign(&signer(&kps, &[0]));
+        assert!(matches!(
+            auth.check(&draft),
+            Err(GovernanceError::InsufficientSignatures {
+                signatures: 1,
+                threshold:  2,
+            })
+        ));
+        draft.sign(&signer(&kps, &[2]));
+        assert!(auth.check(&draft).is_ok());
+    }
+
+    #[test]
+    fn test_check_unauthorized_key() {
+        let kps = key_pairs(3);
+        let auth = authorization(&kps, &[0, 1], 1);
+        let mut draft = draft();
+        draft.sign(&signer(&kps, &[0, 2]));
+        assert!(matches!(
+            auth.check(&draft),
+            Err(GovernanceError::UnauthorizedKey(UpdateKeysIndex { index: 2 }))
+        ));
+    }
+
+    #[test]
+    fn test_check_bad_signature() {
+        let kps = key_pairs(2);
+        let auth = authorization(&kps, &[0, 1], 1);
+        // A valid signature under the wrong index.
+        let mut draft = draft();
+        draft.sign(&signer(&kps, &[0]));
+        let signatures = &mut draft.instruction.signatures.signatures;
+        let signature = signatures[&UpdateKeysIndex { index: 0 }].clone();
+        signatures.insert(UpdateKeysIndex { index: 1 }, signature);
+        assert!(matches!(
+            auth.check(&draft),
+            Err(GovernanceError::InvalidSignature(UpdateKeysIndex { index: 1 }))
+        ));
+        // A corrupted signature.
+        let mut draft = self::draft();
+        draft.sign(&signer(&kps, &[0]));
+        for signature in draft.instruction.signatures.signatures.values_mut() {
+            signature.sig[0] ^= 1;
+        }
+        assert!(matches!(
+            auth.check(&draft),
+            Err(GovernanceError::InvalidSignature(UpdateKeysIndex { index: 0 }))
+        ));
+    }
+
+    #[test]
+    fn test_merge() {
+        let kps = key_pairs(3);
+        let auth = authorization(&kps, &[0, 1, 2], 2);
+        let mut first = draft();
+        first.sign(&signer(&kps, &[0]));
+        let mut second = draft();
+        second.sign(&signer(&kps, &[1]));
+        first.merge(&second).expect("The drafts do not conflict.");
+        assert_eq!(first.signers().len(), 2);
+        assert!(auth.check(&first).is_ok());
+
+        let mut conflicting = draft();
+        conflicting.sign(&signer(&kps, &[1]));
+        for signature in conflicting.instruction.signatures.signatures.values_mut() {
+            signature.sig[0] ^= 1;
+        }
+        assert!(matches!(
+            first.merge(&conflicting),
+            Err(GovernanceError::ConflictingSignature(UpdateKeysIndex { index: 1 }))
+        ));
+        assert!(auth.check(&first).is_ok());
+    }
+}
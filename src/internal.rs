@@ -124,3 +124,18 @@ pub(crate) mod duration_millis {
         Ok(chrono::Duration::milliseconds(millis))
     }
 }
+
+/// Write the rows in CSV format, with a header row derived from the field
+/// names of the first row.
+#[cfg(feature = "csv")]
+pub(crate) fn write_csv<T: serde::Serialize, W: std::io::Write>(
+    rows: &[T],
+    writer: W,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
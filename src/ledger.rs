@@ -0,0 +1,260 @@
+//! Export of account histories as accounting ledgers.
+//!
+//! The [`ledger_rows`] function turns an [`AccountHistory`] into a list of
+//! [`LedgerRow`]s, one for each balance change of the account, with the fee of
+//! a transaction attached to the row of the transaction's effect. The rows can
+//! be written in JSON format using [`write_json`], or in CSV format using
+//! `write_csv` if the `csv` feature is enabled.
+//!
+//! Since the history is reconstructed with alias-aware matching, transfers to
+//! and from any alias of the account are included, and transfers between two
+//! aliases of the account do not appear as separate debits and credits.
+use crate::{
+    account_history::{AccountHistory, BalanceChange, BalanceChangeKind},
+    types::{
+        hashes::{BlockHash, TransactionHash},
+        AbsoluteBlockHeight, Memo,
+    },
+};
+use concordium_base::common::{types::Amount, SerdeSerialize};
+use std::io::Write;
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when writing a ledger.
+pub enum LedgerExportError {
+    #[cfg(feature = "csv")]
+    #[error("Unable to write CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Unable to write JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+/// A single row of a ledger. Amounts are written in CCD.
+pub struct LedgerRow {
+    /// Slot time of the block in which the change happened.
+    pub timestamp:    chrono::DateTime<chrono::Utc>,
+    pub block_hash:   BlockHash,
+    pub block_height: AbsoluteBlockHeight,
+    /// The transaction that caused the change, if any. Rewards are not
+    /// caused by transactions.
+    pub transaction:  Option<TransactionHash>,
+    /// A short description of the cause of the change, such as `transfer`
+    /// or `delegation-reward`.
+    pub kind:         &'static str,
+    /// The other account or smart contract instance involved, if any.
+    pub counterparty: Option<String>,
+    #[serde(serialize_with = "serialize_ccd")]
+    pub debit:        Amount,
+    #[serde(serialize_with = "serialize_ccd")]
+    pub credit:       Amount,
+    /// The transaction fee paid by the account.
+    #[serde(serialize_with = "serialize_ccd")]
+    pub fee:          Amount,
+    /// The balance of the account after the row.
+    #[serde(serialize_with = "serialize_ccd")]
+    pub balance:      Amount,
+    /// The memo of the transfer, hex encoded.
+    pub memo:         Option<Memo>,
+}
+
+fn serialize_ccd<S: serde::Serializer>(amount: &Amount, ser: S) -> Result<S::Ok, S::Error> {
+    ser.collect_str(amount)
+}
+
+/// A short description of the kind of a balance change.
+pub fn kind_label(kind: &BalanceChangeKind) -> &'static str {
+    match kind {
+        BalanceChangeKind::TransactionFee => "transaction-fee",
+        BalanceChangeKind::Transfer { .. } => "transfer",
+        BalanceChangeKind::ScheduledTransfer { .. } => "scheduled-transfer",
+        BalanceChangeKind::ShieldedTransfer => "shielded-transfer",
+        BalanceChangeKind::ContractPayment { .. } => "contract-payment",
+        BalanceChangeKind::ContractPayout { .. } => "contract-payout",
+        BalanceChangeKind::BakingReward => "baking-reward",
+        BalanceChangeKind::DelegationReward => "delegation-reward",
+        BalanceChangeKind::FinalizationReward => "finalization-reward",
+        BalanceChangeKind::FoundationReward => "foundation-reward",
+    }
+}
+
+fn counterparty(kind: &BalanceChangeKind) -> Option<String> {
+    match kind {
+        BalanceChangeKind::Transfer { counterparty } => Some(counterparty.to_string()),
+        BalanceChangeKind::ScheduledTransfer { counterparty, .. } => Some(counterparty.to_string()),
+        BalanceChangeKind::ContractPayment { contract } => Some(contract.to_string()),
+        BalanceChangeKind::ContractPayout { contract } => Some(contract.to_string()),
+        _ => None,
+    }
+}
+
+fn row(change: &BalanceChange, fee: Amount) -> LedgerRow {
+    LedgerRow {
+        timestamp: change.block_time,
+        block_hash: change.block_hash,
+        block_height: change.block_height,
+        transaction: change.transaction,
+        kind: kind_label(&change.kind),
+        counterparty: counterparty(&change.kind),
+        debit: change.debit,
+        credit: change.credit,
+        fee,
+        balance: change.balance,
+        memo: change.memo.clone(),
+    }
+}
+
+/// Convert the history of an account to ledger rows.
+///
+/// Each transaction fee is reported in the `fee` column of the first row of
+/// the transaction. Transactions whose only effect on the balance is the fee,
+/// such as rejected transactions, get a single row with zero debit and
+/// credit.
+pub fn ledger_rows(history: &AccountHistory) -> Vec<LedgerRow> {
+    let zero = Amount::from_micro_ccd(0);
+    let mut rows = Vec::with_capacity(history.changes.len());
+    let mut changes = history.changes.iter().peekable();
+    while let Some(change) = changes.next() {
+        if change.kind != BalanceChangeKind::TransactionFee {
+            rows.push(row(change, zero));
+            continue;
+        }
+        // The fee is always recorded before the other effects of the
+        // transaction.
+        let fee = change.debit;
+        let mut first = true;
+        while let Some(next) = changes.next_if(|next| {
+            next.transaction == change.transaction && next.kind != BalanceChangeKind::TransactionFee
+        }) {
+            rows.push(row(next, if first { fee } else { zero }));
+            first = false;
+        }
+        if first {
+            let mut fee_row = row(change, fee);
+            fee_row.debit = zero;
+            rows.push(fee_row);
+        }
+    }
+    rows
+}
+
+/// Write the rows in CSV format, with a header row.
+#[cfg(feature = "csv")]
+pub fn write_csv<W: Write>(rows: &[LedgerRow], writer: W) -> Result<(), LedgerExportError> {
+    crate::internal::write_csv(rows, writer)?;
+    Ok(())
+}
+
+/// Write the rows as a JSON array.
+pub fn write_json<W: Write>(rows: &[LedgerRow], writer: W) -> Result<(), LedgerExportError> {
+    serde_json::to_writer_pretty(writer, rows)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{hashes::HashBytes, ContractAddress};
+    use concordium_base::contracts_common::AccountAddress;
+
+    const OTHER: AccountAddress = AccountAddress([2u8; 32]);
+
+    fn change(
+        transaction: Option<u8>,
+        kind: BalanceChangeKind,
+        credit: u64,
+        debit: u64,
+        balance: u64,
+    ) -> BalanceChange {
+        BalanceChange {
+            block_hash: HashBytes::new([0u8; 32]),
+            block_height: AbsoluteBlockHeight { height: 1 },
+            block_time: chrono::Utc::now(),
+            transaction: transaction.map(|tx| HashBytes::new([tx; 32])),
+            kind,
+            credit: Amount::from_micro_ccd(credit),
+            debit: Amount::from_micro_ccd(debit),
+            memo: None,
+            balance: Amount::from_micro_ccd(balance),
+        }
+    }
+
+    fn history() -> AccountHistory {
+        let contract = ContractAddress::new(0, 0);
+        AccountHistory {
+            address:         AccountAddress([1u8; 32]),
+            initial_balance: Amount::from_micro_ccd(1000),
+            changes:         vec![
+                change(Some(1), BalanceChangeKind::TransactionFee, 0, 10, 990),
+                change(
+                    Some(1),
+                    BalanceChangeKind::Transfer {
+                        counterparty: OTHER,
+                    },
+                    0,
+                    100,
+                    890,
+                ),
+                // A rejected transaction only pays the fee.
+                change(Some(2), BalanceChangeKind::TransactionFee, 0, 5, 885),
+                change(None, BalanceChangeKind::BakingReward, 50, 0, 935),
+                change(Some(3), BalanceChangeKind::TransactionFee, 0, 10, 925),
+                change(Some(3), BalanceChangeKind::ContractPayment { contract }, 0, 20, 905),
+                change(Some(3), BalanceChangeKind::ContractPayout { contract }, 30, 0, 935),
+            ],
+            checkpoints:     Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_ledger_rows() {
+        let rows = ledger_rows(&history());
+        let summary = rows
+            .iter()
+            .map(|row| {
+                (
+                    row.kind,
+                    row.debit.micro_ccd(),
+                    row.credit.micro_ccd(),
+                    row.fee.micro_ccd(),
+                    row.balance.micro_ccd(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(summary, [
+            ("transfer", 100, 0, 10, 890),
+            ("transaction-fee", 0, 0, 5, 885),
+            ("baking-reward", 0, 50, 0, 935),
+            ("contract-payment", 20, 0, 10, 905),
+            ("contract-payout", 0, 30, 0, 935),
+        ]);
+        assert_eq!(rows[0].counterparty, Some(OTHER.to_string()));
+        assert_eq!(rows[2].transaction, None);
+        assert_eq!(rows[3].counterparty, Some(ContractAddress::new(0, 0).to_string()));
+    }
+
+    #[test]
+    fn test_ledger_rows_empty() {
+        let mut history = history();
+        history.changes.clear();
+        assert!(ledger_rows(&history).is_empty());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_write_csv() {
+        let mut out = Vec::new();
+        write_csv(&ledger_rows(&history()), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "timestamp,blockHash,blockHeight,transaction,kind,counterparty,debit,credit,fee,\
+                 balance,memo"
+            )
+        );
+        assert_eq!(lines.count(), 5);
+    }
+}
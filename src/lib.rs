@@ -60,6 +60,36 @@ pub mod cis4;
 
 pub mod web3id;
 
+/// Planning and execution of key rotations and credential changes on
+/// accounts.
+pub mod account_keys;
+
+/// Tracking of shielded balances and construction of transfers from them.
+pub mod shielded;
+
+/// Reconstruction of the balance history of accounts.
+pub mod account_history;
+
+/// Export of account histories as accounting ledgers in CSV and JSON formats.
+pub mod ledger;
+
+/// Derivation of account aliases and mapping of aliases to canonical
+/// addresses.
+pub mod aliases;
+
+/// Management of stake, both for bakers and delegators.
+pub mod staking;
+
+/// Aggregation of finalization records into participation, delay and
+/// committee analytics.
+pub mod finalization_analytics;
+
+/// Projection of pending releases of scheduled transfers.
+pub mod release_schedule;
+
+/// Preparation, multi-party signing and submission of chain updates.
+pub mod governance;
+
 /// Re-export of the identity library.
 pub use concordium_base::id;
 
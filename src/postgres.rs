@@ -85,6 +85,14 @@ struct QueryStatements {
     /// It has 4 placeholders, for contract index and subindex, `id` start and
     /// limit.
     query_contract_statement_desc: QueryStatement,
+    /// Prepared statement that is used to query all aliases of an account in
+    /// ascending order. It has 4 placeholders, for the smallest and largest
+    /// alias, `id` start and limit.
+    query_aliases_statement_asc:   QueryStatement,
+    /// Prepared statement that is used to query all aliases of an account in
+    /// descending order. It has 4 placeholders, for the smallest and largest
+    /// alias, `id` start and limit.
+    query_aliases_statement_desc:  QueryStatement,
 }
 
 impl QueryStatements {
@@ -149,11 +157,50 @@ impl QueryStatements {
                 QueryStatement::Raw(statement)
             }
         };
+
+        // All aliases of an account lie in a contiguous range of addresses, so
+        // the queries use a range condition which can make use of an index on
+        // the account column. A transaction that affects several aliases of
+        // the account has a row for each of them in `ati`. It is returned only
+        // once, with the smallest of the row ids. The grouping happens before
+        // the `id` condition so that a transaction is not returned again on
+        // the next page when its rows are split between the pages.
+        let query_aliases_statement_asc = {
+            let statement = "SELECT aliases.id, summaries.block, summaries.timestamp, \
+                             summaries.height, summaries.summary
+ FROM (SELECT min(ati.id) AS id, ati.summary FROM ati
+       WHERE ati.account >= $1 AND ati.account <= $2 GROUP BY ati.summary) AS aliases
+ JOIN summaries ON aliases.summary = summaries.id
+ WHERE aliases.id >= $3
+ ORDER BY aliases.id ASC LIMIT $4";
+            if prepared {
+                QueryStatement::Prepared(client.prepare(statement).await?)
+            } else {
+                QueryStatement::Raw(statement)
+            }
+        };
+
+        let query_aliases_statement_desc = {
+            let statement = "SELECT aliases.id, summaries.block, summaries.timestamp, \
+                             summaries.height, summaries.summary
+ FROM (SELECT min(ati.id) AS id, ati.summary FROM ati
+       WHERE ati.account >= $1 AND ati.account <= $2 GROUP BY ati.summary) AS aliases
+ JOIN summaries ON aliases.summary = summaries.id
+ WHERE aliases.id <= $3
+ ORDER BY aliases.id DESC LIMIT $4";
+            if prepared {
+                QueryStatement::Prepared(client.prepare(statement).await?)
+            } else {
+                QueryStatement::Raw(statement)
+            }
+        };
         Ok(Self {
             query_account_statement_asc,
             query_contract_statement_asc,
             query_account_statement_desc,
             query_contract_statement_desc,
+            query_aliases_statement_asc,
+            query_aliases_statement_desc,
         })
     }
 }
@@ -295,6 +342,45 @@ impl DatabaseClient {
         Ok(rows.filter_map(|row_or_err| async move { construct_row(row_or_err) }))
     }
 
+    /// Get the list of transactions affecting the given account or any of its
+    /// aliases. The return value is a stream of rows that have been parsed.
+    ///
+    /// In contrast to [`query_account`](Self::query_account), which only
+    /// returns transactions that mention the exact address, this also returns,
+    /// e.g., transfers to other aliases of the account. The account may be
+    /// given by any of its aliases.
+    ///
+    /// The `limit` value limits the number of rows that will be returned.
+    pub async fn query_account_aliases(
+        &self,
+        acc: &AccountAddress,
+        limit: i64,
+        order: QueryOrder,
+    ) -> Result<impl futures::stream::Stream<Item = DatabaseRow>, tokio_postgres::Error> {
+        let (statement, start) = match order {
+            QueryOrder::Ascending { start } => (
+                &self.statements.query_aliases_statement_asc,
+                start.unwrap_or(i64::MIN),
+            ),
+            QueryOrder::Descending { start } => (
+                &self.statements.query_aliases_statement_desc,
+                start.unwrap_or(i64::MAX),
+            ),
+        };
+        let (lower, upper) = crate::aliases::alias_range(acc);
+        let lower_raw: &[u8] = lower.as_ref();
+        let upper_raw: &[u8] = upper.as_ref();
+        let params = [
+            &lower_raw as &(dyn ToSql + Sync),
+            &upper_raw as &(dyn ToSql + Sync),
+            &start as &(dyn ToSql + Sync),
+            &limit as &(dyn ToSql + Sync),
+        ];
+
+        let rows = self.query(statement, params).await?;
+        Ok(rows.filter_map(|row_or_err| async move { construct_row(row_or_err) }))
+    }
+
     /// Get the list of transactions affecting the given contract.
     /// The return value is a stream of rows that have been parsed.
     ///
@@ -333,6 +419,17 @@ impl DatabaseClient {
             .await
     }
 
+    /// Return all transactions affecting the account or any of its aliases,
+    /// starting with the given row id.
+    pub async fn iterate_account_aliases(
+        &self,
+        acc: &AccountAddress,
+        start: Option<i64>,
+    ) -> Result<impl futures::stream::Stream<Item = DatabaseRow>, tokio_postgres::Error> {
+        self.query_account_aliases(acc, i64::MAX, QueryOrder::Ascending { start })
+            .await
+    }
+
     /// Return all transactions affecting the contract, starting with the given
     /// row id.
     pub async fn iterate_contract(
@@ -366,3 +463,78 @@ fn construct_row(
         summary,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aliases::alias;
+    use concordium_base::common::types::Amount;
+
+    /// The row ids returned by the alias query of the account.
+    async fn alias_ids(
+        db: &DatabaseClient,
+        acc: &AccountAddress,
+        order: QueryOrder,
+    ) -> anyhow::Result<Vec<i64>> {
+        let rows = db.query_account_aliases(acc, 10, order).await?;
+        Ok(rows.map(|row| row.id).collect().await)
+    }
+
+    #[tokio::test]
+    #[ignore = "Requires a database given by the POSTGRES_TEST_CONFIG environment variable."]
+    async fn test_query_aliases_once_per_transaction() -> anyhow::Result<()> {
+        let config: Config = std::env::var("POSTGRES_TEST_CONFIG")?.parse()?;
+        let db = DatabaseClient::create(config, NoTls).await?;
+        // Temporary tables take precedence over any existing tables of the same
+        // name, and are dropped with the connection.
+        db.as_ref()
+            .batch_execute(
+                "CREATE TEMPORARY TABLE summaries (id INT8 PRIMARY KEY, block BYTEA NOT NULL, \
+                 timestamp INT8 NOT NULL, height INT8 NOT NULL, summary JSONB NOT NULL);
+                 CREATE TEMPORARY TABLE ati (id INT8 PRIMARY KEY, account BYTEA NOT NULL, \
+                 summary INT8 NOT NULL);",
+            )
+            .await?;
+        let address = AccountAddress([7u8; 32]);
+        // The rows must parse, since rows that do not are skipped by the query.
+        let entry = serde_json::to_value(DatabaseSummaryEntry::ProtocolEvent(
+            SpecialTransactionOutcome::PaydayFoundationReward {
+                foundation_account: address,
+                development_charge: Amount::from_micro_ccd(1),
+            },
+        ))?;
+        let block_raw: &[u8] = &[0u8; 32];
+        for id in [1i64, 2] {
+            db.as_ref()
+                .execute("INSERT INTO summaries VALUES ($1, $2, 0, $1, $3)", &[
+                    &id,
+                    &block_raw,
+                    &entry,
+                ])
+                .await?;
+        }
+        let first = alias(&address, 1).expect("Counter is in range.");
+        let second = alias(&address, 2).expect("Counter is in range.");
+        // The first transaction is a transfer between two aliases of the account.
+        for (id, account, summary) in [(1i64, first, 1i64), (2, second, 1), (3, address, 2)] {
+            let account_raw: &[u8] = account.as_ref();
+            db.as_ref()
+                .execute("INSERT INTO ati VALUES ($1, $2, $3)", &[
+                    &id,
+                    &account_raw,
+                    &summary,
+                ])
+                .await?;
+        }
+        let ascending = alias_ids(&db, &address, QueryOrder::Ascending { start: None }).await?;
+        assert_eq!(ascending, vec![1, 3]);
+        let descending = alias_ids(&db, &address, QueryOrder::Descending { start: None }).await?;
+        assert_eq!(descending, vec![3, 1]);
+        // The next page after the first transaction does not repeat it.
+        let next = alias_ids(&db, &address, QueryOrder::Ascending { start: Some(2) }).await?;
+        assert_eq!(next, vec![3]);
+        let next = alias_ids(&db, &address, QueryOrder::Descending { start: Some(2) }).await?;
+        assert_eq!(next, vec![1]);
+        Ok(())
+    }
+}
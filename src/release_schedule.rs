@@ -0,0 +1,317 @@
+//! Projection of future releases of locked amounts.
+//!
+//! Amounts sent with a scheduled transfer are locked on the receiving account
+//! until their release time, as recorded in its [`AccountReleaseSchedule`].
+//! A [`ReleaseProjection`] collects the pending releases of one or more
+//! accounts and aggregates them into the amounts unlocking per day or month,
+//! or the amount still locked at a given time.
+//!
+//! Each release lists the scheduled transfers that contribute to it. These
+//! can be looked up with [`scheduled_transfers`] to find the sender and the
+//! full schedule of each transfer.
+use crate::{
+    endpoints::QueryResult,
+    types::{
+        hashes::{BlockHash, TransactionHash},
+        AccountReleaseSchedule, AccountTransactionEffects, BlockItemSummaryDetails,
+    },
+    v2::{self, IntoBlockIdentifier},
+};
+use chrono::Datelike;
+use concordium_base::{
+    common::types::{Amount, Timestamp},
+    contracts_common::AccountAddress,
+};
+use futures::TryStreamExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Bound,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A release of a locked amount on an account.
+pub struct PendingRelease {
+    pub account:      AccountAddress,
+    pub timestamp:    chrono::DateTime<chrono::Utc>,
+    pub amount:       Amount,
+    /// The scheduled transfers that contribute to the release.
+    pub transactions: Vec<TransactionHash>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The length of the periods in which releases are aggregated.
+pub enum Granularity {
+    Day,
+    Month,
+}
+
+impl Granularity {
+    /// The first day of the period containing the given time.
+    pub fn period_start(self, time: chrono::DateTime<chrono::Utc>) -> chrono::NaiveDate {
+        let date = time.naive_utc().date();
+        match self {
+            Granularity::Day => date,
+            Granularity::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The releases in a single period.
+pub struct UnlockPeriod {
+    /// The first day of the period.
+    pub start:        chrono::NaiveDate,
+    /// The total amount released in the period.
+    pub amount:       Amount,
+    /// The accounts that receive releases in the period.
+    pub accounts:     BTreeSet<AccountAddress>,
+    /// The scheduled transfers that contribute to releases in the period.
+    pub transactions: BTreeSet<TransactionHash>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Pending releases of a set of accounts.
+pub struct ReleaseProjection {
+    /// The releases, grouped by timestamp. Releases with the same timestamp
+    /// are in the order they were added.
+    releases: BTreeMap<chrono::DateTime<chrono::Utc>, Vec<PendingRelease>>,
+}
+
+impl ReleaseProjection {
+    /// Construct an empty projection.
+    pub fn new() -> Self { Self::default() }
+
+    /// Add the release schedule of an account to the projection.
+    pub fn add_account(&mut self, account: AccountAddress, schedule: &AccountReleaseSchedule) {
+        for release in &schedule.schedule {
+            self.releases
+                .entry(release.timestamp)
+                .or_default()
+                .push(PendingRelease {
+                    account,
+                    timestamp: release.timestamp,
+                    amount: release.amount,
+                    transactions: release.transactions.clone(),
+                });
+        }
+    }
+
+    /// All pending releases, ordered by increasing timestamp.
+    pub fn releases(&self) -> impl Iterator<Item = &PendingRelease> {
+        self.releases.values().flatten()
+    }
+
+    /// The releases that happen at or after the given time.
+    pub fn releases_from(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+    ) -> impl Iterator<Item = &PendingRelease> {
+        self.releases.range(from..).flat_map(|(_, releases)| releases)
+    }
+
+    /// The total amount that is locked at the given time, i.e., the sum of
+    /// all releases after that time.
+    pub fn locked_at(&self, time: chrono::DateTime<chrono::Utc>) -> Amount {
+        Amount::from_micro_ccd(
+            self.releases
+                .range((Bound::Excluded(time), Bound::Unbounded))
+                .flat_map(|(_, releases)| releases)
+                .map(|release| release.amount.micro_ccd())
+                .sum(),
+        )
+    }
+
+    /// The total amount released in each period, starting with the period
+    /// containing `from`. Periods without releases are omitted.
+    pub fn unlocks(
+        &self,
+        granularity: Granularity,
+        from: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<UnlockPeriod> {
+        let mut periods = BTreeMap::<chrono::NaiveDate, UnlockPeriod>::new();
+        for release in self.releases_from(from) {
+            let start = granularity.period_start(release.timestamp);
+            let period = periods.entry(start).or_insert_with(|| UnlockPeriod {
+                start,
+                amount: Amount::from_micro_ccd(0),
+                accounts: BTreeSet::new(),
+                transactions: BTreeSet::new(),
+            });
+            period.amount =
+                Amount::from_micro_ccd(period.amount.micro_ccd() + release.amount.micro_ccd());
+            period.accounts.insert(release.account);
+            period.transactions.extend(release.transactions.iter().copied());
+        }
+        periods.into_values().collect()
+    }
+
+    /// The scheduled transfers that contribute to any of the pending
+    /// releases.
+    pub fn transactions(&self) -> BTreeSet<TransactionHash> {
+        self.releases()
+            .flat_map(|release| release.transactions.iter().copied())
+            .collect()
+    }
+}
+
+/// Construct the projection of the releases of a single account in the given
+/// block.
+pub async fn account_release_projection(
+    client: &mut v2::Client,
+    account: AccountAddress,
+    bi: impl IntoBlockIdentifier,
+) -> QueryResult<ReleaseProjection> {
+    let info = client.get_account_info(&account.into(), bi).await?.response;
+    let mut projection = ReleaseProjection::new();
+    projection.add_account(account, &info.account_release_schedule);
+    Ok(projection)
+}
+
+/// Construct the projection of the releases of all accounts in the given
+/// block. This queries every account on the chain and may take a while.
+pub async fn network_release_projection(
+    client: &mut v2::Client,
+    bi: impl IntoBlockIdentifier,
+) -> QueryResult<ReleaseProjection> {
+    let accounts = client.get_account_list(bi).await?;
+    let block = accounts.block_hash;
+    let accounts = accounts.response.try_collect::<Vec<_>>().await?;
+    let mut projection = ReleaseProjection::new();
+    for account in accounts {
+        let info = client.get_account_info(&account.into(), block).await?.response;
+        if !info.account_release_schedule.schedule.is_empty() {
+            projection.add_account(account, &info.account_release_schedule);
+        }
+    }
+    Ok(projection)
+}
+
+#[derive(Debug, Clone)]
+/// A transfer with a release schedule.
+pub struct ScheduledTransfer {
+    pub transaction: TransactionHash,
+    /// The block in which the transfer was finalized.
+    pub block_hash:  BlockHash,
+    pub sender:      AccountAddress,
+    pub receiver:    AccountAddress,
+    /// The releases of the transfer, ordered by increasing timestamp.
+    pub schedule:    Vec<(Timestamp, Amount)>,
+}
+
+/// Look up the scheduled transfers with the given hashes, for example the
+/// ones returned by [`ReleaseProjection::transactions`]. Transactions that
+/// are not finalized scheduled transfers are skipped.
+pub async fn scheduled_transfers(
+    client: &mut v2::Client,
+    transactions: impl IntoIterator<Item = TransactionHash>,
+) -> QueryResult<Vec<ScheduledTransfer>> {
+    let mut transfers = Vec::new();
+    for hash in transactions {
+        let status = client.get_block_item_status(&hash).await?;
+        let Some((block_hash, summary)) = status.is_finalized() else {
+            continue;
+        };
+        let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+            continue;
+        };
+        let (to, amount) = match &at.effects {
+            AccountTransactionEffects::TransferredWithSchedule { to, amount } => (to, amount),
+            AccountTransactionEffects::TransferredWithScheduleAndMemo { to, amount, .. } => {
+                (to, amount)
+            }
+            _ => continue,
+        };
+        transfers.push(ScheduledTransfer {
+            transaction: hash,
+            block_hash:  *block_hash,
+            sender:      at.sender,
+            receiver:    *to,
+            schedule:    amount.clone(),
+        });
+    }
+    Ok(transfers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{hashes::HashBytes, Release};
+    use chrono::TimeZone;
+
+    fn time(month: u32, day: u32) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.with_ymd_and_hms(2023, month, day, 12, 0, 0).unwrap()
+    }
+
+    fn schedule(releases: &[(chrono::DateTime<chrono::Utc>, u64, u8)]) -> AccountReleaseSchedule {
+        AccountReleaseSchedule {
+            total:    Amount::from_micro_ccd(releases.iter().map(|r| r.1).sum()),
+            schedule: releases
+                .iter()
+                .map(|&(timestamp, amount, tx)| Release {
+                    timestamp,
+                    amount: Amount::from_micro_ccd(amount),
+                    transactions: vec![HashBytes::new([tx; 32])],
+                })
+                .collect(),
+        }
+    }
+
+    const ALICE: AccountAddress = AccountAddress([1u8; 32]);
+    const BOB: AccountAddress = AccountAddress([2u8; 32]);
+
+    fn projection() -> ReleaseProjection {
+        let mut projection = ReleaseProjection::new();
+        projection.add_account(
+            ALICE,
+            &schedule(&[(time(1, 10), 100, 1), (time(2, 10), 200, 1), (time(3, 1), 50, 2)]),
+        );
+        // Accounts are added out of order of their releases.
+        projection.add_account(BOB, &schedule(&[(time(1, 10), 10, 3), (time(1, 20), 20, 3)]));
+        projection
+    }
+
+    #[test]
+    fn test_releases_ordered() {
+        let projection = projection();
+        let times = projection
+            .releases()
+            .map(|r| (r.timestamp, r.account))
+            .collect::<Vec<_>>();
+        assert_eq!(times, [
+            (time(1, 10), ALICE),
+            (time(1, 10), BOB),
+            (time(1, 20), BOB),
+            (time(2, 10), ALICE),
+            (time(3, 1), ALICE),
+        ]);
+        assert_eq!(projection.locked_at(time(1, 10)), Amount::from_micro_ccd(270));
+        assert_eq!(projection.locked_at(time(3, 1)), Amount::from_micro_ccd(0));
+    }
+
+    #[test]
+    fn test_unlocks_by_month() {
+        let unlocks = projection().unlocks(Granularity::Month, time(1, 15));
+        let summary = unlocks
+            .iter()
+            .map(|p| (p.start, p.amount.micro_ccd(), p.accounts.len(), p.transactions.len()))
+            .collect::<Vec<_>>();
+        // The releases on January 10 are before `from`.
+        assert_eq!(summary, [
+            (chrono::NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(), 20, 1, 1),
+            (chrono::NaiveDate::from_ymd_opt(2023, 2, 1).unwrap(), 200, 1, 1),
+            (chrono::NaiveDate::from_ymd_opt(2023, 3, 1).unwrap(), 50, 1, 1),
+        ]);
+    }
+
+    #[test]
+    fn test_unlocks_by_day() {
+        let unlocks = projection().unlocks(Granularity::Day, time(1, 1));
+        assert_eq!(unlocks.len(), 4);
+        let first = &unlocks[0];
+        assert_eq!(first.start, chrono::NaiveDate::from_ymd_opt(2023, 1, 10).unwrap());
+        assert_eq!(first.amount, Amount::from_micro_ccd(110));
+        assert_eq!(first.accounts, [ALICE, BOB].into_iter().collect());
+        assert_eq!(first.transactions.len(), 2);
+        assert!(projection().unlocks(Granularity::Day, time(4, 1)).is_empty());
+    }
+}
@@ -0,0 +1,519 @@
+//! Management of the shielded (encrypted) balance of an account.
+//!
+//! Decrypting encrypted amounts requires a precomputed table which is
+//! expensive to construct. The [`load_or_build_context`] function builds the
+//! table once and stores it on disk so that subsequent uses only need to read
+//! it.
+//!
+//! The [`ShieldedBalance`] tracks the encrypted balance of a single account. It
+//! remembers all the amounts it has already decrypted so that processing new
+//! blocks only requires decrypting the newly received amounts. It can also
+//! construct transfers from the shielded balance, selecting the correct
+//! aggregation index automatically.
+use crate::{
+    common::{self, types::TransactionTime},
+    encrypted_transfers::{
+        self,
+        types::{AggregatedDecryptedAmount, EncryptedAmount},
+    },
+    id::{constants::ArCurve, elgamal},
+    types::{
+        hashes::BlockHash,
+        transactions::{self, send, AccountTransaction, EncodedPayload},
+        AccountEncryptedAmount, AccountTransactionEffects, BlockItemSummary,
+        BlockItemSummaryDetails, CryptographicParameters, EncryptedAmountDecryptionContext,
+        MakeEncryptedTransferError, Memo, Nonce,
+    },
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::{common::types::Amount, contracts_common::AccountAddress};
+use futures::TryStreamExt;
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+/// The maximum number of incoming amounts an account can have before they are
+/// aggregated by the chain.
+const MAX_INCOMING_AMOUNTS: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when loading or storing a decryption table.
+pub enum DecryptionTableError {
+    #[error("Unable to access the table file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unable to parse the stored table.")]
+    Parse,
+}
+
+/// Load the decryption table from the given file, or construct it if the
+/// file does not exist. If the table is constructed it is stored in the file
+/// for future use.
+///
+/// The stored table is tagged with the generator it was constructed for. If
+/// the file contains a table for different cryptographic parameters then it
+/// is reconstructed and the file is overwritten.
+pub fn load_or_build_context<'a>(
+    params: &'a CryptographicParameters,
+    path: impl AsRef<Path>,
+) -> Result<EncryptedAmountDecryptionContext<'a>, DecryptionTableError> {
+    let path = path.as_ref();
+    let generator = params.encryption_in_exponent_generator();
+    if path.exists() {
+        let bytes = std::fs::read(path)?;
+        let mut source = std::io::Cursor::new(bytes);
+        let stored_generator: ArCurve =
+            common::from_bytes(&mut source).map_err(|_| DecryptionTableError::Parse)?;
+        if &stored_generator == generator {
+            let table = common::from_bytes(&mut source).map_err(|_| DecryptionTableError::Parse)?;
+            return Ok(EncryptedAmountDecryptionContext::from_table(params, table));
+        }
+    }
+    let ctx = EncryptedAmountDecryptionContext::new(params);
+    store_context(&ctx, path)?;
+    Ok(ctx)
+}
+
+/// Store the table of the decryption context in the given file, in the format
+/// expected by [`load_or_build_context`]. The table is first written to a
+/// temporary file next to it, which is then renamed, so that the file is never
+/// left partially written.
+pub fn store_context(
+    ctx: &EncryptedAmountDecryptionContext,
+    path: impl AsRef<Path>,
+) -> Result<(), DecryptionTableError> {
+    let path = path.as_ref();
+    let mut out = common::to_bytes(ctx.params().encryption_in_exponent_generator());
+    out.extend_from_slice(&common::to_bytes(ctx.table()));
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(&out)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when updating a [`ShieldedBalance`] or making
+/// transfers from it.
+pub enum ShieldedBalanceError {
+    #[error("Unable to query the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Unable to construct the transfer: {0}")]
+    Transfer(#[from] MakeEncryptedTransferError),
+    #[error("The shielded balance has not been synchronized with the chain.")]
+    NotSynchronized,
+}
+
+/// The state of the shielded balance of an account, together with a cache of
+/// already decrypted amounts.
+pub struct ShieldedBalance<'a> {
+    /// The account whose balance is tracked.
+    pub address:    AccountAddress,
+    /// The last block whose effects are reflected in the state.
+    pub last_block: Option<BlockHash>,
+    ctx:            EncryptedAmountDecryptionContext<'a>,
+    secret_key:     elgamal::SecretKey<ArCurve>,
+    /// The current encrypted balance of the account, as last observed.
+    state:          Option<AccountEncryptedAmount>,
+    /// Decrypted values of encrypted amounts. The key is the serialization of
+    /// the encrypted amount.
+    decrypted:      BTreeMap<Vec<u8>, Amount>,
+}
+
+impl<'a> ShieldedBalance<'a> {
+    /// Construct a new tracker for the account with the given address and
+    /// secret decryption key. The balance must be
+    /// [synchronized](Self::sync) before it can be used.
+    pub fn new(
+        address: AccountAddress,
+        ctx: EncryptedAmountDecryptionContext<'a>,
+        secret_key: elgamal::SecretKey<ArCurve>,
+    ) -> Self {
+        Self {
+            address,
+            ctx,
+            secret_key,
+            state: None,
+            decrypted: BTreeMap::new(),
+            last_block: None,
+        }
+    }
+
+    /// Decrypt the amount, using the cache if the amount was already
+    /// decrypted.
+    fn decrypt(&mut self, amount: &EncryptedAmount<ArCurve>) -> Amount {
+        let key = common::to_bytes(amount);
+        if let Some(&value) = self.decrypted.get(&key) {
+            return value;
+        }
+        let value =
+            encrypted_transfers::decrypt_amount(self.ctx.table(), &self.secret_key, amount);
+        self.decrypted.insert(key, value);
+        value
+    }
+
+    /// Query the encrypted balance of the account in the given block and
+    /// decrypt any amounts that have not yet been decrypted.
+    pub async fn sync(
+        &mut self,
+        client: &mut v2::Client,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Amount, ShieldedBalanceError> {
+        let ai = client.get_account_info(&self.address.into(), bi).await?;
+        self.state = Some(ai.response.account_encrypted_amount);
+        self.last_block = Some(ai.block_hash);
+        self.aggregate().map(|agg| agg.agg_amount)
+    }
+
+    /// Update the state with the effects of the given block. Only the
+    /// transactions that affect the encrypted balance of the tracked account
+    /// are considered, and only newly received amounts are decrypted.
+    ///
+    /// Blocks must be processed in order, e.g., by following
+    /// [`get_finalized_blocks_from`](v2::Client::get_finalized_blocks_from),
+    /// and the balance must be [synchronized](Self::sync) in the block
+    /// preceding the first processed block.
+    pub async fn process_block(
+        &mut self,
+        client: &mut v2::Client,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Amount, ShieldedBalanceError> {
+        let events = client.get_block_transaction_events(bi).await?;
+        let block_hash = events.block_hash;
+        let summaries = events
+            .response
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(v2::QueryError::from)?;
+        self.process_summaries(&summaries)?;
+        self.last_block = Some(block_hash);
+        self.aggregate().map(|agg| agg.agg_amount)
+    }
+
+    /// Update the state with the given transaction outcomes. See
+    /// [`process_block`](Self::process_block) for requirements.
+    pub fn process_summaries<'b>(
+        &mut self,
+        summaries: impl IntoIterator<Item = &'b BlockItemSummary>,
+    ) -> Result<(), ShieldedBalanceError> {
+        let Some(mut state) = self.state.take() else {
+            return Err(ShieldedBalanceError::NotSynchronized);
+        };
+        for summary in summaries {
+            let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+                continue;
+            };
+            match &at.effects {
+                AccountTransactionEffects::EncryptedAmountTransferred { removed, added }
+                | AccountTransactionEffects::EncryptedAmountTransferredWithMemo {
+                    removed,
+                    added,
+                    ..
+                } => {
+                    if removed.account.is_alias(&self.address) {
+                        remove_amounts(
+                            &mut state,
+                            removed.new_amount.clone(),
+                            removed.up_to_index.index,
+                        );
+                    }
+                    if added.receiver.is_alias(&self.address) {
+                        // Decrypt eagerly so that the balance is available immediately.
+                        self.decrypt(&added.encrypted_amount);
+                        add_incoming(
+                            &mut state,
+                            added.new_index.index,
+                            added.encrypted_amount.clone(),
+                        );
+                    }
+                }
+                AccountTransactionEffects::TransferredToEncrypted { data } => {
+                    if data.account.is_alias(&self.address) {
+                        state.self_amount = data.new_amount.clone();
+                    }
+                }
+                AccountTransactionEffects::TransferredToPublic { removed, .. } => {
+                    if removed.account.is_alias(&self.address) {
+                        remove_amounts(
+                            &mut state,
+                            removed.new_amount.clone(),
+                            removed.up_to_index.index,
+                        );
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.state = Some(state);
+        Ok(())
+    }
+
+    /// Combine all the encrypted amounts of the account. The result contains
+    /// the index up to which incoming amounts are used, which is needed when
+    /// making transfers.
+    pub fn aggregate(
+        &mut self,
+    ) -> Result<AggregatedDecryptedAmount<ArCurve>, ShieldedBalanceError> {
+        let Some(state) = self.state.take() else {
+            return Err(ShieldedBalanceError::NotSynchronized);
+        };
+        let mut combined = state.self_amount.clone();
+        let mut agg_amount = self.decrypt(&state.self_amount);
+        let mut index = state.start_index;
+        if let Some((agg, num_agg)) = state.aggregated_amount.as_ref() {
+            agg_amount += self.decrypt(agg);
+            combined = encrypted_transfers::aggregate(&combined, agg);
+            index += u64::from(*num_agg);
+        }
+        for amount in &state.incoming_amounts {
+            agg_amount += self.decrypt(amount);
+            combined = encrypted_transfers::aggregate(&combined, amount);
+            index += 1;
+        }
+        self.state = Some(state);
+        Ok(AggregatedDecryptedAmount {
+            agg_encrypted_amount: combined,
+            agg_amount,
+            agg_index: index.into(),
+        })
+    }
+
+    /// The total shielded balance of the account.
+    pub fn balance(&mut self) -> Result<Amount, ShieldedBalanceError> {
+        self.aggregate().map(|agg| agg.agg_amount)
+    }
+
+    /// Construct a transaction that transfers the entire shielded balance of
+    /// the account to its public balance.
+    pub fn unshield_all<R: rand::CryptoRng + rand::Rng>(
+        &mut self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        nonce: Nonce,
+        expiry: TransactionTime,
+        rng: &mut R,
+    ) -> Result<AccountTransaction<EncodedPayload>, ShieldedBalanceError> {
+        let agg = self.aggregate()?;
+        let amount = agg.agg_amount;
+        self.unshield_aggregated(signer, nonce, expiry, agg, amount, rng)
+    }
+
+    /// Construct a transaction that transfers the given amount from the
+    /// shielded balance of the account to its public balance.
+    pub fn unshield<R: rand::CryptoRng + rand::Rng>(
+        &mut self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        nonce: Nonce,
+        expiry: TransactionTime,
+        amount: Amount,
+        rng: &mut R,
+    ) -> Result<AccountTransaction<EncodedPayload>, ShieldedBalanceError> {
+        let agg = self.aggregate()?;
+        self.unshield_aggregated(signer, nonce, expiry, agg, amount, rng)
+    }
+
+    fn unshield_aggregated<R: rand::CryptoRng + rand::Rng>(
+        &self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        nonce: Nonce,
+        expiry: TransactionTime,
+        agg: AggregatedDecryptedAmount<ArCurve>,
+        amount: Amount,
+        rng: &mut R,
+    ) -> Result<AccountTransaction<EncodedPayload>, ShieldedBalanceError> {
+        if amount > agg.agg_amount {
+            return Err(MakeEncryptedTransferError::InsufficientAmount {
+                existing:  agg.agg_amount,
+                requested: amount,
+            }
+            .into());
+        }
+        let data = encrypted_transfers::make_sec_to_pub_transfer_data(
+            self.ctx.params(),
+            &self.secret_key,
+            &agg,
+            amount,
+            rng,
+        )
+        .ok_or(MakeEncryptedTransferError::FailedToProve)?;
+        Ok(send::transfer_to_public(signer, self.address, nonce, expiry, data))
+    }
+
+    /// Construct an encrypted transfer of the given amount to the receiver
+    /// with the given public key. If a memo is supplied the transfer includes
+    /// it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encrypted_transfer<R: rand::CryptoRng + rand::Rng>(
+        &mut self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        nonce: Nonce,
+        expiry: TransactionTime,
+        receiver: AccountAddress,
+        receiver_pk: &elgamal::PublicKey<ArCurve>,
+        amount: Amount,
+        memo: Option<Memo>,
+        rng: &mut R,
+    ) -> Result<AccountTransaction<EncodedPayload>, ShieldedBalanceError> {
+        let agg = self.aggregate()?;
+        if amount > agg.agg_amount {
+            return Err(MakeEncryptedTransferError::InsufficientAmount {
+                existing:  agg.agg_amount,
+                requested: amount,
+            }
+            .into());
+        }
+        let data = encrypted_transfers::make_transfer_data(
+            self.ctx.params(),
+            receiver_pk,
+            &self.secret_key,
+            &agg,
+            amount,
+            rng,
+        )
+        .ok_or(MakeEncryptedTransferError::FailedToProve)?;
+        let tx = match memo {
+            Some(memo) => send::encrypted_transfer_with_memo(
+                signer,
+                self.address,
+                nonce,
+                expiry,
+                receiver,
+                data,
+                memo,
+            ),
+            None => send::encrypted_transfer(signer, self.address, nonce, expiry, receiver, data),
+        };
+        Ok(tx)
+    }
+}
+
+/// Record that incoming amounts up to (but not including) `up_to_index` were
+/// consumed and that the new self amount is `new_amount`.
+fn remove_amounts(
+    state: &mut AccountEncryptedAmount,
+    new_amount: EncryptedAmount<ArCurve>,
+    up_to_index: u64,
+) {
+    let mut first_remaining = state.start_index;
+    if let Some((_, num_agg)) = state.aggregated_amount.as_ref() {
+        first_remaining += u64::from(*num_agg);
+    }
+    let num_used = up_to_index.saturating_sub(first_remaining) as usize;
+    let num_used = std::cmp::min(num_used, state.incoming_amounts.len());
+    state.incoming_amounts.drain(..num_used);
+    state.aggregated_amount = None;
+    state.start_index = up_to_index;
+    state.self_amount = new_amount;
+}
+
+/// Record a new incoming amount with the given index. If the maximum number of
+/// incoming amounts is exceeded the oldest ones are aggregated, mirroring what
+/// happens on the chain.
+fn add_incoming(state: &mut AccountEncryptedAmount, index: u64, amount: EncryptedAmount<ArCurve>) {
+    let mut next_index = state.start_index + state.incoming_amounts.len() as u64;
+    if let Some((_, num_agg)) = state.aggregated_amount.as_ref() {
+        next_index += u64::from(*num_agg);
+    }
+    if index < next_index {
+        // Already accounted for.
+        return;
+    }
+    state.incoming_amounts.push(amount);
+    if state.incoming_amounts.len() > MAX_INCOMING_AMOUNTS {
+        let oldest = state.incoming_amounts.remove(0);
+        state.aggregated_amount = Some(match state.aggregated_amount.take() {
+            Some((agg, num_agg)) => (encrypted_transfers::aggregate(&agg, &oldest), num_agg + 1),
+            None => {
+                let second = state.incoming_amounts.remove(0);
+                (encrypted_transfers::aggregate(&oldest, &second), 2)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(params: &CryptographicParameters, micro_ccd: u64) -> EncryptedAmount<ArCurve> {
+        encrypted_transfers::encrypt_amount_with_fixed_randomness(
+            params,
+            Amount::from_micro_ccd(micro_ccd),
+        )
+    }
+
+    fn bytes(amount: &EncryptedAmount<ArCurve>) -> Vec<u8> { common::to_bytes(amount) }
+
+    /// The state after incoming amounts with indices `0..n`, where the amount
+    /// with index `i` is `i` micro CCD.
+    fn state_with_incoming(params: &CryptographicParameters, n: u64) -> AccountEncryptedAmount {
+        let mut state = AccountEncryptedAmount {
+            self_amount:       amount(params, 0),
+            start_index:       0,
+            aggregated_amount: None,
+            incoming_amounts:  Vec::new(),
+        };
+        for index in 0..n {
+            add_incoming(&mut state, index, amount(params, index));
+        }
+        state
+    }
+
+    #[test]
+    fn test_add_incoming() {
+        let params = CryptographicParameters::generate("shielded tests".into());
+        let mut state = state_with_incoming(&params, MAX_INCOMING_AMOUNTS as u64);
+        assert!(state.aggregated_amount.is_none());
+        assert_eq!(state.incoming_amounts.len(), MAX_INCOMING_AMOUNTS);
+        // Amounts that are already accounted for are ignored.
+        add_incoming(&mut state, 5, amount(&params, 100));
+        assert_eq!(state.incoming_amounts.len(), MAX_INCOMING_AMOUNTS);
+        assert_eq!(bytes(&state.incoming_amounts[5]), bytes(&amount(&params, 5)));
+
+        // The two oldest amounts are aggregated when the maximum is exceeded.
+        add_incoming(&mut state, 32, amount(&params, 32));
+        let (agg, num_agg) = state.aggregated_amount.as_ref().unwrap();
+        assert_eq!(*num_agg, 2);
+        let expected = encrypted_transfers::aggregate(&amount(&params, 0), &amount(&params, 1));
+        assert_eq!(bytes(agg), bytes(&expected));
+        assert_eq!(state.incoming_amounts.len(), MAX_INCOMING_AMOUNTS - 1);
+        assert_eq!(bytes(&state.incoming_amounts[0]), bytes(&amount(&params, 2)));
+
+        // The list is filled up again, after which the oldest amount is added to
+        // the aggregate.
+        add_incoming(&mut state, 33, amount(&params, 33));
+        assert_eq!(state.incoming_amounts.len(), MAX_INCOMING_AMOUNTS);
+        assert_eq!(state.aggregated_amount.as_ref().unwrap().1, 2);
+        add_incoming(&mut state, 34, amount(&params, 34));
+        let (agg, num_agg) = state.aggregated_amount.as_ref().unwrap();
+        assert_eq!(*num_agg, 3);
+        let expected = encrypted_transfers::aggregate(&expected, &amount(&params, 2));
+        assert_eq!(bytes(agg), bytes(&expected));
+        assert_eq!(state.incoming_amounts.len(), MAX_INCOMING_AMOUNTS);
+        assert_eq!(bytes(&state.incoming_amounts[0]), bytes(&amount(&params, 3)));
+        assert_eq!(state.start_index, 0);
+    }
+
+    #[test]
+    fn test_remove_amounts() {
+        let params = CryptographicParameters::generate("shielded tests".into());
+        // The aggregated amount covers indices 0 to 2, and the incoming amounts
+        // indices 3 to 34.
+        let mut state = state_with_incoming(&params, 35);
+        remove_amounts(&mut state, amount(&params, 1000), 10);
+        assert_eq!(state.start_index, 10);
+        assert!(state.aggregated_amount.is_none());
+        assert_eq!(bytes(&state.self_amount), bytes(&amount(&params, 1000)));
+        assert_eq!(state.incoming_amounts.len(), 25);
+        assert_eq!(bytes(&state.incoming_amounts[0]), bytes(&amount(&params, 10)));
+        // New amounts continue after the remaining ones.
+        add_incoming(&mut state, 34, amount(&params, 100));
+        assert_eq!(state.incoming_amounts.len(), 25);
+        add_incoming(&mut state, 35, amount(&params, 35));
+        assert_eq!(state.incoming_amounts.len(), 26);
+
+        // Using all amounts leaves no incoming amounts.
+        let mut state = state_with_incoming(&params, 5);
+        remove_amounts(&mut state, amount(&params, 0), 5);
+        assert_eq!(state.start_index, 5);
+        assert!(state.incoming_amounts.is_empty());
+    }
+}
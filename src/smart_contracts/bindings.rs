@@ -0,0 +1,804 @@
+//! Generation of typed contract clients from schemas.
+//!
+//! [`generate_bindings`] emits the Rust source of a client for one contract of
+//! a module, with a `view_*`, `dry_run_*` and `update_*` method for each
+//! entrypoint on top of a [`ContractClient`]. The parameters, return values,
+//! errors and events described by the schema are mapped to generated Rust
+//! types deriving `Serial` and `Deserial`, as well as the comparison traits so
+//! that they can be used in sets and as map keys. Types that have no Rust
+//! counterpart, such as LEB128 integers and enums with explicit tags, are
+//! handled as JSON. The schema is then included in the generated code, since
+//! modules used with a schema file need not have a schema embedded, and the
+//! generated code requires `serde_json` as a dependency.
+//!
+//! The generator is intended to be called from a build script, for example
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use concordium_rust_sdk::smart_contracts::bindings;
+//! let out_dir = std::env::var("OUT_DIR")?;
+//! let code = bindings::bindings_from_module_file("contracts/auction.wasm.v1", "auction")?;
+//! std::fs::write(format!("{out_dir}/auction.rs"), code)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! after which the client can be included in a module of its own with
+//! `include!(concat!(env!("OUT_DIR"), "/auction.rs"));`.
+//!
+//! [`ContractClient`]: crate::contract_client::ContractClient
+use super::deploy::{read_module, DeployError};
+use crate::contract_client::{embedded_schema, SchemaError};
+use concordium_base::contracts_common::{
+    self,
+    schema::{Fields, SizeLength, Type, VersionedModuleSchema},
+};
+use std::{collections::BTreeSet, path::Path};
+
+/// The path of the SDK in the generated code.
+const SDK: &str = "concordium_rust_sdk";
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when generating bindings.
+pub enum BindingsError {
+    #[error("The schema does not contain the contract {0}.")]
+    UnknownContract(String),
+    #[error("Unable to load the schema: {0}")]
+    Schema(#[from] SchemaError),
+    #[error("Unable to read the module: {0}")]
+    Module(#[from] DeployError),
+    #[error("Unable to read the schema: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Generate bindings for the contract using the schema embedded in a module
+/// file, such as the `.wasm.v1` files produced by `cargo concordium build`.
+/// The contract name is without the `init_` prefix.
+pub fn bindings_from_module_file(
+    path: impl AsRef<Path>,
+    contract_name: &str,
+) -> Result<String, BindingsError> {
+    let module = read_module(path)?;
+    let schema = embedded_schema(&module)?;
+    generate_bindings(&schema, contract_name)
+}
+
+/// Generate bindings for the contract using a schema file, such as the ones
+/// produced by `cargo concordium build --schema-out`.
+pub fn bindings_from_schema_file(
+    path: impl AsRef<Path>,
+    contract_name: &str,
+) -> Result<String, BindingsError> {
+    let bytes = std::fs::read(path)?;
+    let schema: VersionedModuleSchema =
+        contracts_common::from_bytes(&bytes).map_err(SchemaError::from)?;
+    generate_bindings(&schema, contract_name)
+}
+
+/// The names of the entrypoints of the contract in the schema.
+fn entrypoints(schema: &VersionedModuleSchema, contract_name: &str) -> Option<Vec<String>> {
+    let names = match schema {
+        VersionedModuleSchema::V0(module) => {
+            module.contracts.get(contract_name)?.receive.keys().cloned().collect()
+        }
+        VersionedModuleSchema::V1(module) => {
+            module.contracts.get(contract_name)?.receive.keys().cloned().collect()
+        }
+        VersionedModuleSchema::V2(module) => {
+            module.contracts.get(contract_name)?.receive.keys().cloned().collect()
+        }
+        VersionedModuleSchema::V3(module) => {
+            module.contracts.get(contract_name)?.receive.keys().cloned().collect()
+        }
+    };
+    Some(names)
+}
+
+/// How a value described by the schema is represented in the generated code.
+enum Repr {
+    /// As the given Rust type.
+    Typed(String),
+    /// As JSON, encoded and decoded using the schema.
+    Json,
+}
+
+/// Convert a name to `snake_case`, replacing characters that cannot appear in
+/// identifiers.
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && prev_lower {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+            prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else {
+            if !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            prev_lower = false;
+        }
+    }
+    let out = out.trim_end_matches('_');
+    match out.chars().next() {
+        None => "unnamed".into(),
+        Some(c) if c.is_ascii_digit() => format!("_{out}"),
+        Some(_) => out.into(),
+    }
+}
+
+/// Convert a name to `CamelCase`, replacing characters that cannot appear in
+/// identifiers.
+fn camel_case(name: &str) -> String {
+    let mut out = String::new();
+    for part in name.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            out.push(first.to_ascii_uppercase());
+            out.extend(chars);
+        }
+    }
+    match out.chars().next() {
+        None => "Unnamed".into(),
+        Some(c) if c.is_ascii_digit() => format!("V{out}"),
+        Some(_) => out,
+    }
+}
+
+/// Turn a `snake_case` name into an identifier, escaping keywords.
+fn identifier(name: String) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use",
+        "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro", "override",
+        "priv", "try", "typeof", "unsized", "virtual", "yield",
+    ];
+    match name.as_str() {
+        "self" | "super" | "crate" => format!("{name}_"),
+        _ if KEYWORDS.contains(&name.as_str()) => format!("r#{name}"),
+        _ => name,
+    }
+}
+
+/// Append a number to the name until it is not in `used`, and mark it as
+/// used.
+fn unique(used: &mut BTreeSet<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut n = 1;
+    while used.contains(&candidate) {
+        n += 1;
+        candidate = format!("{name}{n}");
+    }
+    used.insert(candidate.clone());
+    candidate
+}
+
+/// The number of bytes of a length prefix, for the `size_length` attribute.
+fn size_bytes(size: &SizeLength) -> u8 {
+    match size {
+        SizeLength::U8 => 1,
+        SizeLength::U16 => 2,
+        SizeLength::U32 => 4,
+        SizeLength::U64 => 8,
+    }
+}
+
+/// The length prefix of a type, for types whose Rust counterpart can be given
+/// a different length prefix with the `size_length` attribute.
+fn size_length(ty: &Type) -> Option<&SizeLength> {
+    match ty {
+        Type::List(size, _)
+        | Type::Set(size, _)
+        | Type::Map(size, _, _)
+        | Type::String(size)
+        | Type::ByteList(size) => Some(size),
+        _ => None,
+    }
+}
+
+/// Emits Rust types for schema types.
+#[derive(Default)]
+struct TypeGenerator {
+    /// The type definitions emitted so far.
+    definitions: String,
+    /// The names of the types defined so far.
+    names:       BTreeSet<String>,
+}
+
+impl TypeGenerator {
+    /// The representation of the schema type. Structs and enums are defined
+    /// using the given name. If the type, or any type it contains, has no
+    /// Rust counterpart, no definitions are emitted and the value is
+    /// represented as JSON.
+    fn repr(&mut self, ty: &Type, name: &str) -> Repr {
+        let (len, names) = (self.definitions.len(), self.names.clone());
+        match self.rust_type(ty, name, false) {
+            Some(ty) => Repr::Typed(ty),
+            None => {
+                self.definitions.truncate(len);
+                self.names = names;
+                Repr::Json
+            }
+        }
+    }
+
+    /// The Rust type of the schema type. Types with a length prefix other
+    /// than four bytes are only supported as fields, where the prefix can be
+    /// set with the `size_length` attribute.
+    fn rust_type(&mut self, ty: &Type, name: &str, field: bool) -> Option<String> {
+        if let Some(size) = size_length(ty) {
+            if !matches!(size, SizeLength::U32) && !field {
+                return None;
+            }
+        }
+        let ty = match ty {
+            Type::Unit => "()".into(),
+            Type::Bool => "bool".into(),
+            Type::U8 => "u8".into(),
+            Type::U16 => "u16".into(),
+            Type::U32 => "u32".into(),
+            Type::U64 => "u64".into(),
+            Type::U128 => "u128".into(),
+            Type::I8 => "i8".into(),
+            Type::I16 => "i16".into(),
+            Type::I32 => "i32".into(),
+            Type::I64 => "i64".into(),
+            Type::I128 => "i128".into(),
+            Type::Amount => "concordium_std::Amount".into(),
+            Type::AccountAddress => "concordium_std::AccountAddress".into(),
+            Type::ContractAddress => "concordium_std::ContractAddress".into(),
+            Type::Timestamp => "concordium_std::Timestamp".into(),
+            Type::Duration => "concordium_std::Duration".into(),
+            Type::Pair(first, second) => {
+                let first = self.rust_type(first, &format!("{name}First"), false)?;
+                let second = self.rust_type(second, &format!("{name}Second"), false)?;
+                format!("({first}, {second})")
+            }
+            Type::List(_, item) => {
+                format!("Vec<{}>", self.rust_type(item, &format!("{name}Item"), false)?)
+            }
+            Type::Set(_, item) => format!(
+                "std::collections::BTreeSet<{}>",
+                self.rust_type(item, &format!("{name}Item"), false)?
+            ),
+            Type::Map(_, key, value) => format!(
+                "std::collections::BTreeMap<{}, {}>",
+                self.rust_type(key, &format!("{name}Key"), false)?,
+                self.rust_type(value, &format!("{name}Value"), false)?
+            ),
+            Type::Array(len, item) => {
+                format!("[{}; {len}]", self.rust_type(item, &format!("{name}Item"), false)?)
+            }
+            Type::String(_) => "String".into(),
+            Type::ByteList(_) => "Vec<u8>".into(),
+            Type::ByteArray(len) => format!("[u8; {len}]"),
+            Type::ContractName(SizeLength::U16) => "concordium_std::OwnedContractName".into(),
+            Type::ReceiveName(SizeLength::U16) => "concordium_std::OwnedReceiveName".into(),
+            Type::Struct(fields) => {
+                let name = unique(&mut self.names, camel_case(name));
+                let body = self.fields(fields, &name, "pub ")?;
+                let end = if matches!(fields, Fields::Named(_)) { "" } else { ";" };
+                self.define(&format!("pub struct {name}{body}{end}"));
+                name
+            }
+            Type::Enum(variants) => {
+                let name = unique(&mut self.names, camel_case(name));
+                let mut used = BTreeSet::new();
+                let mut body = String::new();
+                for (variant, fields) in variants {
+                    let variant = unique(&mut used, camel_case(variant));
+                    let variant_fields = self.fields(fields, &format!("{name}{variant}"), "")?;
+                    body.push_str(&format!("    {variant}{variant_fields},\n"));
+                }
+                self.define(&format!("pub enum {name} {{\n{body}}}"));
+                name
+            }
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    /// The fields of a struct or enum variant, or [`None`] if the type has no
+    /// Rust counterpart. The fields are prefixed with `vis`.
+    fn fields(&mut self, fields: &Fields, name: &str, vis: &str) -> Option<String> {
+        // The `size_length` attribute, if needed, and the type of a field.
+        let mut field = |ty: &Type, name: String| -> Option<(String, String)> {
+            let rust_ty = self.rust_type(ty, &name, true)?;
+            let attr = match size_length(ty) {
+                Some(size) if !matches!(size, SizeLength::U32) => {
+                    format!("#[concordium(size_length = {})] ", size_bytes(size))
+                }
+                _ => String::new(),
+            };
+            Some((attr, rust_ty))
+        };
+        let body = match fields {
+            Fields::Named(named) => {
+                let mut used = BTreeSet::new();
+                let mut body = String::new();
+                for (field_name, ty) in named {
+                    let ident = identifier(unique(&mut used, snake_case(field_name)));
+                    let (attr, rust_ty) = field(ty, format!("{name}{}", camel_case(field_name)))?;
+                    body.push_str(&format!("    {attr}{vis}{ident}: {rust_ty},\n"));
+                }
+                format!(" {{\n{body}}}")
+            }
+            Fields::Unnamed(unnamed) => {
+                let mut types = Vec::new();
+                for (i, ty) in unnamed.iter().enumerate() {
+                    let (attr, rust_ty) = field(ty, format!("{name}{i}"))?;
+                    types.push(format!("{attr}{vis}{rust_ty}"));
+                }
+                format!("({})", types.join(", "))
+            }
+            Fields::None => String::new(),
+        };
+        Some(body)
+    }
+
+    fn define(&mut self, definition: &str) {
+        self.definitions.push_str(
+            "#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, concordium_std::Serial, \
+             concordium_std::Deserial)]\n",
+        );
+        self.definitions.push_str(definition);
+        self.definitions.push_str("\n\n");
+    }
+}
+
+/// Format bytes as the elements of an array literal, sixteen per line.
+fn byte_lines(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .map(|chunk| {
+            let items = chunk.iter().map(|b| format!("{b:#04x},")).collect::<Vec<_>>();
+            format!("        {}", items.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The methods of the generated client for an entrypoint.
+fn entrypoint_methods(
+    types: &mut TypeGenerator,
+    schema: &VersionedModuleSchema,
+    contract_name: &str,
+    entrypoint: &str,
+    method: &str,
+) -> (String, bool) {
+    let type_name = camel_case(entrypoint);
+    let param = schema
+        .get_receive_param_schema(contract_name, entrypoint)
+        .ok()
+        .map(|ty| types.repr(&ty, &format!("{type_name}Parameter")));
+    let return_value = schema
+        .get_receive_return_value_schema(contract_name, entrypoint)
+        .ok()
+        .map(|ty| types.repr(&ty, &format!("{type_name}ReturnValue")));
+    let error = schema
+        .get_receive_error_schema(contract_name, entrypoint)
+        .ok()
+        .map(|ty| types.repr(&ty, &format!("{type_name}Error")));
+    let uses_json = [&param, &return_value, &error]
+        .iter()
+        .any(|repr| matches!(repr, Some(Repr::Json)));
+
+    let lit = format!("{entrypoint:?}");
+    // The argument of the methods, and the statement that serializes it, if
+    // it is not already serialized.
+    let (param_arg, param_let) = match &param {
+        Some(Repr::Typed(ty)) => (
+            format!("parameter: &{ty}"),
+            format!(
+                "        let parameter =
+            {SDK}::types::smart_contracts::OwnedParameter::from_serial(parameter)?;
+"
+            ),
+        ),
+        Some(Repr::Json) => (
+            "parameter: &serde_json::Value".into(),
+            format!("        let parameter = self.client.encode_parameter({lit}, parameter)?;\n"),
+        ),
+        None => (
+            format!("parameter: {SDK}::types::smart_contracts::OwnedParameter"),
+            String::new(),
+        ),
+    };
+
+    let mut out = String::new();
+    if let Some(return_value) = &return_value {
+        let (ty, expr) = match return_value {
+            Repr::Typed(ty) => (
+                ty.clone(),
+                format!("{SDK}::contract_client::parse_view_result(result)"),
+            ),
+            Repr::Json => (
+                "serde_json::Value".into(),
+                format!("self.client.parse_view_result_json({lit}, result)"),
+            ),
+        };
+        out.push_str(&format!(
+            "    /// Invoke the `{entrypoint}` entrypoint and decode its return value.
+    pub async fn view_{method}(
+        &mut self,
+        {param_arg},
+        bi: impl {SDK}::v2::IntoBlockIdentifier,
+    ) -> Result<{ty}, {SDK}::contract_client::SchemaError> {{
+{param_let}        let result = self
+            .client
+            .invoke_raw::<{SDK}::contract_client::SchemaError>(
+                {lit},
+                concordium_std::Amount::zero(),
+                None,
+                parameter,
+                bi,
+            )
+            .await?;
+        {expr}
+    }}
+
+"
+        ));
+    }
+    out.push_str(&format!(
+        "    /// Invoke the `{entrypoint}` entrypoint without sending a transaction.
+    pub async fn dry_run_{method}(
+        &mut self,
+        invoker: Option<concordium_std::Address>,
+        amount: concordium_std::Amount,
+        {param_arg},
+        bi: impl {SDK}::v2::IntoBlockIdentifier,
+    ) -> Result<
+        {SDK}::types::smart_contracts::InvokeContractResult,
+        {SDK}::contract_client::SchemaError,
+    > {{
+{param_let}        self.client.invoke_raw({lit}, amount, invoker, parameter, bi).await
+    }}
+
+    /// Send a transaction to the `{entrypoint}` entrypoint.
+    pub async fn update_{method}(
+        &mut self,
+        signer: &impl {SDK}::types::transactions::ExactSizeTransactionSigner,
+        metadata: &{SDK}::contract_client::ContractTransactionMetadata,
+        {param_arg},
+    ) -> Result<{SDK}::types::hashes::TransactionHash, {SDK}::contract_client::SchemaError> {{
+{param_let}        self.client.update_raw(signer, metadata, {lit}, parameter).await
+    }}
+
+"
+    ));
+    match &error {
+        Some(Repr::Typed(ty)) => out.push_str(&format!(
+            "    /// Decode the error returned when the `{entrypoint}` entrypoint rejects.
+    pub fn decode_{method}_error(
+        &self,
+        return_value: &[u8],
+    ) -> concordium_std::ParseResult<{ty}> {{
+        concordium_std::from_bytes(return_value)
+    }}
+
+"
+        )),
+        Some(Repr::Json) => out.push_str(&format!(
+            "    /// Decode the error returned when the `{entrypoint}` entrypoint rejects.
+    pub fn decode_{method}_error(
+        &self,
+        return_value: &[u8],
+    ) -> Result<serde_json::Value, {SDK}::contract_client::SchemaError> {{
+        self.client.decode_error({lit}, return_value)
+    }}
+
+"
+        )),
+        None => (),
+    }
+    (out, uses_json)
+}
+
+/// Generate the Rust source of a typed client for the contract. The contract
+/// name is without the `init_` prefix.
+///
+/// The generated code defines a client `<Name>Contract`, where `<Name>` is the
+/// contract name in `CamelCase`, wrapping a [`ContractClient`] with the
+/// marker type `<Name>Type`. For each entrypoint `e` it has
+/// - `view_e`, which invokes the entrypoint and decodes its return value, if
+///   the schema has a return value type for it,
+/// - `dry_run_e`, which invokes the entrypoint with a given invoker and
+///   amount and returns the raw result,
+/// - `update_e`, which sends a transaction to the entrypoint, and
+/// - `decode_e_error`, which decodes the error returned when the entrypoint
+///   rejects, if the schema has an error type for it.
+///
+/// If the schema has an event type for the contract, the client also has a
+/// `decode_events` function. Entrypoints without a parameter type take a
+/// serialized parameter.
+///
+/// [`ContractClient`]: crate::contract_client::ContractClient
+pub fn generate_bindings(
+    schema: &VersionedModuleSchema,
+    contract_name: &str,
+) -> Result<String, BindingsError> {
+    let entrypoints = entrypoints(schema, contract_name)
+        .ok_or_else(|| BindingsError::UnknownContract(contract_name.into()))?;
+    let name = format!("{}Contract", camel_case(contract_name));
+    let marker = format!("{}Type", camel_case(contract_name));
+    let mut types = TypeGenerator::default();
+    types.names.extend([name.clone(), marker.clone()]);
+
+    let mut methods = String::new();
+    let mut uses_json = false;
+    let mut method_names = BTreeSet::new();
+    for entrypoint in &entrypoints {
+        let method = unique(&mut method_names, snake_case(entrypoint));
+        let (code, json) =
+            entrypoint_methods(&mut types, schema, contract_name, entrypoint, &method);
+        methods.push_str(&code);
+        uses_json |= json;
+    }
+
+    match schema
+        .get_event_schema(contract_name)
+        .ok()
+        .map(|ty| types.repr(&ty, &format!("{}Event", camel_case(contract_name))))
+    {
+        Some(Repr::Typed(ty)) => methods.push_str(&format!(
+            "    /// Decode events logged by the contract.
+    pub fn decode_events(
+        &self,
+        events: &[{SDK}::types::smart_contracts::ContractEvent],
+    ) -> concordium_std::ParseResult<Vec<{ty}>> {{
+        events.iter().map(|event| concordium_std::from_bytes(event.as_ref())).collect()
+    }}
+"
+        )),
+        Some(Repr::Json) => {
+            uses_json = true;
+            methods.push_str(&format!(
+                "    /// Decode events logged by the contract.
+    pub fn decode_events(
+        &self,
+        events: &[{SDK}::types::smart_contracts::ContractEvent],
+    ) -> Result<Vec<serde_json::Value>, {SDK}::contract_client::SchemaError> {{
+        self.client.decode_events(events)
+    }}
+"
+            ))
+        }
+        None => (),
+    }
+
+    // The schema is only needed for values that are handled as JSON.
+    let (schema_const, with_schema) = if uses_json {
+        let bytes = contracts_common::to_bytes(schema);
+        (
+            format!(
+                "    /// The schema the bindings were generated from, used for the values that are
+    /// handled as JSON.
+    const SCHEMA: &'static [u8] = &[
+{}
+    ];
+
+",
+                byte_lines(&bytes)
+            ),
+            "        let client = client.with_schema(concordium_std::from_bytes(Self::SCHEMA)?);\n",
+        )
+    } else {
+        (String::new(), "")
+    };
+
+    Ok(format!(
+        "// Bindings for the `{contract_name}` contract, generated from its schema by
+// `{SDK}::smart_contracts::bindings`. Do not edit.
+
+#[allow(unused_imports)]
+use {SDK}::smart_contracts::common as concordium_std;
+
+{definitions}/// Marker type of [`{name}`].
+#[derive(Debug, Clone, Copy)]
+pub enum {marker} {{}}
+
+/// A client for instances of the `{contract_name}` contract.
+#[derive(Debug, Clone)]
+pub struct {name} {{
+    pub client: {SDK}::contract_client::ContractClient<{marker}>,
+}}
+
+impl {name} {{
+{schema_const}    /// Construct a client for the instance at the address.
+    pub async fn create(
+        client: {SDK}::v2::Client,
+        address: concordium_std::ContractAddress,
+    ) -> Result<Self, {SDK}::contract_client::SchemaError> {{
+        let client = {SDK}::contract_client::ContractClient::create(client, address).await?;
+{with_schema}        Ok(Self {{ client }})
+    }}
+
+{methods}}}
+",
+        definitions = types.definitions,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_base::contracts_common::schema::{ContractV3, FunctionV2, ModuleV3};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_name_mangling() {
+        assert_eq!(snake_case("balanceOf"), "balance_of");
+        assert_eq!(snake_case("set-operators"), "set_operators");
+        assert_eq!(snake_case("tokenID"), "token_id");
+        assert_eq!(snake_case("view.x_"), "view_x");
+        assert_eq!(snake_case("2fa"), "_2fa");
+        assert_eq!(snake_case("-"), "unnamed");
+        assert_eq!(camel_case("balance_of"), "BalanceOf");
+        assert_eq!(camel_case("set-operators"), "SetOperators");
+        assert_eq!(camel_case("1st"), "V1st");
+        assert_eq!(camel_case(""), "Unnamed");
+        assert_eq!(identifier("type".into()), "r#type");
+        assert_eq!(identifier("self".into()), "self_");
+        assert_eq!(identifier("amount".into()), "amount");
+        let mut used = BTreeSet::new();
+        assert_eq!(unique(&mut used, "a".into()), "a");
+        assert_eq!(unique(&mut used, "a".into()), "a2");
+        assert_eq!(unique(&mut used, "a".into()), "a3");
+    }
+
+    fn function(
+        parameter: Option<Type>,
+        return_value: Option<Type>,
+        error: Option<Type>,
+    ) -> FunctionV2 {
+        FunctionV2 {
+            parameter,
+            return_value,
+            error,
+        }
+    }
+
+    fn schema() -> VersionedModuleSchema {
+        let transfer = Type::Struct(Fields::Named(vec![
+            ("tokenId".into(), Type::ByteList(SizeLength::U8)),
+            ("amount".into(), Type::U64),
+            ("to".into(), Type::AccountAddress),
+            ("data".into(), Type::List(SizeLength::U16, Box::new(Type::U8))),
+        ]));
+        let operator = Type::Struct(Fields::Unnamed(vec![Type::AccountAddress, Type::Bool]));
+        let key = Type::Enum(vec![
+            ("A".into(), Fields::None),
+            ("B".into(), Fields::Unnamed(vec![Type::U8])),
+        ]);
+        let error = Type::Enum(vec![
+            ("Unauthorized".into(), Fields::None),
+            ("InsufficientFunds".into(), Fields::Named(vec![("needed".into(), Type::Amount)])),
+        ]);
+        let event = Type::Enum(vec![(
+            "Minted".into(),
+            Fields::Named(vec![
+                ("owner".into(), Type::AccountAddress),
+                ("self".into(), Type::U8),
+            ]),
+        )]);
+        let receive = BTreeMap::from([
+            ("transfer".to_string(), function(Some(transfer), None, Some(error.clone()))),
+            (
+                "setOperators".to_string(),
+                function(Some(Type::Set(SizeLength::U32, Box::new(operator))), None, None),
+            ),
+            (
+                // A list with a one byte length prefix has no Rust counterpart
+                // outside of a struct, so the parameter is JSON.
+                "balanceOf".to_string(),
+                function(
+                    Some(Type::List(SizeLength::U8, Box::new(Type::ContractAddress))),
+                    Some(Type::List(SizeLength::U32, Box::new(Type::U64))),
+                    None,
+                ),
+            ),
+            (
+                "view".to_string(),
+                function(
+                    None,
+                    Some(Type::Map(
+                        SizeLength::U32,
+                        Box::new(key),
+                        Box::new(Type::String(SizeLength::U32)),
+                    )),
+                    None,
+                ),
+            ),
+            ("type".to_string(), function(Some(Type::ULeb128(5)), None, Some(error))),
+        ]);
+        let contract = ContractV3 {
+            init: None,
+            receive,
+            event: Some(event),
+        };
+        VersionedModuleSchema::V3(ModuleV3 {
+            contracts: BTreeMap::from([("token".to_string(), contract)]),
+        })
+    }
+
+    /// The schema of the bindings in `tests/bindings/counter.rs`, which are
+    /// compiled and used by the `bindings` integration test.
+    fn counter_schema() -> VersionedModuleSchema {
+        let increment = Type::Struct(Fields::Named(vec![("by".into(), Type::U64)]));
+        let error = Type::Enum(vec![("Overflow".into(), Fields::None)]);
+        let event = Type::Enum(vec![(
+            "Incremented".into(),
+            Fields::Named(vec![
+                ("by".into(), Type::U64),
+                ("owner".into(), Type::AccountAddress),
+            ]),
+        )]);
+        let receive = BTreeMap::from([
+            ("get".to_string(), function(None, Some(Type::U64), None)),
+            ("increment".to_string(), function(Some(increment), None, Some(error))),
+        ]);
+        let contract = ContractV3 {
+            init: None,
+            receive,
+            event: Some(event),
+        };
+        VersionedModuleSchema::V3(ModuleV3 {
+            contracts: BTreeMap::from([("counter".to_string(), contract)]),
+        })
+    }
+
+    #[test]
+    fn test_generate_counter_bindings() {
+        let code = generate_bindings(&counter_schema(), "counter").unwrap();
+        assert_eq!(code, include_str!("../../tests/bindings/counter.rs"));
+    }
+
+    #[test]
+    fn test_generate_bindings() {
+        let code = generate_bindings(&schema(), "token").expect("The contract is in the schema.");
+        if let Err(e) = syn::parse_file(&code) {
+            panic!("The generated code does not parse: {e}\n{code}");
+        }
+        let expected = [
+            "pub struct TokenContract {",
+            // Fields with other length prefixes than four bytes.
+            "#[concordium(size_length = 1)] pub token_id: Vec<u8>,",
+            "#[concordium(size_length = 2)] pub data: Vec<u8>,",
+            "pub struct SetOperatorsParameterItem(pub concordium_std::AccountAddress, pub bool);",
+            "parameter: &std::collections::BTreeSet<SetOperatorsParameterItem>,",
+            "pub enum ViewReturnValueKey {",
+            "-> Result<std::collections::BTreeMap<ViewReturnValueKey, String>,",
+            "pub async fn dry_run_view(",
+            "parameter: concordium_rust_sdk::types::smart_contracts::OwnedParameter,",
+            // The JSON fallback.
+            "pub async fn view_balance_of(",
+            "parameter: &serde_json::Value,",
+            "self.client.encode_parameter(\"balanceOf\", parameter)?",
+            "const SCHEMA: &'static [u8] = &[",
+            "client.with_schema(concordium_std::from_bytes(Self::SCHEMA)?)",
+            "pub async fn update_type(",
+            // Errors and events.
+            "pub enum TransferError {",
+            "InsufficientFunds {",
+            "pub fn decode_transfer_error(\n        &self,",
+            "pub fn decode_type_error(\n        &self,",
+            "pub enum TokenEvent {",
+            "self_: u8,",
+            "pub fn decode_events(\n        &self,",
+        ];
+        for snippet in expected {
+            assert!(code.contains(snippet), "Missing `{snippet}` in\n{code}");
+        }
+        // Methods that need a return value schema.
+        assert!(!code.contains("fn view_transfer("));
+        assert!(!code.contains("fn view_set_operators("));
+        assert!(matches!(
+            generate_bindings(&schema(), "other"),
+            Err(BindingsError::UnknownContract(_))
+        ));
+    }
+}
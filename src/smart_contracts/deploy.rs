@@ -0,0 +1,277 @@
+//! Deployment of smart contract modules and initialization of instances.
+//!
+//! [`deploy_module`] deploys a module, such as one read from a `.wasm.v1`
+//! file produced by `cargo concordium build` using [`read_module`], unless it
+//! is already deployed. [`init_contract`] then creates an instance of a
+//! contract in the module. If no energy limit is given, the energy needed is
+//! estimated by executing the init function locally before the transaction is
+//! sent. [`deploy_and_init`] combines the two, and returns a
+//! [`ContractClient`] for the new instance.
+use super::local::{dry_run_init, slot_time, InitOutcome};
+use crate::{
+    contract_client::ContractClient,
+    types::{
+        hashes::TransactionHash,
+        smart_contracts::{ModuleReference, OwnedContractName, OwnedParameter, WasmModule},
+        transactions::{send, InitContractPayload},
+        ContractAddress, Energy, RejectReason, WalletAccount,
+    },
+    v2::{self, BlockIdentifier},
+};
+use concordium_base::{
+    common::{
+        self,
+        types::{Amount, TransactionTime},
+    },
+    contracts_common::AccountAddress,
+};
+use std::path::Path;
+
+/// The energy limit used when estimating the energy of an init function.
+const ESTIMATION_ENERGY: Energy = Energy { energy: 3_000_000 };
+
+/// The number of bytes of a module per unit of energy charged for looking up
+/// the module when an instance is initialized. The chain charges this in
+/// addition to the execution of the init function.
+const MODULE_LOOKUP_BYTES_PER_NRG: u64 = 50;
+
+/// Energy added to cover the fixed costs of an initialization that are not
+/// part of the local execution, namely the base cost of the operation and of
+/// creating the instance, with room to spare. The costs of the transaction
+/// header are added by [`send::init_contract`] itself.
+const INIT_FIXED_COST: u64 = 500;
+
+/// The fraction of the energy used by the local execution that is added as a
+/// safety margin, since the execution on the chain can differ, e.g., if the
+/// slot time differs. A value of 10 adds a tenth.
+const EXECUTION_MARGIN_DIVISOR: u64 = 10;
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when deploying a module or initializing a
+/// contract.
+pub enum DeployError {
+    #[error("Unable to read the module: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unable to parse the module: {0}")]
+    InvalidModule(String),
+    #[error("Unable to query the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Unable to send the transaction: {0}")]
+    Send(#[from] v2::RPCError),
+    #[error("Transaction {hash} was rejected: {reason:?}")]
+    Rejected {
+        hash:   TransactionHash,
+        reason: RejectReason,
+    },
+    #[error("Unable to estimate the energy of the init function: {0}")]
+    Estimation(String),
+    #[error("Unexpected transaction outcome: {0}")]
+    UnexpectedOutcome(String),
+}
+
+/// Read a module from a file, such as the `.wasm.v1` files produced by
+/// `cargo concordium build`. The file must contain the version of the module
+/// followed by its source.
+pub fn read_module(path: impl AsRef<Path>) -> Result<WasmModule, DeployError> {
+    let contents = std::fs::read(path)?;
+    common::from_bytes(&mut std::io::Cursor::new(contents))
+        .map_err(|e| DeployError::InvalidModule(e.to_string()))
+}
+
+/// Whether the module is deployed in the given block.
+pub async fn module_exists(
+    client: &mut v2::Client,
+    module_ref: &ModuleReference,
+    bi: impl v2::IntoBlockIdentifier,
+) -> v2::QueryResult<bool> {
+    match client.get_module_source(module_ref, bi).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.is_not_found() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The result of [`deploy_module`].
+pub struct ModuleDeployment {
+    pub module_ref:  ModuleReference,
+    /// The transaction that deployed the module, or [`None`] if it was
+    /// already deployed.
+    pub transaction: Option<TransactionHash>,
+}
+
+/// Deploy the module unless it is already deployed, and wait until the
+/// deployment is finalized. A module deployed concurrently by another
+/// account, which causes the transaction to be rejected with
+/// [`ModuleHashAlreadyExists`](RejectReason::ModuleHashAlreadyExists), is
+/// also treated as already deployed.
+pub async fn deploy_module(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    module: WasmModule,
+    expiry: TransactionTime,
+) -> Result<ModuleDeployment, DeployError> {
+    let module_ref = module.get_module_ref();
+    if module_exists(client, &module_ref, BlockIdentifier::LastFinal).await? {
+        return Ok(ModuleDeployment {
+            module_ref,
+            transaction: None,
+        });
+    }
+    let nonce = client
+        .get_next_account_sequence_number(&wallet.address)
+        .await?
+        .nonce;
+    let tx = send::deploy_module(wallet, wallet.address, nonce, expiry, module);
+    let hash = client.send_account_transaction(tx).await?;
+    let (_, summary) = client.wait_until_finalized(&hash).await?;
+    match summary.is_rejected_account_transaction() {
+        None => Ok(ModuleDeployment {
+            module_ref,
+            transaction: Some(hash),
+        }),
+        Some(RejectReason::ModuleHashAlreadyExists { .. }) => Ok(ModuleDeployment {
+            module_ref,
+            transaction: None,
+        }),
+        Some(reason) => Err(DeployError::Rejected {
+            hash,
+            reason: reason.clone(),
+        }),
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The initialization of a contract instance.
+pub struct ContractInit {
+    pub module_ref: ModuleReference,
+    /// The name of the init function, including the `init_` prefix.
+    pub init_name:  OwnedContractName,
+    pub param:      OwnedParameter,
+    pub amount:     Amount,
+    /// The energy limit of the transaction. If this is [`None`] the energy is
+    /// estimated by executing the init function locally.
+    pub energy:     Option<Energy>,
+}
+
+impl ContractInit {
+    /// Initialize the contract without an amount and with an estimated energy
+    /// limit.
+    pub fn new(
+        module_ref: ModuleReference,
+        init_name: OwnedContractName,
+        param: OwnedParameter,
+    ) -> Self {
+        Self {
+            module_ref,
+            init_name,
+            param,
+            amount: Amount::zero(),
+            energy: None,
+        }
+    }
+
+    fn payload(&self) -> InitContractPayload {
+        InitContractPayload {
+            amount:    self.amount,
+            mod_ref:   self.module_ref,
+            init_name: self.init_name.clone(),
+            param:     self.param.clone(),
+        }
+    }
+}
+
+/// Estimate the energy needed to initialize the contract by executing the
+/// init function of the deployed module locally, with the given account as
+/// the sender. Only V1 modules are supported.
+///
+/// The estimate is the energy used by the init function plus a tenth of it
+/// as a safety margin, the cost of looking up the module, which is one unit
+/// per [`MODULE_LOOKUP_BYTES_PER_NRG`] bytes of the module, and the fixed
+/// [`INIT_FIXED_COST`].
+pub async fn estimate_init_energy(
+    client: &mut v2::Client,
+    init: &ContractInit,
+    sender: AccountAddress,
+) -> Result<Energy, DeployError> {
+    let module = client
+        .get_module_source(&init.module_ref, BlockIdentifier::LastFinal)
+        .await?;
+    let block_info = client.get_block_info(module.block_hash).await?.response;
+    let slot_time = slot_time(block_info.block_slot_time);
+    let module = module.response;
+    let outcome = dry_run_init(&module, &init.payload(), sender, slot_time, ESTIMATION_ENERGY)
+        .map_err(|e| DeployError::Estimation(e.to_string()))?;
+    let used = match outcome {
+        InitOutcome::Success { used_energy, .. } => used_energy.energy,
+        InitOutcome::Reject { reason, .. } => {
+            return Err(DeployError::Estimation(format!(
+                "The init function rejected with reason {reason}."
+            )));
+        }
+        InitOutcome::Trap { error, .. } => {
+            return Err(DeployError::Estimation(format!("The init function failed: {error}")));
+        }
+        InitOutcome::OutOfEnergy { .. } => {
+            return Err(DeployError::Estimation("The init function ran out of energy.".into()));
+        }
+    };
+    let module_size = module.source.as_ref().len() as u64;
+    let margin = used / EXECUTION_MARGIN_DIVISOR;
+    let lookup = module_size / MODULE_LOOKUP_BYTES_PER_NRG;
+    Ok(Energy {
+        energy: used + margin + lookup + INIT_FIXED_COST,
+    })
+}
+
+/// Initialize a contract instance and wait until the transaction is
+/// finalized. Returns the address of the new instance.
+pub async fn init_contract(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    init: &ContractInit,
+    expiry: TransactionTime,
+) -> Result<ContractAddress, DeployError> {
+    let energy = match init.energy {
+        Some(energy) => energy,
+        None => estimate_init_energy(client, init, wallet.address).await?,
+    };
+    let nonce = client
+        .get_next_account_sequence_number(&wallet.address)
+        .await?
+        .nonce;
+    let tx = send::init_contract(wallet, wallet.address, nonce, expiry, init.payload(), energy);
+    let hash = client.send_account_transaction(tx).await?;
+    let (_, summary) = client.wait_until_finalized(&hash).await?;
+    if let Some(reason) = summary.is_rejected_account_transaction() {
+        return Err(DeployError::Rejected {
+            hash,
+            reason: reason.clone(),
+        });
+    }
+    summary
+        .contract_init()
+        .map(|data| data.address)
+        .ok_or_else(|| DeployError::UnexpectedOutcome(format!("{:?}", summary.details)))
+}
+
+/// Deploy the module unless it is already deployed, initialize an instance
+/// of each of the given contracts, and return a [`ContractClient`] for each
+/// new instance. The module references of the `inits` are replaced by the
+/// reference of the module.
+pub async fn deploy_and_init<Type>(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    module: WasmModule,
+    inits: impl IntoIterator<Item = ContractInit>,
+    expiry: TransactionTime,
+) -> Result<Vec<ContractClient<Type>>, DeployError> {
+    let deployment = deploy_module(client, wallet, module, expiry).await?;
+    let mut contracts = Vec::new();
+    for mut init in inits {
+        init.module_ref = deployment.module_ref;
+        let address = init_contract(client, wallet, &init, expiry).await?;
+        contracts.push(ContractClient::new(client.clone(), address, init.init_name));
+    }
+    Ok(contracts)
+}
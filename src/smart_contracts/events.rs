@@ -0,0 +1,276 @@
+//! Decoding of the events logged by smart contracts.
+//!
+//! Contracts log events as raw bytes. If the module of a contract embeds a
+//! schema with an event schema for the contract, the events can be decoded
+//! into JSON values using [`decode_event`].
+//!
+//! A [`SchemaRegistry`] keeps track of the module and name of each contract
+//! instance it has seen, and caches the embedded schema of each module, so
+//! that the events of all contracts in a block can be decoded with
+//! [`decode_block`](SchemaRegistry::decode_block). Instances that are
+//! initialized or upgraded in the decoded transactions are registered
+//! automatically.
+use crate::{
+    contract_client::{embedded_schema, SchemaError},
+    endpoints::QueryResult,
+    types::{
+        hashes::TransactionHash,
+        smart_contracts::{ContractEvent, ModuleReference, OwnedContractName},
+        AccountTransactionEffects, BlockItemSummary, BlockItemSummaryDetails, ContractAddress,
+        ContractTraceElement,
+    },
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::contracts_common::{schema::VersionedModuleSchema, Cursor};
+use futures::TryStreamExt;
+use std::{collections::HashMap, sync::Arc};
+
+/// Decode an event logged by the given contract using the event schema of
+/// the contract. The contract name is without the `init_` prefix.
+pub fn decode_event(
+    schema: &VersionedModuleSchema,
+    contract_name: &str,
+    event: &ContractEvent,
+) -> Result<serde_json::Value, SchemaError> {
+    let event_schema = schema.get_event_schema(contract_name)?;
+    Ok(event_schema.to_json(&mut Cursor::new(event.as_ref()))?)
+}
+
+#[derive(Debug)]
+/// An event logged by a contract instance, together with its decoded value.
+pub struct DecodedEvent {
+    /// The instance that logged the event.
+    pub address: ContractAddress,
+    /// The event as logged by the instance.
+    pub event:   ContractEvent,
+    /// The event decoded using the schema of the instance. This is
+    /// [`SchemaError::NoSchema`] if the module of the instance does not have
+    /// an embedded schema.
+    pub value:   Result<serde_json::Value, SchemaError>,
+}
+
+#[derive(Debug)]
+/// The decoded events of a single transaction, in the order they were
+/// logged.
+pub struct TransactionEvents {
+    pub transaction: TransactionHash,
+    pub events:      Vec<DecodedEvent>,
+}
+
+#[derive(Debug, Clone)]
+/// The module and name of a contract instance.
+struct Instance {
+    module: ModuleReference,
+    name:   OwnedContractName,
+}
+
+#[derive(Debug, Default, Clone)]
+/// A registry of the schemas of contract instances.
+///
+/// The schemas are cached per module, so each module is only looked up once.
+/// Cloning the registry is cheap since the schemas are shared.
+pub struct SchemaRegistry {
+    /// The schema of each module that has been looked up, or [`None`] if the
+    /// module does not embed a schema.
+    modules:   HashMap<ModuleReference, Option<Arc<VersionedModuleSchema>>>,
+    instances: HashMap<ContractAddress, Instance>,
+}
+
+impl SchemaRegistry {
+    /// Construct an empty registry.
+    pub fn new() -> Self { Self::default() }
+
+    /// Use the given schema for the module instead of the schema embedded in
+    /// it. This is needed for modules that were deployed without an embedded
+    /// schema.
+    pub fn insert_schema(&mut self, module: ModuleReference, schema: VersionedModuleSchema) {
+        self.modules.insert(module, Some(Arc::new(schema)));
+    }
+
+    /// Register the module and name of a contract instance.
+    pub fn insert_instance(
+        &mut self,
+        address: ContractAddress,
+        module: ModuleReference,
+        name: OwnedContractName,
+    ) {
+        self.instances.insert(address, Instance { module, name });
+    }
+
+    /// The schema of the module, if it has been looked up and has a schema.
+    pub fn schema(&self, module: &ModuleReference) -> Option<&VersionedModuleSchema> {
+        self.modules.get(module)?.as_deref()
+    }
+
+    /// Look up the schema embedded in the module, unless it is already
+    /// cached. Returns [`None`] if the module does not embed a schema.
+    pub async fn module_schema(
+        &mut self,
+        client: &mut v2::Client,
+        module: ModuleReference,
+        bi: impl IntoBlockIdentifier,
+    ) -> QueryResult<Option<Arc<VersionedModuleSchema>>> {
+        if let Some(schema) = self.modules.get(&module) {
+            return Ok(schema.clone());
+        }
+        let source = client.get_module_source(&module, bi).await?.response;
+        let schema = embedded_schema(&source).ok().map(Arc::new);
+        self.modules.insert(module, schema.clone());
+        Ok(schema)
+    }
+
+    /// Look up the module and name of the instance, unless it is already
+    /// registered.
+    async fn instance(
+        &mut self,
+        client: &mut v2::Client,
+        address: ContractAddress,
+        bi: impl IntoBlockIdentifier,
+    ) -> QueryResult<Instance> {
+        if let Some(instance) = self.instances.get(&address) {
+            return Ok(instance.clone());
+        }
+        let info = client.get_instance_info(address, bi).await?.response;
+        let instance = Instance {
+            module: info.source_module(),
+            name:   info.name().clone(),
+        };
+        self.instances.insert(address, instance.clone());
+        Ok(instance)
+    }
+
+    /// Decode events logged by the instance. The module and schema of the
+    /// instance are looked up in the given block if they are not yet known.
+    pub async fn decode_events(
+        &mut self,
+        client: &mut v2::Client,
+        address: ContractAddress,
+        events: &[ContractEvent],
+        bi: impl IntoBlockIdentifier + Copy,
+    ) -> QueryResult<Vec<DecodedEvent>> {
+        let instance = self.instance(client, address, bi).await?;
+        let schema = self.module_schema(client, instance.module, bi).await?;
+        let contract_name = instance.name.as_contract_name().contract_name();
+        Ok(events
+            .iter()
+            .map(|event| DecodedEvent {
+                address,
+                event: event.clone(),
+                value: match &schema {
+                    Some(schema) => decode_event(schema, contract_name, event),
+                    None => Err(SchemaError::NoSchema),
+                },
+            })
+            .collect())
+    }
+
+    /// Register the instances in the trace that are not yet known, using the
+    /// module they had before the block. Instances that did not exist before
+    /// the block are left to be looked up in the block itself.
+    async fn register_trace_instances(
+        &mut self,
+        client: &mut v2::Client,
+        effects: &[ContractTraceElement],
+        bi: impl IntoBlockIdentifier + Copy,
+    ) -> QueryResult<()> {
+        let mut parent = None;
+        for effect in effects {
+            let address = match effect {
+                ContractTraceElement::Updated { data } => data.address,
+                ContractTraceElement::Interrupted { address, .. }
+                | ContractTraceElement::Resumed { address, .. }
+                | ContractTraceElement::Upgraded { address, .. } => *address,
+                ContractTraceElement::Transferred { .. } => continue,
+            };
+            if self.instances.contains_key(&address) {
+                continue;
+            }
+            let parent = match parent {
+                Some(parent) => parent,
+                None => *parent.insert(client.get_block_info(bi).await?.response.block_parent),
+            };
+            let info = match client.get_instance_info(address, parent).await {
+                Ok(info) => info.response,
+                Err(e) if e.is_not_found() => continue,
+                Err(e) => return Err(e),
+            };
+            self.insert_instance(address, info.source_module(), info.name().clone());
+        }
+        Ok(())
+    }
+
+    /// Decode the events logged by the contracts in a transaction that was
+    /// included in the given block. Instances initialized or upgraded by the
+    /// transaction are registered in the registry.
+    ///
+    /// Instances that are not yet registered are looked up as they were
+    /// before the block, and their module is updated at each upgrade in the
+    /// trace. Events logged before an upgrade are thus decoded with the
+    /// schema of the old module, and events logged after it with the schema
+    /// of the new module. Earlier transactions of the same block must be
+    /// decoded first for this to be accurate, as
+    /// [`decode_block`](Self::decode_block) does.
+    pub async fn decode_block_item(
+        &mut self,
+        client: &mut v2::Client,
+        summary: &BlockItemSummary,
+        bi: impl IntoBlockIdentifier + Copy,
+    ) -> QueryResult<Vec<DecodedEvent>> {
+        let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+            return Ok(Vec::new());
+        };
+        let mut decoded = Vec::new();
+        match &at.effects {
+            AccountTransactionEffects::ContractInitialized { data } => {
+                self.insert_instance(data.address, data.origin_ref, data.init_name.clone());
+                decoded.extend(self.decode_events(client, data.address, &data.events, bi).await?);
+            }
+            AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                self.register_trace_instances(client, effects, bi).await?;
+                for effect in effects {
+                    match effect {
+                        ContractTraceElement::Updated { data } => decoded.extend(
+                            self.decode_events(client, data.address, &data.events, bi).await?,
+                        ),
+                        ContractTraceElement::Interrupted { address, events } => {
+                            decoded.extend(self.decode_events(client, *address, events, bi).await?)
+                        }
+                        ContractTraceElement::Upgraded { address, to, .. } => {
+                            // Events logged after the upgrade use the schema of
+                            // the new module.
+                            let instance = self.instance(client, *address, bi).await?;
+                            self.insert_instance(*address, *to, instance.name);
+                        }
+                        ContractTraceElement::Transferred { .. } => (),
+                        ContractTraceElement::Resumed { .. } => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+        Ok(decoded)
+    }
+
+    /// Decode the events logged by all contracts in the given block. Only
+    /// transactions that logged events are returned.
+    pub async fn decode_block(
+        &mut self,
+        client: &mut v2::Client,
+        bi: impl IntoBlockIdentifier,
+    ) -> QueryResult<Vec<TransactionEvents>> {
+        let summaries = client.get_block_transaction_events(bi).await?;
+        let block_hash = summaries.block_hash;
+        let summaries = summaries.response.try_collect::<Vec<_>>().await?;
+        let mut result = Vec::new();
+        for summary in summaries {
+            let events = self.decode_block_item(client, &summary, block_hash).await?;
+            if !events.is_empty() {
+                result.push(TransactionEvents {
+                    transaction: summary.hash,
+                    events,
+                });
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,448 @@
+//! The lifecycle history of a contract instance.
+//!
+//! [`instance_events`] extracts everything a transaction did to or on behalf
+//! of an instance: its initialization, updates together with their caller and
+//! amount, calls the instance made to other contracts, transfers out of the
+//! instance, upgrades of its module, and the points where its execution was
+//! interrupted and resumed.
+//!
+//! The complete history of an instance can be built by traversing all
+//! finalized blocks since its creation with [`instance_history`], or, if the
+//! `postgres` feature is enabled, from the transaction log of the node with
+//! [`instance_history_from_database`].
+use crate::{
+    endpoints::QueryResult,
+    types::{
+        hashes::{BlockHash, TransactionHash},
+        smart_contracts::{ContractEvent, ModuleReference, OwnedParameter, OwnedReceiveName},
+        AbsoluteBlockHeight, AccountTransactionEffects, Address, BlockItemSummary,
+        BlockItemSummaryDetails, ContractAddress, ContractTraceElement,
+    },
+    v2,
+};
+use concordium_base::{
+    common::types::{Amount, Timestamp},
+    contracts_common::{AccountAddress, OwnedContractName},
+};
+use futures::TryStreamExt;
+
+#[derive(Debug, Clone)]
+/// Something a transaction did to or on behalf of an instance.
+pub enum InstanceEventKind {
+    /// The instance was created.
+    Initialized {
+        sender:    AccountAddress,
+        module:    ModuleReference,
+        init_name: OwnedContractName,
+        amount:    Amount,
+        events:    Vec<ContractEvent>,
+    },
+    /// An entrypoint of the instance was invoked.
+    Updated {
+        /// The account or contract that invoked the entrypoint.
+        instigator:   Address,
+        receive_name: OwnedReceiveName,
+        message:      OwnedParameter,
+        amount:       Amount,
+        events:       Vec<ContractEvent>,
+    },
+    /// The instance invoked an entrypoint of another contract.
+    Called {
+        contract:     ContractAddress,
+        receive_name: OwnedReceiveName,
+        amount:       Amount,
+    },
+    /// The instance transferred CCD to an account.
+    Transferred { to: AccountAddress, amount: Amount },
+    /// The execution of the instance was interrupted to call another contract
+    /// or transfer CCD.
+    Interrupted { events: Vec<ContractEvent> },
+    /// The execution of the instance resumed after an interrupt.
+    Resumed {
+        /// Whether the operation that caused the interrupt succeeded.
+        success: bool,
+    },
+    /// The module of the instance was upgraded.
+    Upgraded {
+        from: ModuleReference,
+        to:   ModuleReference,
+    },
+}
+
+#[derive(Debug, Clone)]
+/// An event in the history of an instance.
+pub struct InstanceEvent {
+    pub block_hash:   BlockHash,
+    pub block_height: AbsoluteBlockHeight,
+    pub block_time:   Timestamp,
+    pub transaction:  TransactionHash,
+    pub kind:         InstanceEventKind,
+}
+
+/// The events of the instance in a transaction, in the order they happened.
+pub fn instance_events(
+    address: ContractAddress,
+    summary: &BlockItemSummary,
+) -> Vec<InstanceEventKind> {
+    let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+        return Vec::new();
+    };
+    match &at.effects {
+        AccountTransactionEffects::ContractInitialized { data } if data.address == address => {
+            vec![InstanceEventKind::Initialized {
+                sender:    at.sender,
+                module:    data.origin_ref,
+                init_name: data.init_name.clone(),
+                amount:    data.amount,
+                events:    data.events.clone(),
+            }]
+        }
+        AccountTransactionEffects::ContractUpdateIssued { effects } => effects
+            .iter()
+            .filter_map(|effect| match effect {
+                ContractTraceElement::Updated { data } if data.address == address => {
+                    Some(InstanceEventKind::Updated {
+                        instigator:   data.instigator,
+                        receive_name: data.receive_name.clone(),
+                        message:      data.message.clone(),
+                        amount:       data.amount,
+                        events:       data.events.clone(),
+                    })
+                }
+                ContractTraceElement::Updated { data }
+                    if data.instigator == Address::Contract(address) =>
+                {
+                    Some(InstanceEventKind::Called {
+                        contract:     data.address,
+                        receive_name: data.receive_name.clone(),
+                        amount:       data.amount,
+                    })
+                }
+                ContractTraceElement::Transferred { from, amount, to } if *from == address => {
+                    Some(InstanceEventKind::Transferred {
+                        to:     *to,
+                        amount: *amount,
+                    })
+                }
+                ContractTraceElement::Interrupted { address: a, events } if *a == address => {
+                    Some(InstanceEventKind::Interrupted {
+                        events: events.clone(),
+                    })
+                }
+                ContractTraceElement::Resumed { address: a, success } if *a == address => {
+                    Some(InstanceEventKind::Resumed { success: *success })
+                }
+                ContractTraceElement::Upgraded { address: a, from, to } if *a == address => {
+                    Some(InstanceEventKind::Upgraded {
+                        from: *from,
+                        to:   *to,
+                    })
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Reconstruct the history of the instance from its creation until the `end`
+/// height (inclusive), or until the last finalized block if `end` is
+/// [`None`]. This queries every finalized block since the creation of the
+/// instance and can take a long time for old instances.
+///
+/// If the instance does not exist at `end`,
+/// [`QueryError::NotFound`](v2::QueryError::NotFound) is returned.
+pub async fn instance_history(
+    client: &mut v2::Client,
+    address: ContractAddress,
+    end: Option<AbsoluteBlockHeight>,
+) -> QueryResult<Vec<InstanceEvent>> {
+    let end = match end {
+        Some(end) => end,
+        None => client.get_consensus_info().await?.last_finalized_block_height,
+    };
+    let (start, _, _) = client.find_instance_creation(..=end, address).await?;
+    let mut history = Vec::new();
+    let mut blocks = client.get_finalized_blocks_from(start).await?;
+    while let Some(block) = blocks.next().await {
+        if block.height > end {
+            break;
+        }
+        let summaries = client
+            .get_block_transaction_events(block.block_hash)
+            .await?
+            .response
+            .try_collect::<Vec<_>>()
+            .await?;
+        let mut block_time = None;
+        for summary in summaries {
+            let events = instance_events(address, &summary);
+            if events.is_empty() {
+                continue;
+            }
+            let time = match block_time {
+                Some(time) => time,
+                None => {
+                    let info = client.get_block_info(block.block_hash).await?.response;
+                    let time = Timestamp::from(info.block_slot_time.timestamp_millis() as u64);
+                    *block_time.insert(time)
+                }
+            };
+            history.extend(events.into_iter().map(|kind| InstanceEvent {
+                block_hash: block.block_hash,
+                block_height: block.height,
+                block_time: time,
+                transaction: summary.hash,
+                kind,
+            }));
+        }
+        if block.height == end {
+            break;
+        }
+    }
+    Ok(history)
+}
+
+#[cfg(feature = "postgres")]
+/// Reconstruct the history of the instance from the transaction log of the
+/// node, starting with the given row id.
+pub async fn instance_history_from_database(
+    db: &crate::postgres::DatabaseClient,
+    address: ContractAddress,
+    start: Option<i64>,
+) -> Result<Vec<InstanceEvent>, crate::postgres::Error> {
+    use crate::postgres::DatabaseSummaryEntry;
+    use futures::StreamExt;
+    let rows = db.iterate_contract(address, start).await?;
+    let history = rows
+        .flat_map(|row| {
+            let events = match &row.summary {
+                DatabaseSummaryEntry::BlockItem(summary) => instance_events(address, summary)
+                    .into_iter()
+                    .map(|kind| InstanceEvent {
+                        block_hash: row.block_hash,
+                        block_height: row.block_height,
+                        block_time: row.block_time,
+                        transaction: summary.hash,
+                        kind,
+                    })
+                    .collect(),
+                DatabaseSummaryEntry::ProtocolEvent(_) => Vec::new(),
+            };
+            futures::stream::iter(events)
+        })
+        .collect()
+        .await;
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        hashes::HashBytes, smart_contracts::WasmVersion, AccountTransactionDetails, Energy,
+        InstanceUpdatedEvent, TransactionIndex,
+    };
+
+    const ACCOUNT: AccountAddress = AccountAddress([0u8; 32]);
+
+    fn contract(index: u64) -> ContractAddress { ContractAddress::new(index, 0) }
+
+    fn interrupted(index: u64, events: usize) -> ContractTraceElement {
+        ContractTraceElement::Interrupted {
+            address: contract(index),
+            events:  vec![ContractEvent::from(vec![0u8]); events],
+        }
+    }
+
+    fn resumed(index: u64, success: bool) -> ContractTraceElement {
+        ContractTraceElement::Resumed {
+            address: contract(index),
+            success,
+        }
+    }
+
+    fn updated(index: u64, instigator: Address) -> ContractTraceElement {
+        ContractTraceElement::Updated {
+            data: InstanceUpdatedEvent {
+                contract_version: WasmVersion::V1,
+                address: contract(index),
+                instigator,
+                amount: Amount::from_micro_ccd(0),
+                message: OwnedParameter::empty(),
+                receive_name: OwnedReceiveName::new_unchecked("c.f".into()),
+                events: Vec::new(),
+            },
+        }
+    }
+
+    fn transferred(index: u64, amount: u64) -> ContractTraceElement {
+        ContractTraceElement::Transferred {
+            from:   contract(index),
+            amount: Amount::from_micro_ccd(amount),
+            to:     ACCOUNT,
+        }
+    }
+
+    fn upgraded(index: u64) -> ContractTraceElement {
+        ContractTraceElement::Upgraded {
+            address: contract(index),
+            from:    ModuleReference::new([0u8; 32]),
+            to:      ModuleReference::new([1u8; 32]),
+        }
+    }
+
+    fn summary(trace: &[ContractTraceElement]) -> BlockItemSummary {
+        BlockItemSummary {
+            index:       TransactionIndex { index: 0 },
+            energy_cost: Energy { energy: 0 },
+            hash:        HashBytes::new([0u8; 32]),
+            details:     BlockItemSummaryDetails::AccountTransaction(AccountTransactionDetails {
+                cost:    Amount::from_micro_ccd(0),
+                sender:  ACCOUNT,
+                effects: AccountTransactionEffects::ContractUpdateIssued {
+                    effects: trace.to_vec(),
+                },
+            }),
+        }
+    }
+
+    /// A short description of an event that identifies the other party.
+    fn describe(kind: &InstanceEventKind) -> String {
+        let address = |address: &Address| match address {
+            Address::Account(_) => "account".to_string(),
+            Address::Contract(c) => format!("<{},{}>", c.index, c.subindex),
+        };
+        match kind {
+            InstanceEventKind::Initialized { .. } => "initialized".into(),
+            InstanceEventKind::Updated { instigator, .. } => {
+                format!("updated by {}", address(instigator))
+            }
+            InstanceEventKind::Called { contract, .. } => {
+                format!("called {}", address(&Address::Contract(*contract)))
+            }
+            InstanceEventKind::Transferred { amount, .. } => {
+                format!("transferred {}", amount.micro_ccd())
+            }
+            InstanceEventKind::Interrupted { events } => format!("interrupted {}", events.len()),
+            InstanceEventKind::Resumed { success } => format!("resumed {success}"),
+            InstanceEventKind::Upgraded { .. } => "upgraded".into(),
+        }
+    }
+
+    #[test]
+    fn test_instance_events() {
+        // The traces are for transactions sent by an account, and the events
+        // are those of instance <0,0>.
+        let cases: Vec<(&str, Vec<ContractTraceElement>, Vec<&str>)> = vec![
+            ("update by account", vec![updated(0, Address::Account(ACCOUNT))], vec![
+                "updated by account",
+            ]),
+            (
+                "call to another contract",
+                vec![
+                    interrupted(0, 1),
+                    updated(1, Address::Contract(contract(0))),
+                    resumed(0, true),
+                    updated(0, Address::Account(ACCOUNT)),
+                ],
+                vec![
+                    "interrupted 1",
+                    "called <1,0>",
+                    "resumed true",
+                    "updated by account",
+                ],
+            ),
+            (
+                "called by another contract",
+                vec![
+                    interrupted(1, 0),
+                    updated(0, Address::Contract(contract(1))),
+                    resumed(1, true),
+                    updated(1, Address::Account(ACCOUNT)),
+                ],
+                vec!["updated by <1,0>"],
+            ),
+            (
+                "self-call with a transfer",
+                vec![
+                    interrupted(0, 1),
+                    interrupted(0, 2),
+                    transferred(0, 5),
+                    resumed(0, true),
+                    updated(0, Address::Contract(contract(0))),
+                    resumed(0, true),
+                    updated(0, Address::Account(ACCOUNT)),
+                ],
+                vec![
+                    "interrupted 1",
+                    "interrupted 2",
+                    "transferred 5",
+                    "resumed true",
+                    "updated by <0,0>",
+                    "resumed true",
+                    "updated by account",
+                ],
+            ),
+            (
+                "failed call",
+                vec![
+                    interrupted(0, 0),
+                    resumed(0, false),
+                    updated(0, Address::Account(ACCOUNT)),
+                ],
+                vec!["interrupted 0", "resumed false", "updated by account"],
+            ),
+            (
+                "transfer by another contract",
+                vec![
+                    interrupted(1, 0),
+                    transferred(1, 5),
+                    resumed(1, true),
+                    updated(1, Address::Account(ACCOUNT)),
+                ],
+                vec![],
+            ),
+            (
+                "upgrade",
+                vec![
+                    interrupted(0, 0),
+                    upgraded(0),
+                    resumed(0, true),
+                    updated(0, Address::Account(ACCOUNT)),
+                ],
+                vec![
+                    "interrupted 0",
+                    "upgraded",
+                    "resumed true",
+                    "updated by account",
+                ],
+            ),
+            (
+                "upgrade of another contract",
+                vec![
+                    interrupted(1, 0),
+                    upgraded(1),
+                    resumed(1, true),
+                    updated(1, Address::Account(ACCOUNT)),
+                ],
+                vec![],
+            ),
+        ];
+        for (name, trace, expected) in cases {
+            let events = instance_events(contract(0), &summary(&trace));
+            let described = events.iter().map(describe).collect::<Vec<_>>();
+            assert_eq!(described, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_instance_events_upgrade_modules() {
+        let events = instance_events(contract(0), &summary(&[upgraded(0)]));
+        let [InstanceEventKind::Upgraded { from, to }] = &events[..] else {
+            panic!("Expected an upgrade, got {events:?}.");
+        };
+        assert_eq!(*from, ModuleReference::new([0u8; 32]));
+        assert_eq!(*to, ModuleReference::new([1u8; 32]));
+    }
+}
@@ -0,0 +1,490 @@
+//! Local execution of smart contracts against a snapshot of on-chain state.
+//!
+//! A [`LocalInstance`] is a snapshot of the module, state and balance of a
+//! V1 contract instance in a given block. Receive functions of the instance
+//! can be executed locally with the [`engine`] using arbitrary
+//! parameters, amounts and invokers. Successful calls update the state and
+//! balance of the snapshot, so sequences of calls can be simulated and the
+//! resulting state inspected without sending any transactions.
+//!
+//! The init function of a module can similarly be dry-run with
+//! [`dry_run_init`], either for a module deployed on the chain or for a
+//! module that is supplied locally, to test an [`InitContractPayload`]
+//! before sending it.
+//!
+//! Only the instance itself is simulated. A call that invokes another
+//! contract, transfers CCD, queries the chain or upgrades the instance is
+//! stopped at that point and reported as [`LocalOutcome::Interrupted`].
+use super::engine::{
+    self,
+    v1::{self, trie},
+};
+use crate::{
+    types::{
+        hashes::BlockHash,
+        smart_contracts::{
+            ContractEvent, InstanceInfo, ModuleReference, OwnedContractName, OwnedParameter,
+            OwnedReceiveName, WasmModule, WasmVersion,
+        },
+        transactions::InitContractPayload,
+        Address, ContractAddress, Energy,
+    },
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::{
+    common::types::Amount,
+    contracts_common::{AccountAddress, ChainMetadata, OwnedEntrypointName, Timestamp},
+};
+use futures::TryStreamExt;
+use std::sync::Arc;
+
+/// The number of interpreter energy units per unit of NRG.
+pub(crate) const INTERPRETER_ENERGY_PER_NRG: u64 = 1000;
+
+/// A V1 module compiled for execution by the engine.
+type Artifact = engine::wasm::artifact::Artifact<
+    v1::ProcessedImports,
+    engine::wasm::artifact::CompiledFunction,
+>;
+
+/// Compile a V1 module for execution.
+fn compile(module: &WasmModule) -> Result<Artifact, LocalExecutionError> {
+    if !matches!(module.version, WasmVersion::V1) {
+        return Err(LocalExecutionError::UnsupportedVersion);
+    }
+    engine::utils::instantiate_with_metering::<v1::ProcessedImports, _>(
+        &v1::ConcordiumAllowedImports {
+            support_upgrade: true,
+        },
+        module.source.as_ref(),
+    )
+    .map_err(|e| LocalExecutionError::Engine(e.to_string()))
+}
+
+/// A loader for tries that are entirely in memory, which is the case for all
+/// states built locally.
+fn memory_loader() -> trie::Loader<&'static [u8]> { trie::Loader::new(&[][..]) }
+
+/// The slot time of a block, as it is passed to contracts.
+pub(crate) fn slot_time(time: chrono::DateTime<chrono::Utc>) -> Timestamp {
+    Timestamp::from_timestamp_millis(time.timestamp_millis() as u64)
+}
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when taking a snapshot of an instance or executing
+/// it locally.
+pub enum LocalExecutionError {
+    #[error("Unable to query the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Local execution is only supported for V1 contracts.")]
+    UnsupportedVersion,
+    #[error("Invalid receive name: {0}")]
+    InvalidName(#[from] concordium_base::contracts_common::NewReceiveNameError),
+    #[error("The engine failed to execute the contract: {0}")]
+    Engine(String),
+}
+
+impl From<tonic::Status> for LocalExecutionError {
+    fn from(value: tonic::Status) -> Self { Self::Query(value.into()) }
+}
+
+#[derive(Debug, Clone)]
+/// A call of a receive function of a [`LocalInstance`].
+pub struct LocalCall {
+    pub entrypoint: OwnedEntrypointName,
+    pub parameter:  OwnedParameter,
+    /// The amount sent to the instance.
+    pub amount:     Amount,
+    /// The account that invoked the transaction.
+    pub invoker:    AccountAddress,
+    /// The immediate sender of the call. This is the same as the invoker
+    /// unless the call is simulated as coming from another contract.
+    pub sender:     Address,
+    /// The maximum energy the call may use.
+    pub energy:     Energy,
+}
+
+impl LocalCall {
+    /// Construct a call of the entrypoint by the given account, without an
+    /// amount and with the default energy limit of
+    /// [`DEFAULT_INVOKE_ENERGY`](crate::types::smart_contracts::DEFAULT_INVOKE_ENERGY).
+    pub fn new(
+        entrypoint: OwnedEntrypointName,
+        parameter: OwnedParameter,
+        invoker: AccountAddress,
+    ) -> Self {
+        Self {
+            entrypoint,
+            parameter,
+            amount: Amount::zero(),
+            invoker,
+            sender: Address::Account(invoker),
+            energy: crate::types::smart_contracts::DEFAULT_INVOKE_ENERGY,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The outcome of executing a [`LocalCall`].
+pub enum LocalOutcome {
+    /// The call succeeded. The state and balance of the instance have been
+    /// updated.
+    Success {
+        return_value:  Vec<u8>,
+        events:        Vec<ContractEvent>,
+        state_changed: bool,
+        used_energy:   Energy,
+    },
+    /// The contract rejected the call with the given error code.
+    Reject {
+        reason:       i32,
+        return_value: Vec<u8>,
+        used_energy:  Energy,
+    },
+    /// The execution of the contract failed at runtime.
+    Trap { error: String, used_energy: Energy },
+    /// The call ran out of energy.
+    OutOfEnergy { used_energy: Energy },
+    /// The contract attempted an operation that involves the rest of the
+    /// chain. The execution is stopped and the instance is not updated.
+    Interrupted {
+        /// The events logged before the interrupt.
+        events:      Vec<ContractEvent>,
+        used_energy: Energy,
+    },
+}
+
+impl LocalOutcome {
+    /// Whether the call succeeded.
+    pub fn is_success(&self) -> bool { matches!(self, LocalOutcome::Success { .. }) }
+}
+
+#[derive(Debug, Clone)]
+/// A snapshot of a V1 contract instance that can be executed locally.
+pub struct LocalInstance {
+    pub address:    ContractAddress,
+    /// The block the snapshot was taken in.
+    pub block_hash: BlockHash,
+    pub name:       OwnedContractName,
+    pub owner:      AccountAddress,
+    pub module_ref: ModuleReference,
+    /// The balance of the instance, including amounts sent in successful
+    /// local calls.
+    pub balance:    Amount,
+    /// The slot time used as the chain metadata of local calls. This is the
+    /// slot time of the snapshot block, but can be changed to simulate calls
+    /// at a later time.
+    pub slot_time:  Timestamp,
+    /// The module of the instance, compiled once when the snapshot is taken.
+    artifact:       Arc<Artifact>,
+    state:          trie::PersistentState,
+}
+
+impl LocalInstance {
+    /// Take a snapshot of the module and state of the instance in the given
+    /// block, and compile the module for local execution.
+    pub async fn fetch(
+        client: &mut v2::Client,
+        address: ContractAddress,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Self, LocalExecutionError> {
+        let info = client.get_instance_info(address, bi).await?;
+        let block_hash = info.block_hash;
+        let InstanceInfo::V1 {
+            owner,
+            amount,
+            source_module,
+            name,
+            ..
+        } = info.response
+        else {
+            return Err(LocalExecutionError::UnsupportedVersion);
+        };
+        let module = client
+            .get_module_source(&source_module, block_hash)
+            .await?
+            .response;
+        let entries = client
+            .get_instance_state(address, block_hash)
+            .await?
+            .response
+            .try_collect::<Vec<_>>()
+            .await?;
+        let block_info = client.get_block_info(block_hash).await?.response;
+        Ok(Self {
+            address,
+            block_hash,
+            name,
+            owner,
+            module_ref: source_module,
+            balance: amount,
+            slot_time: slot_time(block_info.block_slot_time),
+            artifact: Arc::new(compile(&module)?),
+            state: build_state(entries)?,
+        })
+    }
+
+    /// Look up the value stored under the key in the current state of the
+    /// instance.
+    pub fn lookup(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.state.lookup(&mut memory_loader(), key)
+    }
+
+    /// The current state of the instance. This can be inspected with the
+    /// functions of the [`trie`] module of the engine.
+    pub fn state(&self) -> &trie::PersistentState { &self.state }
+
+    /// Execute a receive function of the instance. If the call succeeds, the
+    /// state and balance of the instance are updated.
+    pub fn call(&mut self, call: &LocalCall) -> Result<LocalOutcome, LocalExecutionError> {
+        let contract_name = self.name.as_contract_name().contract_name();
+        let receive_name =
+            OwnedReceiveName::try_from(format!("{contract_name}.{}", call.entrypoint))?;
+        let context = v1::ReceiveContext {
+            common:     engine::v0::ReceiveContext {
+                metadata:        ChainMetadata {
+                    slot_time: self.slot_time,
+                },
+                invoker:         call.invoker,
+                self_address:    self.address,
+                // The balance of a V1 instance includes the amount sent.
+                self_balance:    self.balance_after(call.amount),
+                sender:          call.sender,
+                owner:           self.owner,
+                // An empty list of policies.
+                sender_policies: vec![0u8, 0],
+            },
+            entrypoint: call.entrypoint.clone(),
+        };
+        let mut loader = memory_loader();
+        let mut mutable_state = self.state.thaw();
+        let inner = mutable_state.get_inner(&mut loader);
+        let instance_state = v1::InstanceState::new(memory_loader(), inner);
+        let energy = call.energy.energy.saturating_mul(INTERPRETER_ENERGY_PER_NRG);
+        type Context = v1::ReceiveContext<Vec<u8>>;
+        let result = v1::invoke_receive::<_, _, _, _, Context, Context>(
+            self.artifact.clone(),
+            context,
+            v1::ReceiveInvocation {
+                amount:       call.amount,
+                receive_name: receive_name.as_receive_name(),
+                parameter:    call.parameter.as_ref(),
+                energy:       engine::InterpreterEnergy::from(energy),
+            },
+            instance_state,
+            v1::ReceiveParams {
+                max_parameter_size:           u16::MAX.into(),
+                limit_logs_and_return_values: false,
+                support_queries:              true,
+            },
+        )
+        .map_err(|e| LocalExecutionError::Engine(e.to_string()))?;
+        let used_energy = |remaining: u64| Energy {
+            energy: energy.saturating_sub(remaining) / INTERPRETER_ENERGY_PER_NRG,
+        };
+        let outcome = match result {
+            v1::ReceiveResult::Success {
+                logs,
+                state_changed,
+                return_value,
+                remaining_energy,
+            } => {
+                if state_changed {
+                    self.state = mutable_state.freeze(&mut loader, &mut trie::EmptyCollector);
+                }
+                self.balance = self.balance_after(call.amount);
+                LocalOutcome::Success {
+                    return_value,
+                    events: collect_events(&logs),
+                    state_changed,
+                    used_energy: used_energy(remaining_energy.into()),
+                }
+            }
+            v1::ReceiveResult::Reject {
+                reason,
+                return_value,
+                remaining_energy,
+            } => LocalOutcome::Reject {
+                reason,
+                return_value,
+                used_energy: used_energy(remaining_energy.into()),
+            },
+            v1::ReceiveResult::Trap {
+                error,
+                remaining_energy,
+            } => LocalOutcome::Trap {
+                error:       error.to_string(),
+                used_energy: used_energy(remaining_energy.into()),
+            },
+            v1::ReceiveResult::OutOfEnergy => LocalOutcome::OutOfEnergy {
+                used_energy: call.energy,
+            },
+            v1::ReceiveResult::Interrupt {
+                logs,
+                remaining_energy,
+                ..
+            } => LocalOutcome::Interrupted {
+                events:      collect_events(&logs),
+                used_energy: used_energy(remaining_energy.into()),
+            },
+        };
+        Ok(outcome)
+    }
+
+    /// Execute a sequence of calls, each against the state resulting from the
+    /// previous successful calls. Calls that do not succeed leave the
+    /// instance unchanged.
+    pub fn simulate<'a>(
+        &mut self,
+        calls: impl IntoIterator<Item = &'a LocalCall>,
+    ) -> Result<Vec<LocalOutcome>, LocalExecutionError> {
+        calls.into_iter().map(|call| self.call(call)).collect()
+    }
+
+    fn balance_after(&self, amount: Amount) -> Amount {
+        Amount::from_micro_ccd(self.balance.micro_ccd().saturating_add(amount.micro_ccd()))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The outcome of a dry-run of an init function.
+pub enum InitOutcome {
+    /// The instance would be created with the given state.
+    Success {
+        state:       trie::PersistentState,
+        events:      Vec<ContractEvent>,
+        used_energy: Energy,
+    },
+    /// The init function rejected with the given error code.
+    Reject {
+        reason:       i32,
+        return_value: Vec<u8>,
+        used_energy:  Energy,
+    },
+    /// The execution of the init function failed at runtime.
+    Trap { error: String, used_energy: Energy },
+    /// The init function ran out of energy.
+    OutOfEnergy { used_energy: Energy },
+}
+
+impl InitOutcome {
+    /// The energy used by the init function.
+    pub fn used_energy(&self) -> Energy {
+        match self {
+            InitOutcome::Success { used_energy, .. } => *used_energy,
+            InitOutcome::Reject { used_energy, .. } => *used_energy,
+            InitOutcome::Trap { used_energy, .. } => *used_energy,
+            InitOutcome::OutOfEnergy { used_energy } => *used_energy,
+        }
+    }
+}
+
+/// Execute the init function of a V1 module locally, as it would be executed
+/// by the given payload sent by `sender` at `slot_time`. The module
+/// reference of the payload is ignored in favour of the given module.
+pub fn dry_run_init(
+    module: &WasmModule,
+    payload: &InitContractPayload,
+    sender: AccountAddress,
+    slot_time: Timestamp,
+    energy: Energy,
+) -> Result<InitOutcome, LocalExecutionError> {
+    let artifact = compile(module)?;
+    let context = engine::v0::InitContext {
+        metadata:        ChainMetadata { slot_time },
+        init_origin:     sender,
+        // An empty list of policies.
+        sender_policies: vec![0u8, 0],
+    };
+    let limit = energy.energy.saturating_mul(INTERPRETER_ENERGY_PER_NRG);
+    let result = v1::invoke_init(
+        artifact,
+        context,
+        v1::InitInvocation {
+            amount:    payload.amount,
+            init_name: payload.init_name.as_contract_name().get_chain_name(),
+            parameter: payload.param.as_ref(),
+            energy:    engine::InterpreterEnergy::from(limit),
+        },
+        false,
+        memory_loader(),
+    )
+    .map_err(|e| LocalExecutionError::Engine(e.to_string()))?;
+    let used_energy = |remaining: u64| Energy {
+        energy: limit.saturating_sub(remaining) / INTERPRETER_ENERGY_PER_NRG,
+    };
+    let outcome = match result {
+        v1::InitResult::Success {
+            logs,
+            mut state,
+            remaining_energy,
+            ..
+        } => InitOutcome::Success {
+            state:       state.freeze(&mut memory_loader(), &mut trie::EmptyCollector),
+            events:      collect_events(&logs),
+            used_energy: used_energy(remaining_energy.into()),
+        },
+        v1::InitResult::Reject {
+            reason,
+            return_value,
+            remaining_energy,
+        } => InitOutcome::Reject {
+            reason,
+            return_value,
+            used_energy: used_energy(remaining_energy.into()),
+        },
+        v1::InitResult::Trap {
+            error,
+            remaining_energy,
+        } => InitOutcome::Trap {
+            error:       error.to_string(),
+            used_energy: used_energy(remaining_energy.into()),
+        },
+        v1::InitResult::OutOfEnergy => InitOutcome::OutOfEnergy {
+            used_energy: energy,
+        },
+    };
+    Ok(outcome)
+}
+
+/// Dry-run the init function of a module deployed on the chain, using the
+/// slot time of the given block. The energy limit is
+/// [`DEFAULT_INVOKE_ENERGY`](crate::types::smart_contracts::DEFAULT_INVOKE_ENERGY).
+pub async fn dry_run_init_on_chain(
+    client: &mut v2::Client,
+    payload: &InitContractPayload,
+    sender: AccountAddress,
+    bi: impl IntoBlockIdentifier,
+) -> Result<InitOutcome, LocalExecutionError> {
+    let module = client.get_module_source(&payload.mod_ref, bi).await?;
+    let block_info = client.get_block_info(module.block_hash).await?.response;
+    dry_run_init(
+        &module.response,
+        payload,
+        sender,
+        slot_time(block_info.block_slot_time),
+        crate::types::smart_contracts::DEFAULT_INVOKE_ENERGY,
+    )
+}
+
+/// Build the state trie of an instance from its key-value pairs, as returned
+/// by [`get_instance_state`](v2::Client::get_instance_state).
+fn build_state(
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+) -> Result<trie::PersistentState, LocalExecutionError> {
+    let mut loader = memory_loader();
+    let mut state = trie::MutableState::initial_state();
+    {
+        let mut inner = state.get_inner(&mut loader);
+        for (key, value) in entries {
+            inner
+                .insert(&mut loader, &key, value)
+                .map_err(|_| LocalExecutionError::Engine("Unable to build the state.".into()))?;
+        }
+    }
+    Ok(state.freeze(&mut loader, &mut trie::EmptyCollector))
+}
+
+fn collect_events(logs: &engine::v0::Logs) -> Vec<ContractEvent> {
+    logs.iterate().map(|log| ContractEvent::from(log.clone())).collect()
+}
@@ -8,3 +8,25 @@ pub use concordium_smart_contract_engine as engine;
 
 /// Functionality that is common to on and off-chain smart contracts.
 pub use concordium_base::contracts_common as common;
+
+/// Decoding of contract events using the schemas embedded in modules.
+pub mod events;
+
+/// Local execution of contract instances using state fetched from the node.
+pub mod local;
+
+/// Inspection, decoding and comparison of the state of contract instances.
+pub mod state;
+
+/// Deployment of modules and initialization of contract instances.
+pub mod deploy;
+
+/// The history of contract instances, from their creation onwards.
+pub mod history;
+
+/// Reconstruction and rendering of the call trees of contract executions.
+pub mod trace;
+
+/// Generation of typed contract clients from schemas, for use in build
+/// scripts.
+pub mod bindings;
@@ -0,0 +1,282 @@
+//! Inspection of the state of contract instances.
+//!
+//! The state of a V0 instance is a single byte array, whereas the state of a
+//! V1 instance is a trie of key-value pairs. [`instance_state`] fetches
+//! either kind as an [`InstanceState`], whose values can be decoded using a
+//! type implementing [`Deserial`] or a schema [`Type`].
+//!
+//! The state of an instance in two blocks can be compared with
+//! [`diff_instance_state`], which lists the entries that were added, removed
+//! or changed in between.
+use crate::{
+    contract_client::SchemaError,
+    endpoints::QueryResult,
+    types::{smart_contracts::InstanceInfo, ContractAddress},
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::contracts_common::{
+    self,
+    schema::{Type, VersionedModuleSchema},
+    Cursor, Deserial, ParseResult,
+};
+use futures::TryStreamExt;
+use std::collections::BTreeMap;
+
+/// Decode a value of the state using a schema type.
+pub fn value_to_json(ty: &Type, value: &[u8]) -> ParseResult<serde_json::Value> {
+    ty.to_json(&mut Cursor::new(value))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The key-value pairs of the state of a V1 instance, ordered by key.
+pub struct StateMap {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl FromIterator<(Vec<u8>, Vec<u8>)> for StateMap {
+    fn from_iter<T: IntoIterator<Item = (Vec<u8>, Vec<u8>)>>(iter: T) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<(Vec<u8>, Vec<u8>)> for StateMap {
+    fn extend<T: IntoIterator<Item = (Vec<u8>, Vec<u8>)>>(&mut self, iter: T) {
+        self.entries.extend(iter)
+    }
+}
+
+impl StateMap {
+    /// The number of entries in the state.
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    /// Whether the state has no entries.
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    /// The value stored under the key, if any.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> { self.entries.get(key).map(Vec::as_slice) }
+
+    /// All entries, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.entries.iter().map(|(k, v)| (k.as_slice(), v.as_slice()))
+    }
+
+    /// The entries whose key starts with the given prefix, ordered by key.
+    /// Collections in the state, such as the maps and sets of
+    /// `concordium-std`, store their entries under a common prefix.
+    pub fn with_prefix<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (&'a [u8], &'a [u8])> + 'a {
+        self.entries
+            .range(prefix.to_vec()..)
+            .take_while(move |(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+    }
+
+    /// Decode the value stored under the key.
+    pub fn decode<T: Deserial>(&self, key: &[u8]) -> ParseResult<Option<T>> {
+        self.get(key).map(contracts_common::from_bytes).transpose()
+    }
+
+    /// Decode the values of all entries whose key starts with the given
+    /// prefix.
+    pub fn decode_prefix<T: Deserial>(&self, prefix: &[u8]) -> ParseResult<Vec<(Vec<u8>, T)>> {
+        self.with_prefix(prefix)
+            .map(|(k, v)| Ok((k.to_vec(), contracts_common::from_bytes(v)?)))
+            .collect()
+    }
+
+    /// Decode the value stored under the key using a schema type.
+    pub fn decode_json(&self, key: &[u8], ty: &Type) -> ParseResult<Option<serde_json::Value>> {
+        self.get(key).map(|value| value_to_json(ty, value)).transpose()
+    }
+
+    /// Compare the state to a later state of the same instance.
+    pub fn diff(&self, after: &StateMap) -> StateDiff {
+        let mut diff = StateDiff::default();
+        for (key, old) in &self.entries {
+            match after.entries.get(key) {
+                None => {
+                    diff.removed.insert(key.clone(), old.clone());
+                }
+                Some(new) if new != old => {
+                    diff.changed.insert(key.clone(), (old.clone(), new.clone()));
+                }
+                Some(_) => (),
+            }
+        }
+        for (key, new) in &after.entries {
+            if !self.entries.contains_key(key) {
+                diff.added.insert(key.clone(), new.clone());
+            }
+        }
+        diff
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The difference between two states of an instance.
+pub struct StateDiff {
+    /// Entries only present in the later state.
+    pub added:   BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Entries only present in the earlier state.
+    pub removed: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Entries whose value changed, with the old and the new value.
+    pub changed: BTreeMap<Vec<u8>, (Vec<u8>, Vec<u8>)>,
+}
+
+impl StateDiff {
+    /// Whether the states are equal.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The state of a contract instance.
+pub enum InstanceState {
+    /// The state of a V0 instance, which is a single byte array.
+    V0 { model: Vec<u8> },
+    /// The state of a V1 instance.
+    V1(StateMap),
+}
+
+impl InstanceState {
+    /// Decode the model of a V0 instance. Returns [`None`] for V1 instances.
+    pub fn decode_model<T: Deserial>(&self) -> ParseResult<Option<T>> {
+        match self {
+            InstanceState::V0 { model } => contracts_common::from_bytes(model).map(Some),
+            InstanceState::V1(_) => Ok(None),
+        }
+    }
+
+    /// Decode the model of a V0 instance using the state schema of the
+    /// contract in the schema of its module. The contract name is without the
+    /// `init_` prefix. Only V0 modules have state schemas, so this returns
+    /// [`None`] for V1 instances. Their entries can be decoded with
+    /// [`StateMap::decode_json`] instead.
+    pub fn model_json(
+        &self,
+        schema: &VersionedModuleSchema,
+        contract_name: &str,
+    ) -> Result<Option<serde_json::Value>, SchemaError> {
+        let InstanceState::V0 { model } = self else {
+            return Ok(None);
+        };
+        let VersionedModuleSchema::V0(module) = schema else {
+            return Err(SchemaError::NoSchema);
+        };
+        let ty = module
+            .contracts
+            .get(contract_name)
+            .and_then(|contract| contract.state.as_ref())
+            .ok_or(SchemaError::NoSchema)?;
+        Ok(Some(value_to_json(ty, model)?))
+    }
+
+    /// The state as key-value pairs. The model of a V0 instance is a single
+    /// entry with the empty key.
+    pub fn entries(&self) -> StateMap {
+        match self {
+            InstanceState::V0 { model } => std::iter::once((Vec::new(), model.clone())).collect(),
+            InstanceState::V1(map) => map.clone(),
+        }
+    }
+
+    /// Compare the state to a later state of the same instance.
+    pub fn diff(&self, after: &InstanceState) -> StateDiff {
+        self.entries().diff(&after.entries())
+    }
+}
+
+/// Fetch the state of the instance in the given block.
+pub async fn instance_state(
+    client: &mut v2::Client,
+    address: ContractAddress,
+    bi: impl IntoBlockIdentifier,
+) -> QueryResult<InstanceState> {
+    let info = client.get_instance_info(address, bi).await?;
+    match info.response {
+        InstanceInfo::V0 { model, .. } => Ok(InstanceState::V0 { model }),
+        InstanceInfo::V1 { .. } => {
+            let map = client
+                .get_instance_state(address, info.block_hash)
+                .await?
+                .response
+                .try_collect::<StateMap>()
+                .await?;
+            Ok(InstanceState::V1(map))
+        }
+    }
+}
+
+/// Compare the state of the instance in two blocks.
+pub async fn diff_instance_state(
+    client: &mut v2::Client,
+    address: ContractAddress,
+    before: impl IntoBlockIdentifier,
+    after: impl IntoBlockIdentifier,
+) -> QueryResult<StateDiff> {
+    let before = instance_state(client, address, before).await?;
+    let after = instance_state(client, address, after).await?;
+    Ok(before.diff(&after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_base::contracts_common::schema::ModuleV3;
+
+    fn state(entries: &[(&[u8], &[u8])]) -> StateMap {
+        entries.iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = state(&[(b"a", b"1"), (b"b", b"2"), (b"c", b"3")]);
+        let after = state(&[(b"a", b"1"), (b"b", b"4"), (b"d", b"5")]);
+        let diff = before.diff(&after);
+        assert_eq!(diff.added, state(&[(b"d", b"5")]).entries);
+        assert_eq!(diff.removed, state(&[(b"c", b"3")]).entries);
+        assert_eq!(
+            diff.changed,
+            [(b"b".to_vec(), (b"2".to_vec(), b"4".to_vec()))]
+                .into_iter()
+                .collect()
+        );
+        // The reverse diff swaps additions and removals.
+        let reverse = after.diff(&before);
+        assert_eq!(reverse.added, diff.removed);
+        assert_eq!(reverse.removed, diff.added);
+    }
+
+    #[test]
+    fn test_diff_empty() {
+        let map = state(&[(b"a", b"1"), (b"", b"")]);
+        assert!(map.diff(&map).is_empty());
+        assert!(StateMap::default().diff(&StateMap::default()).is_empty());
+        let diff = StateMap::default().diff(&map);
+        assert_eq!(diff.added, map.entries);
+        assert!(diff.removed.is_empty() && diff.changed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_v0() {
+        let before = InstanceState::V0 { model: vec![1] };
+        let after = InstanceState::V0 { model: vec![2] };
+        let diff = before.diff(&after);
+        assert_eq!(diff.changed, [(Vec::new(), (vec![1], vec![2]))].into_iter().collect());
+        assert!(before.diff(&before).is_empty());
+    }
+
+    #[test]
+    fn test_model_json_v1() {
+        let schema = VersionedModuleSchema::V3(ModuleV3 {
+            contracts: BTreeMap::new(),
+        });
+        let state = InstanceState::V1(StateMap::default());
+        assert!(matches!(state.model_json(&schema, "contract"), Ok(None)));
+    }
+}
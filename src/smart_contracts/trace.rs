@@ -0,0 +1,453 @@
+//! Reconstruction of the call tree of a contract execution.
+//!
+//! The effects of a contract update are reported as a flat list of
+//! [`ContractTraceElement`]s, in the order in which the calls completed. When
+//! a V1 contract calls another contract or transfers CCD, its execution is
+//! [`Interrupted`](ContractTraceElement::Interrupted), the elements of the
+//! operation follow, and the execution is then
+//! [`Resumed`](ContractTraceElement::Resumed). A [`CallTree`] recovers this
+//! nesting, so that each call lists the calls, transfers and upgrades it made
+//! in order.
+//!
+//! The events of the calls can be decoded using a [`SchemaRegistry`], and
+//! the tree can be rendered as indented text using its [`Display`]
+//! implementation, or as JSON using [`CallTree::to_json`].
+//!
+//! [`Display`]: std::fmt::Display
+use super::events::SchemaRegistry;
+use crate::{
+    endpoints::QueryResult,
+    types::{
+        smart_contracts::{
+            ContractEvent, InvokeContractResult, ModuleReference, OwnedParameter, OwnedReceiveName,
+        },
+        AccountTransactionEffects, Address, BlockItemSummary, BlockItemSummaryDetails,
+        ContractAddress, ContractTraceElement,
+    },
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::{
+    common::{types::Amount, SerdeSerialize},
+    contracts_common::AccountAddress,
+};
+use std::fmt;
+
+#[derive(Debug, thiserror::Error)]
+#[error("The trace is not well-nested: {0}")]
+/// The trace elements do not describe a well-nested execution.
+pub struct MalformedTrace(String);
+
+#[derive(Debug, Clone, SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+/// An event logged by a contract, together with its decoded value if a schema
+/// was available.
+pub struct TraceEvent {
+    pub event:   ContractEvent,
+    pub decoded: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+/// A call of an entrypoint of a contract instance.
+pub struct Call {
+    pub address:      ContractAddress,
+    /// The account or contract that made the call.
+    pub instigator:   Address,
+    pub receive_name: OwnedReceiveName,
+    pub message:      OwnedParameter,
+    pub amount:       Amount,
+    /// All events logged by the call, in order. These include the events
+    /// logged before each interrupt.
+    pub events:       Vec<TraceEvent>,
+    /// The operations performed by the call, in order.
+    pub children:     Vec<TraceNode>,
+}
+
+#[derive(Debug, Clone, SerdeSerialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+/// A node of a [`CallTree`].
+pub enum TraceNode {
+    /// A call of a contract entrypoint.
+    Call(Call),
+    /// A transfer of CCD from a contract to an account.
+    Transfer {
+        from:   ContractAddress,
+        to:     AccountAddress,
+        amount: Amount,
+    },
+    /// An upgrade of the module of a contract.
+    Upgrade {
+        address: ContractAddress,
+        from:    ModuleReference,
+        to:      ModuleReference,
+    },
+    /// An operation that was started by the contract but failed, and whose
+    /// effects were therefore rolled back. The trace does not contain the
+    /// details of failed operations.
+    Failed { address: ContractAddress },
+}
+
+#[derive(Debug, Clone, Default, SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+/// The calls made in a contract execution, as a tree.
+pub struct CallTree {
+    /// The top-level operations of the execution. This is usually a single
+    /// call.
+    pub roots: Vec<TraceNode>,
+}
+
+/// A call whose execution has been interrupted, and whose completion has not
+/// yet been seen.
+struct Frame {
+    address:  ContractAddress,
+    events:   Vec<ContractEvent>,
+    children: Vec<TraceNode>,
+    /// Whether the call is waiting to be resumed.
+    waiting:  bool,
+}
+
+/// Add the node to the call that is currently executing, or to the roots if
+/// there is none.
+fn attach(stack: &mut [Frame], roots: &mut Vec<TraceNode>, node: TraceNode) {
+    match stack.last_mut() {
+        Some(frame) => frame.children.push(node),
+        None => roots.push(node),
+    }
+}
+
+fn trace_events(events: impl IntoIterator<Item = ContractEvent>) -> Vec<TraceEvent> {
+    events
+        .into_iter()
+        .map(|event| TraceEvent {
+            event,
+            decoded: None,
+        })
+        .collect()
+}
+
+impl CallTree {
+    /// Build the tree from the elements of a successful contract execution.
+    pub fn new<'a>(
+        elements: impl IntoIterator<Item = &'a ContractTraceElement>,
+    ) -> Result<Self, MalformedTrace> {
+        let mut roots = Vec::new();
+        let mut stack: Vec<Frame> = Vec::new();
+        for element in elements {
+            match element {
+                ContractTraceElement::Interrupted { address, events } => match stack.last_mut() {
+                    Some(frame) if frame.address == *address && !frame.waiting => {
+                        frame.events.extend(events.iter().cloned());
+                        frame.waiting = true;
+                    }
+                    _ => stack.push(Frame {
+                        address:  *address,
+                        events:   events.clone(),
+                        children: Vec::new(),
+                        waiting:  true,
+                    }),
+                },
+                ContractTraceElement::Resumed { address, success } => {
+                    let Some(frame) = stack
+                        .last_mut()
+                        .filter(|frame| frame.address == *address && frame.waiting)
+                    else {
+                        return Err(MalformedTrace(format!(
+                            "{address} resumed without an interrupt"
+                        )));
+                    };
+                    frame.waiting = false;
+                    if !success {
+                        frame.children.push(TraceNode::Failed { address: *address });
+                    }
+                }
+                ContractTraceElement::Updated { data } => {
+                    let (mut events, children) = match stack.last() {
+                        Some(frame) if frame.address == data.address && !frame.waiting => {
+                            let frame = stack.pop().expect("The stack is not empty.");
+                            (frame.events, frame.children)
+                        }
+                        _ => (Vec::new(), Vec::new()),
+                    };
+                    events.extend(data.events.iter().cloned());
+                    let call = Call {
+                        address:      data.address,
+                        instigator:   data.instigator,
+                        receive_name: data.receive_name.clone(),
+                        message:      data.message.clone(),
+                        amount:       data.amount,
+                        events:       trace_events(events),
+                        children,
+                    };
+                    attach(&mut stack, &mut roots, TraceNode::Call(call));
+                }
+                ContractTraceElement::Transferred { from, amount, to } => attach(
+                    &mut stack,
+                    &mut roots,
+                    TraceNode::Transfer {
+                        from:   *from,
+                        to:     *to,
+                        amount: *amount,
+                    },
+                ),
+                ContractTraceElement::Upgraded { address, from, to } => attach(
+                    &mut stack,
+                    &mut roots,
+                    TraceNode::Upgrade {
+                        address: *address,
+                        from:    *from,
+                        to:      *to,
+                    },
+                ),
+            }
+        }
+        if let Some(frame) = stack.last() {
+            return Err(MalformedTrace(format!("{} did not complete", frame.address)));
+        }
+        Ok(Self { roots })
+    }
+
+    /// Build the tree of a successful invocation. Returns [`None`] if the
+    /// invocation failed, since there is no trace in that case.
+    pub fn from_invoke_result(
+        result: &InvokeContractResult,
+    ) -> Option<Result<Self, MalformedTrace>> {
+        match result {
+            InvokeContractResult::Success { events, .. } => Some(Self::new(events)),
+            InvokeContractResult::Failure { .. } => None,
+        }
+    }
+
+    /// Build the tree of a contract update transaction. Returns [`None`] if
+    /// the transaction is not a successful contract update.
+    pub fn from_summary(summary: &BlockItemSummary) -> Option<Result<Self, MalformedTrace>> {
+        let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+            return None;
+        };
+        match &at.effects {
+            AccountTransactionEffects::ContractUpdateIssued { effects } => Some(Self::new(effects)),
+            _ => None,
+        }
+    }
+
+    /// Decode the events of all calls using the registry. The schemas of
+    /// contracts not yet known to the registry are looked up in the given
+    /// block. Events that cannot be decoded are left undecoded.
+    pub async fn decode_events(
+        &mut self,
+        registry: &mut SchemaRegistry,
+        client: &mut v2::Client,
+        bi: impl IntoBlockIdentifier + Copy,
+    ) -> QueryResult<()> {
+        let mut pending: Vec<&mut TraceNode> = self.roots.iter_mut().collect();
+        while let Some(node) = pending.pop() {
+            if let TraceNode::Call(call) = node {
+                let events: Vec<ContractEvent> =
+                    call.events.iter().map(|e| e.event.clone()).collect();
+                let decoded = registry
+                    .decode_events(client, call.address, &events, bi)
+                    .await?;
+                for (event, decoded) in call.events.iter_mut().zip(decoded) {
+                    event.decoded = decoded.value.ok();
+                }
+                pending.extend(call.children.iter_mut());
+            }
+        }
+        Ok(())
+    }
+
+    /// The tree as a JSON value.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("Serialization of a call tree does not fail.")
+    }
+}
+
+fn fmt_address(address: &Address) -> String {
+    match address {
+        Address::Account(account) => account.to_string(),
+        Address::Contract(contract) => contract.to_string(),
+    }
+}
+
+fn fmt_node(f: &mut fmt::Formatter, node: &TraceNode, depth: usize) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+    match node {
+        TraceNode::Call(call) => {
+            writeln!(
+                f,
+                "{indent}{} {} from {} with {}",
+                call.address,
+                call.receive_name,
+                fmt_address(&call.instigator),
+                call.amount
+            )?;
+            for event in &call.events {
+                match &event.decoded {
+                    Some(value) => writeln!(f, "{indent}  event {value}")?,
+                    None => writeln!(f, "{indent}  event {}", hex::encode(event.event.as_ref()))?,
+                }
+            }
+            for child in &call.children {
+                fmt_node(f, child, depth + 1)?;
+            }
+            Ok(())
+        }
+        TraceNode::Transfer { from, to, amount } => {
+            writeln!(f, "{indent}transfer {amount} from {from} to {to}")
+        }
+        TraceNode::Upgrade { address, from, to } => {
+            writeln!(f, "{indent}upgrade {address} from module {from} to {to}")
+        }
+        TraceNode::Failed { address } => writeln!(f, "{indent}failed operation of {address}"),
+    }
+}
+
+impl fmt::Display for CallTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for node in &self.roots {
+            fmt_node(f, node, 0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{smart_contracts::WasmVersion, InstanceUpdatedEvent};
+
+    const ACCOUNT: AccountAddress = AccountAddress([0u8; 32]);
+
+    fn contract(index: u64) -> ContractAddress { ContractAddress::new(index, 0) }
+
+    fn event(byte: u8) -> ContractEvent { ContractEvent::from(vec![byte]) }
+
+    fn interrupted(index: u64, events: Vec<ContractEvent>) -> ContractTraceElement {
+        ContractTraceElement::Interrupted {
+            address: contract(index),
+            events,
+        }
+    }
+
+    fn resumed(index: u64, success: bool) -> ContractTraceElement {
+        ContractTraceElement::Resumed {
+            address: contract(index),
+            success,
+        }
+    }
+
+    fn updated(
+        index: u64,
+        instigator: Address,
+        events: Vec<ContractEvent>,
+    ) -> ContractTraceElement {
+        ContractTraceElement::Updated {
+            data: InstanceUpdatedEvent {
+                contract_version: WasmVersion::V1,
+                address:          contract(index),
+                instigator,
+                amount:           Amount::from_micro_ccd(0),
+                message:          OwnedParameter::empty(),
+                receive_name:     OwnedReceiveName::new_unchecked("c.f".into()),
+                events,
+            },
+        }
+    }
+
+    /// The single call at the root of the tree.
+    fn root_call(tree: &CallTree) -> &Call {
+        assert_eq!(tree.roots.len(), 1);
+        match &tree.roots[0] {
+            TraceNode::Call(call) => call,
+            node => panic!("Expected a call, got {node:?}."),
+        }
+    }
+
+    fn child_call(call: &Call, i: usize) -> &Call {
+        match &call.children[i] {
+            TraceNode::Call(call) => call,
+            node => panic!("Expected a call, got {node:?}."),
+        }
+    }
+
+    fn events(call: &Call) -> Vec<ContractEvent> {
+        call.events.iter().map(|e| e.event.clone()).collect()
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        // Account calls A, which calls B, which calls C.
+        let trace = [
+            interrupted(0, vec![event(0)]),
+            interrupted(1, vec![event(1)]),
+            updated(2, Address::Contract(contract(1)), vec![event(2)]),
+            resumed(1, true),
+            updated(1, Address::Contract(contract(0)), vec![event(3)]),
+            resumed(0, true),
+            updated(0, Address::Account(ACCOUNT), vec![event(4)]),
+        ];
+        let tree = CallTree::new(&trace).unwrap();
+        let a = root_call(&tree);
+        assert_eq!(a.address, contract(0));
+        assert_eq!(events(a), [event(0), event(4)]);
+        assert_eq!(a.children.len(), 1);
+        let b = child_call(a, 0);
+        assert_eq!(b.address, contract(1));
+        assert_eq!(events(b), [event(1), event(3)]);
+        assert_eq!(b.children.len(), 1);
+        let c = child_call(b, 0);
+        assert_eq!(c.address, contract(2));
+        assert_eq!(events(c), [event(2)]);
+        assert!(c.children.is_empty());
+    }
+
+    #[test]
+    fn test_reentrancy() {
+        // A calls itself, and the inner call makes a transfer.
+        let trace = [
+            interrupted(0, vec![event(0)]),
+            interrupted(0, vec![event(1)]),
+            ContractTraceElement::Transferred {
+                from:   contract(0),
+                amount: Amount::from_micro_ccd(1),
+                to:     ACCOUNT,
+            },
+            resumed(0, true),
+            updated(0, Address::Contract(contract(0)), vec![event(2)]),
+            resumed(0, true),
+            updated(0, Address::Account(ACCOUNT), vec![event(3)]),
+        ];
+        let tree = CallTree::new(&trace).unwrap();
+        let outer = root_call(&tree);
+        assert_eq!(outer.instigator, Address::Account(ACCOUNT));
+        assert_eq!(events(outer), [event(0), event(3)]);
+        assert_eq!(outer.children.len(), 1);
+        let inner = child_call(outer, 0);
+        assert_eq!(inner.instigator, Address::Contract(contract(0)));
+        assert_eq!(events(inner), [event(1), event(2)]);
+        assert!(matches!(inner.children[..], [TraceNode::Transfer { .. }]));
+    }
+
+    #[test]
+    fn test_failed_operation() {
+        // An operation of A fails, after which A completes.
+        let trace = [
+            interrupted(0, vec![event(0)]),
+            resumed(0, false),
+            updated(0, Address::Account(ACCOUNT), vec![event(1)]),
+        ];
+        let tree = CallTree::new(&trace).unwrap();
+        let a = root_call(&tree);
+        assert_eq!(events(a), [event(0), event(1)]);
+        assert!(matches!(
+            a.children[..],
+            [TraceNode::Failed { address }] if address == contract(0)
+        ));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(CallTree::new(&[resumed(0, true)]).is_err());
+        assert!(CallTree::new(&[interrupted(0, Vec::new())]).is_err());
+        assert!(CallTree::new(&[interrupted(0, Vec::new()), resumed(1, true)]).is_err());
+    }
+}
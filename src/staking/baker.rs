@@ -0,0 +1,336 @@
+//! Management of the lifecycle of a baker.
+//!
+//! Fresh [`BakerCredentials`] for an account are created with
+//! [`prepare_credentials`], and should be stored in the format the node
+//! expects with [`write_baker_credentials`] before they are used. The baker is
+//! then registered with [`register_baker`], which sends the `ConfigureBaker`
+//! transaction with the proofs of ownership of the keys.
+//!
+//! The keys can later be rotated with [`update_baker_keys`], the stake, pool
+//! commissions and metadata changed with [`update_baker`], and the baker
+//! removed with [`remove_baker`].
+use super::{
+    check_commissions, pool_parameters, send_and_wait, StakingError, StakingValidationError,
+};
+use crate::{
+    types::{
+        transactions::{send, ConfigureBakerPayload},
+        AccountInfo, AccountStakingInfo, AccountTransactionEffects, AmountFraction, BakerEvent,
+        BakerId, BlockItemSummary, BlockItemSummaryDetails, OpenStatus, PoolParameters, UrlText,
+        WalletAccount,
+    },
+    v2::{self, BlockIdentifier},
+};
+pub use concordium_base::base::{BakerCredentials, BakerKeyPairs};
+use concordium_base::{
+    common::types::{Amount, TransactionTime},
+    contracts_common::AccountAddress,
+};
+use std::{io::Write, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when reading or writing a baker credentials file.
+pub enum BakerKeyFileError {
+    #[error("Unable to access the credentials file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unable to parse the credentials file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Write the baker credentials to a file in the JSON format that the node
+/// expects. The file must not already exist. On unix it is created readable
+/// and writable only by the owner, since it contains the private keys.
+pub fn write_baker_credentials(
+    path: impl AsRef<Path>,
+    credentials: &BakerCredentials,
+) -> Result<(), BakerKeyFileError> {
+    let bytes = serde_json::to_vec_pretty(credentials)?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Read baker credentials from a file in the format the node uses.
+pub fn read_baker_credentials(
+    path: impl AsRef<Path>,
+) -> Result<BakerCredentials, BakerKeyFileError> {
+    let bytes = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Generate fresh baker keys.
+pub fn generate_keys() -> BakerKeyPairs { BakerKeyPairs::generate(&mut rand::thread_rng()) }
+
+/// Generate fresh baker credentials for the account. The id of a baker is the
+/// index of its account, so the credentials can be stored before the keys are
+/// registered with [`register_baker`] or [`update_baker_keys`].
+pub fn prepare_credentials(account: &AccountInfo) -> BakerCredentials {
+    BakerCredentials {
+        baker_id: BakerId {
+            id: account.account_index,
+        },
+        keys:     generate_keys(),
+    }
+}
+
+fn check_stake(
+    params: &PoolParameters,
+    account: &AccountInfo,
+    stake: Amount,
+) -> Result<(), StakingValidationError> {
+    if stake < params.minimum_equity_capital {
+        return Err(StakingValidationError::InsufficientStake {
+            stake,
+            minimum: params.minimum_equity_capital,
+        });
+    }
+    if stake > account.account_amount {
+        return Err(StakingValidationError::InsufficientBalance {
+            stake,
+            balance: account.account_amount,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+/// The configuration of a new baker.
+pub struct BakerRegistration {
+    /// The amount to stake.
+    pub capital:                        Amount,
+    /// Whether earnings should be added to the stake.
+    pub restake_earnings:               bool,
+    /// Whether the pool is open for delegators.
+    pub open_for_delegation:            OpenStatus,
+    /// A link to metadata about the baker.
+    pub metadata_url:                   UrlText,
+    pub transaction_fee_commission:     AmountFraction,
+    pub baking_reward_commission:       AmountFraction,
+    pub finalization_reward_commission: AmountFraction,
+}
+
+impl BakerRegistration {
+    /// Check that the registration is allowed for the account with the given
+    /// pool parameters.
+    pub fn validate(
+        &self,
+        params: &PoolParameters,
+        account: &AccountInfo,
+    ) -> Result<(), StakingValidationError> {
+        if account.account_stake.is_some() {
+            return Err(StakingValidationError::AlreadyStaking);
+        }
+        check_stake(params, account, self.capital)?;
+        check_commissions(
+            &params.commission_bounds,
+            Some(self.transaction_fee_commission),
+            Some(self.baking_reward_commission),
+            Some(self.finalization_reward_commission),
+        )
+    }
+
+    /// Construct the payload that registers the baker with the given keys.
+    pub fn payload(&self, keys: &BakerKeyPairs, sender: AccountAddress) -> ConfigureBakerPayload {
+        let mut payload = ConfigureBakerPayload::new();
+        payload
+            .set_capital(self.capital)
+            .set_restake_earnings(self.restake_earnings)
+            .set_open_for_delegation(self.open_for_delegation)
+            .add_keys(keys, sender, &mut rand::thread_rng())
+            .set_metadata_url(self.metadata_url.clone())
+            .set_transaction_fee_commission(self.transaction_fee_commission)
+            .set_baking_reward_commission(self.baking_reward_commission)
+            .set_finalization_reward_commission(self.finalization_reward_commission);
+        payload
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Changes to the configuration of an existing baker. Fields that are
+/// [`None`] are left unchanged.
+pub struct BakerUpdate {
+    /// The new stake. Use [`remove_baker`] to remove the baker.
+    pub capital:                        Option<Amount>,
+    pub restake_earnings:               Option<bool>,
+    pub open_for_delegation:            Option<OpenStatus>,
+    pub metadata_url:                   Option<UrlText>,
+    pub transaction_fee_commission:     Option<AmountFraction>,
+    pub baking_reward_commission:       Option<AmountFraction>,
+    pub finalization_reward_commission: Option<AmountFraction>,
+}
+
+impl BakerUpdate {
+    fn is_empty(&self) -> bool {
+        self.capital.is_none()
+            && self.restake_earnings.is_none()
+            && self.open_for_delegation.is_none()
+            && self.metadata_url.is_none()
+            && self.transaction_fee_commission.is_none()
+            && self.baking_reward_commission.is_none()
+            && self.finalization_reward_commission.is_none()
+    }
+
+    /// Check that the update is allowed for the account with the given pool
+    /// parameters.
+    pub fn validate(
+        &self,
+        params: &PoolParameters,
+        account: &AccountInfo,
+    ) -> Result<(), StakingValidationError> {
+        if !matches!(account.account_stake, Some(AccountStakingInfo::Baker { .. })) {
+            return Err(StakingValidationError::NotStaking);
+        }
+        if self.is_empty() {
+            return Err(StakingValidationError::EmptyConfiguration);
+        }
+        if let Some(capital) = self.capital {
+            check_stake(params, account, capital)?;
+        }
+        check_commissions(
+            &params.commission_bounds,
+            self.transaction_fee_commission,
+            self.baking_reward_commission,
+            self.finalization_reward_commission,
+        )
+    }
+
+    /// Construct the payload for the update.
+    pub fn payload(&self) -> ConfigureBakerPayload {
+        let mut payload = ConfigureBakerPayload::new();
+        if let Some(capital) = self.capital {
+            payload.set_capital(capital);
+        }
+        if let Some(restake_earnings) = self.restake_earnings {
+            payload.set_restake_earnings(restake_earnings);
+        }
+        if let Some(open_status) = self.open_for_delegation {
+            payload.set_open_for_delegation(open_status);
+        }
+        if let Some(url) = &self.metadata_url {
+            payload.set_metadata_url(url.clone());
+        }
+        if let Some(commission) = self.transaction_fee_commission {
+            payload.set_transaction_fee_commission(commission);
+        }
+        if let Some(commission) = self.baking_reward_commission {
+            payload.set_baking_reward_commission(commission);
+        }
+        if let Some(commission) = self.finalization_reward_commission {
+            payload.set_finalization_reward_commission(commission);
+        }
+        payload
+    }
+}
+
+/// Extract the id of the baker from the outcome of a transaction that
+/// configured a baker.
+fn configured_baker_id(summary: &BlockItemSummary) -> Option<BakerId> {
+    let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+        return None;
+    };
+    let AccountTransactionEffects::BakerConfigured { data } = &at.effects else {
+        return None;
+    };
+    data.iter().find_map(|event| match event {
+        BakerEvent::BakerAdded { data } => Some(data.keys_event.baker_id),
+        BakerEvent::BakerKeysUpdated { data } => Some(data.baker_id),
+        _ => None,
+    })
+}
+
+async fn send_configure_baker(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    payload: ConfigureBakerPayload,
+    expiry: TransactionTime,
+) -> Result<BlockItemSummary, StakingError> {
+    let nonce = client
+        .get_next_account_sequence_number(&wallet.address)
+        .await?
+        .nonce;
+    let tx = send::configure_baker(wallet, wallet.address, nonce, expiry, payload);
+    let (_, summary) = send_and_wait(client, tx).await?;
+    Ok(summary)
+}
+
+/// Register the account as a baker with the given keys. This validates the
+/// registration against the current chain parameters and the state of the
+/// account, and waits until the transaction is finalized.
+///
+/// The keys should be stored, for example with [`prepare_credentials`] and
+/// [`write_baker_credentials`], before this is called, since the baker may be
+/// registered even if waiting for the outcome fails. The id of the registered
+/// baker is returned.
+pub async fn register_baker(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    registration: &BakerRegistration,
+    keys: &BakerKeyPairs,
+    expiry: TransactionTime,
+) -> Result<BakerId, StakingError> {
+    let account = client
+        .get_account_info(&wallet.address.into(), BlockIdentifier::LastFinal)
+        .await?;
+    let params = pool_parameters(client, account.block_hash).await?;
+    registration.validate(&params, &account.response)?;
+    let payload = registration.payload(keys, wallet.address);
+    let summary = send_configure_baker(client, wallet, payload, expiry).await?;
+    configured_baker_id(&summary)
+        .ok_or_else(|| StakingError::UnexpectedOutcome("The baker was not added.".into()))
+}
+
+/// Replace the keys of the baker with the given ones, which should then
+/// replace the ones used by the node. As with [`register_baker`], the keys
+/// should be stored before this is called.
+pub async fn update_baker_keys(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    keys: &BakerKeyPairs,
+    expiry: TransactionTime,
+) -> Result<BakerId, StakingError> {
+    let account = client
+        .get_account_info(&wallet.address.into(), BlockIdentifier::LastFinal)
+        .await?
+        .response;
+    if !matches!(account.account_stake, Some(AccountStakingInfo::Baker { .. })) {
+        return Err(StakingValidationError::NotStaking.into());
+    }
+    let mut payload = ConfigureBakerPayload::new();
+    payload.add_keys(keys, wallet.address, &mut rand::thread_rng());
+    let summary = send_configure_baker(client, wallet, payload, expiry).await?;
+    configured_baker_id(&summary).ok_or_else(|| {
+        StakingError::UnexpectedOutcome("The baker keys were not updated.".into())
+    })
+}
+
+/// Update the configuration of the baker after validating the changes against
+/// the current chain parameters.
+pub async fn update_baker(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    update: &BakerUpdate,
+    expiry: TransactionTime,
+) -> Result<BlockItemSummary, StakingError> {
+    let account = client
+        .get_account_info(&wallet.address.into(), BlockIdentifier::LastFinal)
+        .await?;
+    let params = pool_parameters(client, account.block_hash).await?;
+    update.validate(&params, &account.response)?;
+    send_configure_baker(client, wallet, update.payload(), expiry).await
+}
+
+/// Remove the baker. The stake is unlocked after the cooldown period.
+pub async fn remove_baker(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    expiry: TransactionTime,
+) -> Result<BlockItemSummary, StakingError> {
+    send_configure_baker(client, wallet, ConfigureBakerPayload::new_remove_baker(), expiry).await
+}
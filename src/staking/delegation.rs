@@ -0,0 +1,345 @@
+//! Planning and management of delegation.
+//!
+//! Candidate pools can be listed with [`list_pools`], which reports how much
+//! more stake each pool can accept. Delegation is added, changed and removed
+//! with [`add_delegation`], [`update_delegation`] and [`remove_delegation`].
+//! Before any transaction is sent the target pool is checked to be open and
+//! to have enough capacity for the new stake, so that transactions that would
+//! be rejected are not sent.
+use super::{send_and_wait, StakingError, StakingValidationError};
+use crate::{
+    types::{
+        transactions::{send, ConfigureDelegationPayload},
+        AccountInfo, AccountStakingInfo, BakerId, BakerPoolStatus, BlockItemSummary,
+        CommissionRates, DelegationTarget, OpenStatus, StakePendingChange, WalletAccount,
+    },
+    v2::{self, BlockIdentifier, IntoBlockIdentifier},
+};
+use concordium_base::{
+    common::types::{Amount, TransactionTime},
+    contracts_common::AccountAddress,
+};
+use futures::TryStreamExt;
+
+#[derive(Debug, Clone)]
+/// A baker pool that can be delegated to.
+pub struct PoolCandidate {
+    pub baker_id:              BakerId,
+    pub baker_address:         AccountAddress,
+    pub open_status:           OpenStatus,
+    pub commission_rates:      CommissionRates,
+    /// The stake of the baker itself.
+    pub equity_capital:        Amount,
+    /// The stake delegated to the pool.
+    pub delegated_capital:     Amount,
+    /// The maximum stake that can be delegated to the pool.
+    pub delegated_capital_cap: Amount,
+}
+
+impl PoolCandidate {
+    /// The amount of stake that can still be delegated to the pool.
+    pub fn available_capacity(&self) -> Amount {
+        Amount::from_micro_ccd(
+            self.delegated_capital_cap
+                .micro_ccd()
+                .saturating_sub(self.delegated_capital.micro_ccd()),
+        )
+    }
+
+    /// Whether new delegators can join the pool.
+    pub fn is_open(&self) -> bool { self.open_status == OpenStatus::OpenForAll }
+}
+
+impl From<BakerPoolStatus> for PoolCandidate {
+    fn from(status: BakerPoolStatus) -> Self {
+        Self {
+            baker_id:              status.baker_id,
+            baker_address:         status.baker_address,
+            open_status:           status.pool_info.open_status,
+            commission_rates:      status.pool_info.commission_rates,
+            equity_capital:        status.baker_equity_capital,
+            delegated_capital:     status.delegated_capital,
+            delegated_capital_cap: status.delegated_capital_cap,
+        }
+    }
+}
+
+/// List all baker pools in the given block. If `only_open` is set then only
+/// pools that are open for new delegators are returned.
+pub async fn list_pools(
+    client: &mut v2::Client,
+    bi: impl IntoBlockIdentifier,
+    only_open: bool,
+) -> Result<Vec<PoolCandidate>, StakingError> {
+    let bakers = client.get_baker_list(bi).await?;
+    let block = bakers.block_hash;
+    let bakers = bakers
+        .response
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(v2::QueryError::from)?;
+    let mut pools = Vec::with_capacity(bakers.len());
+    for baker_id in bakers {
+        let pool = PoolCandidate::from(client.get_pool_info(block, baker_id).await?.response);
+        if !only_open || pool.is_open() {
+            pools.push(pool);
+        }
+    }
+    Ok(pools)
+}
+
+/// The stake the account currently delegates to the given target, if any.
+fn current_delegation(account: &AccountInfo, target: &DelegationTarget) -> Option<Amount> {
+    match &account.account_stake {
+        Some(AccountStakingInfo::Delegated {
+            staked_amount,
+            delegation_target,
+            ..
+        }) if delegation_target == target => Some(*staked_amount),
+        _ => None,
+    }
+}
+
+/// Check that the account can delegate `stake` to the given pool. Stake the
+/// account already delegates to the pool counts towards the capacity.
+pub fn check_pool(
+    pool: &PoolCandidate,
+    account: &AccountInfo,
+    stake: Amount,
+) -> Result<(), StakingValidationError> {
+    let target = DelegationTarget::Baker {
+        baker_id: pool.baker_id,
+    };
+    let existing = current_delegation(account, &target);
+    if existing.is_none() && !pool.is_open() {
+        return Err(StakingValidationError::PoolClosed(pool.baker_id));
+    }
+    let existing = existing.map_or(0, |amount| amount.micro_ccd());
+    let available = pool.available_capacity().micro_ccd() + existing;
+    if stake.micro_ccd() > available {
+        return Err(StakingValidationError::PoolCapacityExceeded {
+            baker_id:  pool.baker_id,
+            requested: stake,
+            available: Amount::from_micro_ccd(available),
+        });
+    }
+    Ok(())
+}
+
+/// Check that delegating `stake` to `target` is possible, querying the state
+/// of the pool in the given block if necessary.
+pub async fn check_target(
+    client: &mut v2::Client,
+    bi: impl IntoBlockIdentifier,
+    account: &AccountInfo,
+    target: &DelegationTarget,
+    stake: Amount,
+) -> Result<(), StakingError> {
+    if stake.micro_ccd() == 0 {
+        return Err(StakingValidationError::EmptyConfiguration.into());
+    }
+    if stake > account.account_amount {
+        return Err(StakingValidationError::InsufficientBalance {
+            stake,
+            balance: account.account_amount,
+        }
+        .into());
+    }
+    if let DelegationTarget::Baker { baker_id } = target {
+        let pool = PoolCandidate::from(client.get_pool_info(bi, *baker_id).await?.response);
+        check_pool(&pool, account, stake)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+/// Changes to an existing delegation. Fields that are [`None`] are left
+/// unchanged.
+pub struct DelegationUpdate {
+    /// The new stake. Use [`remove_delegation`] to stop delegating.
+    pub capital:           Option<Amount>,
+    pub restake_earnings:  Option<bool>,
+    pub delegation_target: Option<DelegationTarget>,
+}
+
+impl DelegationUpdate {
+    /// Construct the payload for the update.
+    pub fn payload(&self) -> ConfigureDelegationPayload {
+        let mut payload = ConfigureDelegationPayload::new();
+        if let Some(capital) = self.capital {
+            payload.set_capital(capital);
+        }
+        if let Some(restake_earnings) = self.restake_earnings {
+            payload.set_restake_earnings(restake_earnings);
+        }
+        if let Some(target) = self.delegation_target {
+            payload.set_delegation_target(target);
+        }
+        payload
+    }
+}
+
+async fn send_configure_delegation(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    payload: ConfigureDelegationPayload,
+    expiry: TransactionTime,
+) -> Result<BlockItemSummary, StakingError> {
+    let nonce = client
+        .get_next_account_sequence_number(&wallet.address)
+        .await?
+        .nonce;
+    let tx = send::configure_delegation(wallet, wallet.address, nonce, expiry, payload);
+    let (_, summary) = send_and_wait(client, tx).await?;
+    Ok(summary)
+}
+
+/// Start delegating `capital` to the given target, after checking that the
+/// target pool is open and has enough capacity.
+pub async fn add_delegation(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    capital: Amount,
+    restake_earnings: bool,
+    target: DelegationTarget,
+    expiry: TransactionTime,
+) -> Result<BlockItemSummary, StakingError> {
+    let account = client
+        .get_account_info(&wallet.address.into(), BlockIdentifier::LastFinal)
+        .await?;
+    if account.response.account_stake.is_some() {
+        return Err(StakingValidationError::AlreadyStaking.into());
+    }
+    check_target(client, account.block_hash, &account.response, &target, capital).await?;
+    let mut payload = ConfigureDelegationPayload::new();
+    payload
+        .set_capital(capital)
+        .set_restake_earnings(restake_earnings)
+        .set_delegation_target(target);
+    send_configure_delegation(client, wallet, payload, expiry).await
+}
+
+/// Update an existing delegation. If the stake or the target changes then
+/// the (new) target pool is checked to have enough capacity.
+pub async fn update_delegation(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    update: &DelegationUpdate,
+    expiry: TransactionTime,
+) -> Result<BlockItemSummary, StakingError> {
+    if update.capital.is_none()
+        && update.restake_earnings.is_none()
+        && update.delegation_target.is_none()
+    {
+        return Err(StakingValidationError::EmptyConfiguration.into());
+    }
+    let account = client
+        .get_account_info(&wallet.address.into(), BlockIdentifier::LastFinal)
+        .await?;
+    let Some(AccountStakingInfo::Delegated {
+        staked_amount,
+        delegation_target,
+        ..
+    }) = &account.response.account_stake
+    else {
+        return Err(StakingValidationError::NotStaking.into());
+    };
+    if update.capital.is_some() || update.delegation_target.is_some() {
+        let stake = update.capital.unwrap_or(*staked_amount);
+        let target = update.delegation_target.unwrap_or(*delegation_target);
+        check_target(client, account.block_hash, &account.response, &target, stake).await?;
+    }
+    send_configure_delegation(client, wallet, update.payload(), expiry).await
+}
+
+/// Stop delegating. The stake is unlocked after the cooldown period.
+pub async fn remove_delegation(
+    client: &mut v2::Client,
+    wallet: &WalletAccount,
+    expiry: TransactionTime,
+) -> Result<BlockItemSummary, StakingError> {
+    let payload = ConfigureDelegationPayload::new_remove_delegation();
+    send_configure_delegation(client, wallet, payload, expiry).await
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A pending change to the stake of an account, either as a baker or as a
+/// delegator.
+pub struct PendingChangeReport {
+    /// The current stake of the account.
+    pub current_stake:  Amount,
+    /// The stake after the change takes effect. This is zero if the stake is
+    /// being removed.
+    pub new_stake:      Amount,
+    /// When the change takes effect.
+    pub effective_time: chrono::DateTime<chrono::Utc>,
+    /// Whether the account is a baker, as opposed to a delegator.
+    pub is_baker:       bool,
+}
+
+impl PendingChangeReport {
+    /// The pending change of the account, if there is one.
+    pub fn of_account(account: &AccountInfo) -> Option<Self> {
+        let (current_stake, pending_change, is_baker) = match account.account_stake.as_ref()? {
+            AccountStakingInfo::Baker {
+                staked_amount,
+                pending_change,
+                ..
+            } => (*staked_amount, (*pending_change)?, true),
+            AccountStakingInfo::Delegated {
+                staked_amount,
+                pending_change,
+                ..
+            } => (*staked_amount, (*pending_change)?, false),
+        };
+        let new_stake = match pending_change {
+            StakePendingChange::ReduceStake { new_stake, .. } => new_stake,
+            StakePendingChange::RemoveStake { .. } => Amount::from_micro_ccd(0),
+        };
+        Some(Self {
+            current_stake,
+            new_stake,
+            effective_time: pending_change.effective_time(),
+            is_baker,
+        })
+    }
+
+    /// Whether the stake is being removed entirely.
+    pub fn is_removal(&self) -> bool { self.new_stake.micro_ccd() == 0 }
+
+    /// The time remaining until the change takes effect, relative to `now`.
+    /// This is negative if the change should already have taken effect.
+    pub fn time_remaining(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::Duration {
+        self.effective_time - now
+    }
+}
+
+impl std::fmt::Display for PendingChangeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let role = if self.is_baker { "Baker" } else { "Delegation" };
+        if self.is_removal() {
+            write!(
+                f,
+                "{role} stake of {} CCD will be removed at {}.",
+                self.current_stake, self.effective_time
+            )
+        } else {
+            write!(
+                f,
+                "{role} stake will be reduced from {} CCD to {} CCD at {}.",
+                self.current_stake, self.new_stake, self.effective_time
+            )
+        }
+    }
+}
+
+/// Look up the pending change to the stake of the account in the given
+/// block, if any.
+pub async fn pending_change(
+    client: &mut v2::Client,
+    address: AccountAddress,
+    bi: impl IntoBlockIdentifier,
+) -> Result<Option<PendingChangeReport>, StakingError> {
+    let account = client.get_account_info(&address.into(), bi).await?.response;
+    Ok(PendingChangeReport::of_account(&account))
+}
@@ -0,0 +1,312 @@
+//! Analysis of the distribution of stake between pools.
+//!
+//! The [`stake_distribution`] function queries all pools in a block and
+//! computes, for each of them, the effective stake after applying the
+//! leverage and capital bounds of the [`PoolParameters`], and how much more
+//! stake can be delegated to it. The resulting [`StakeDistribution`] also
+//! provides network-wide concentration metrics.
+//!
+//! The effective stake computed here is the stake the pool would have if a
+//! payday happened in the queried block. The effective stake used for the
+//! current reward period is reported by the node in the
+//! [`current_payday_status`](BakerPoolStatus::current_payday_status) of the
+//! pool.
+use super::{pool_parameters, StakingError};
+use crate::{
+    types::{hashes::BlockHash, BakerId, BakerPoolStatus, OpenStatus, PoolParameters},
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::common::types::Amount;
+use futures::TryStreamExt;
+use rust_decimal::prelude::ToPrimitive;
+
+#[derive(Debug, Clone, Copy)]
+/// The stake of a single pool.
+pub struct PoolStake {
+    pub baker_id:              BakerId,
+    pub open_status:           OpenStatus,
+    /// The stake of the baker itself.
+    pub equity_capital:        Amount,
+    /// The stake delegated to the pool.
+    pub delegated_capital:     Amount,
+    /// The maximum stake allowed by the leverage bound.
+    pub leverage_cap:          Amount,
+    /// The maximum stake allowed by the capital bound.
+    pub capital_cap:           Amount,
+    /// The stake of the pool after applying the leverage and capital bounds.
+    pub effective_stake:       Amount,
+    /// The maximum stake that can be delegated to the pool, as computed by
+    /// the node.
+    pub delegated_capital_cap: Amount,
+}
+
+impl PoolStake {
+    /// Compute the stake of the pool given its status and the pool parameters
+    /// in effect.
+    pub fn new(status: &BakerPoolStatus, params: &PoolParameters) -> Self {
+        let equity = status.baker_equity_capital.micro_ccd();
+        let leverage = &params.leverage_bound;
+        let leverage_cap =
+            u128::from(equity) * u128::from(leverage.numerator) / u128::from(leverage.denominator);
+        let leverage_cap = u64::try_from(leverage_cap).unwrap_or(u64::MAX);
+        let capital_cap = (rust_decimal::Decimal::from(params.capital_bound.bound)
+            * rust_decimal::Decimal::from(status.all_pool_total_capital.micro_ccd()))
+        .floor()
+        .to_u64()
+        .unwrap_or(u64::MAX);
+        let total = equity + status.delegated_capital.micro_ccd();
+        Self {
+            baker_id:              status.baker_id,
+            open_status:           status.pool_info.open_status,
+            equity_capital:        status.baker_equity_capital,
+            delegated_capital:     status.delegated_capital,
+            leverage_cap:          Amount::from_micro_ccd(leverage_cap),
+            capital_cap:           Amount::from_micro_ccd(capital_cap),
+            effective_stake:       Amount::from_micro_ccd(total.min(leverage_cap).min(capital_cap)),
+            delegated_capital_cap: status.delegated_capital_cap,
+        }
+    }
+
+    /// The total stake of the pool, before applying any bounds.
+    pub fn total_capital(&self) -> Amount {
+        Amount::from_micro_ccd(self.equity_capital.micro_ccd() + self.delegated_capital.micro_ccd())
+    }
+
+    /// The amount of stake that can still be delegated to the pool.
+    pub fn remaining_capacity(&self) -> Amount {
+        Amount::from_micro_ccd(
+            self.delegated_capital_cap
+                .micro_ccd()
+                .saturating_sub(self.delegated_capital.micro_ccd()),
+        )
+    }
+
+    /// Whether part of the stake of the pool does not count towards its
+    /// effective stake because of the leverage or capital bound.
+    pub fn is_capped(&self) -> bool { self.effective_stake < self.total_capital() }
+}
+
+#[derive(Debug, Clone)]
+/// The distribution of stake between all pools in a block.
+pub struct StakeDistribution {
+    pub block_hash:         BlockHash,
+    /// The pools, ordered by decreasing effective stake.
+    pub pools:              Vec<PoolStake>,
+    /// The stake delegated to passive delegation.
+    pub passive_delegation: Amount,
+}
+
+impl StakeDistribution {
+    fn sum(&self, f: impl Fn(&PoolStake) -> Amount) -> Amount {
+        Amount::from_micro_ccd(self.pools.iter().map(|pool| f(pool).micro_ccd()).sum())
+    }
+
+    /// The total equity capital of all bakers.
+    pub fn total_equity(&self) -> Amount { self.sum(|pool| pool.equity_capital) }
+
+    /// The total stake delegated to pools, excluding passive delegation.
+    pub fn total_delegated(&self) -> Amount { self.sum(|pool| pool.delegated_capital) }
+
+    /// The total effective stake of all pools.
+    pub fn total_effective(&self) -> Amount { self.sum(|pool| pool.effective_stake) }
+
+    /// The share of the total effective stake held by each pool, in the order
+    /// of [`pools`](Self::pools).
+    pub fn shares(&self) -> Vec<f64> {
+        let total = self.total_effective().micro_ccd() as f64;
+        self.pools
+            .iter()
+            .map(|pool| {
+                if total > 0.0 {
+                    pool.effective_stake.micro_ccd() as f64 / total
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// The Herfindahl-Hirschman index of the effective stake, i.e., the sum of
+    /// the squares of the shares of the pools. This ranges from `1/n` for
+    /// `n` pools of equal stake to 1 if a single pool holds all the stake.
+    pub fn herfindahl_index(&self) -> f64 { self.shares().iter().map(|s| s * s).sum() }
+
+    /// The smallest number of pools that together hold more than the given
+    /// share of the effective stake. With a threshold of `1/3` this is the
+    /// number of bakers that can stop finalization.
+    pub fn nakamoto_coefficient(&self, threshold: f64) -> usize {
+        let mut accumulated = 0.0;
+        for (count, share) in self.shares().into_iter().enumerate() {
+            accumulated += share;
+            if accumulated > threshold {
+                return count + 1;
+            }
+        }
+        self.pools.len()
+    }
+
+    /// The share of the effective stake held by the `n` largest pools.
+    pub fn top_share(&self, n: usize) -> f64 { self.shares().iter().take(n).sum() }
+}
+
+/// Compute the distribution of stake between all pools in the given block.
+pub async fn stake_distribution(
+    client: &mut v2::Client,
+    bi: impl IntoBlockIdentifier,
+) -> Result<StakeDistribution, StakingError> {
+    let bakers = client.get_baker_list(bi).await?;
+    let block_hash = bakers.block_hash;
+    let bakers = bakers
+        .response
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(v2::QueryError::from)?;
+    let params = pool_parameters(client, block_hash).await?;
+    let mut pools = Vec::with_capacity(bakers.len());
+    for baker_id in bakers {
+        let status = client.get_pool_info(block_hash, baker_id).await?.response;
+        pools.push(PoolStake::new(&status, &params));
+    }
+    pools.sort_by(|a, b| b.effective_stake.cmp(&a.effective_stake));
+    let passive_delegation = client
+        .get_passive_delegation_info(block_hash)
+        .await?
+        .response
+        .delegated_capital;
+    Ok(StakeDistribution {
+        block_hash,
+        pools,
+        passive_delegation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        hashes::HashBytes, AmountFraction, BakerPoolInfo, CapitalBound, CommissionRanges,
+        CommissionRates, InclusiveRange, LeverageFactor, PoolPendingChange,
+    };
+    use concordium_base::contracts_common::AccountAddress;
+
+    fn fraction(parts_per_hundred_thousand: u32) -> AmountFraction {
+        AmountFraction::new_unchecked(parts_per_hundred_thousand)
+    }
+
+    /// Pool parameters with the given leverage bound and a capital bound in
+    /// parts per hundred thousand.
+    fn params(leverage: (u64, u64), capital_bound: u32) -> PoolParameters {
+        let range = || InclusiveRange {
+            min: fraction(0),
+            max: fraction(100_000),
+        };
+        PoolParameters {
+            passive_finalization_commission: fraction(0),
+            passive_baking_commission:       fraction(0),
+            passive_transaction_commission:  fraction(0),
+            commission_bounds:               CommissionRanges {
+                finalization: range(),
+                baking:       range(),
+                transaction:  range(),
+            },
+            minimum_equity_capital:          Amount::from_micro_ccd(0),
+            capital_bound:                   CapitalBound {
+                bound: fraction(capital_bound),
+            },
+            leverage_bound:                  LeverageFactor::new(leverage.0, leverage.1).unwrap(),
+        }
+    }
+
+    fn status(equity: u64, delegated: u64, all_pool_total: u64) -> BakerPoolStatus {
+        BakerPoolStatus {
+            baker_id:                   BakerId { id: 1.into() },
+            baker_address:              AccountAddress([1u8; 32]),
+            baker_equity_capital:       Amount::from_micro_ccd(equity),
+            delegated_capital:          Amount::from_micro_ccd(delegated),
+            delegated_capital_cap:      Amount::from_micro_ccd(equity * 2),
+            pool_info:                  BakerPoolInfo {
+                open_status:      OpenStatus::OpenForAll,
+                metadata_url:     String::new().try_into().unwrap(),
+                commission_rates: CommissionRates {
+                    finalization: fraction(0),
+                    baking:       fraction(0),
+                    transaction:  fraction(0),
+                },
+            },
+            baker_stake_pending_change: PoolPendingChange::NoChange,
+            current_payday_status:      None,
+            all_pool_total_capital:     Amount::from_micro_ccd(all_pool_total),
+        }
+    }
+
+    /// A distribution of pools with the given effective stakes.
+    fn distribution(stakes: &[u64]) -> StakeDistribution {
+        let params = params((u64::MAX, 1), 100_000);
+        let total = stakes.iter().sum();
+        let pools = stakes
+            .iter()
+            .map(|&stake| PoolStake::new(&status(stake, 0, total), &params))
+            .collect();
+        StakeDistribution {
+            block_hash: HashBytes::new([0u8; 32]),
+            pools,
+            passive_delegation: Amount::from_micro_ccd(0),
+        }
+    }
+
+    #[test]
+    fn test_pool_stake_bounds() {
+        // Leverage bound of 3 allows 300, the capital bound of 10% of 2000
+        // allows 200.
+        let pool = PoolStake::new(&status(100, 900, 2000), &params((3, 1), 10_000));
+        assert_eq!(pool.leverage_cap, Amount::from_micro_ccd(300));
+        assert_eq!(pool.capital_cap, Amount::from_micro_ccd(200));
+        assert_eq!(pool.effective_stake, Amount::from_micro_ccd(200));
+        assert!(pool.is_capped());
+        assert_eq!(pool.remaining_capacity(), Amount::from_micro_ccd(0));
+        let pool = PoolStake::new(&status(100, 50, 2000), &params((3, 1), 10_000));
+        assert_eq!(pool.effective_stake, Amount::from_micro_ccd(150));
+        assert!(!pool.is_capped());
+        assert_eq!(pool.remaining_capacity(), Amount::from_micro_ccd(150));
+    }
+
+    #[test]
+    fn test_pool_stake_empty() {
+        // Without any stake all caps are zero.
+        let pool = PoolStake::new(&status(0, 0, 0), &params((3, 1), 10_000));
+        assert_eq!(pool.leverage_cap, Amount::from_micro_ccd(0));
+        assert_eq!(pool.capital_cap, Amount::from_micro_ccd(0));
+        assert_eq!(pool.effective_stake, Amount::from_micro_ccd(0));
+        assert!(!pool.is_capped());
+    }
+
+    #[test]
+    fn test_nakamoto_empty() {
+        let dist = distribution(&[]);
+        assert_eq!(dist.nakamoto_coefficient(1.0 / 3.0), 0);
+        assert!(dist.shares().is_empty());
+        assert_eq!(dist.herfindahl_index(), 0.0);
+        assert_eq!(dist.top_share(1), 0.0);
+    }
+
+    #[test]
+    fn test_nakamoto_single_pool() {
+        let dist = distribution(&[1000]);
+        assert_eq!(dist.shares(), [1.0]);
+        assert_eq!(dist.nakamoto_coefficient(1.0 / 3.0), 1);
+        assert_eq!(dist.herfindahl_index(), 1.0);
+    }
+
+    #[test]
+    fn test_nakamoto_one_third_boundary() {
+        // A pool with exactly a third of the stake does not exceed the
+        // threshold on its own.
+        let dist = distribution(&[100, 100, 100]);
+        assert_eq!(dist.nakamoto_coefficient(1.0 / 3.0), 2);
+        let dist = distribution(&[200, 200, 100, 100]);
+        assert_eq!(dist.nakamoto_coefficient(1.0 / 3.0), 2);
+        // Slightly more than a third is enough.
+        let dist = distribution(&[101, 100, 99]);
+        assert_eq!(dist.nakamoto_coefficient(1.0 / 3.0), 1);
+    }
+}
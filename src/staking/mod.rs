@@ -0,0 +1,145 @@
+//! Support for managing stake on the chain, either as a baker or as a
+//! delegator.
+//!
+//! The [`baker`] module drives the lifecycle of a baker, from generating keys
+//! and registering the baker to updating and removing it. Configurations are
+//! validated against the [`PoolParameters`] in effect on the chain before any
+//! transaction is sent.
+//!
+//! The [`delegation`] module supports choosing a pool to delegate to, and
+//! managing the delegation of an account, and the [`rewards`] module estimates
+//! the returns of staking with a pool. The [`payday`] module reports the
+//! rewards paid out at paydays, and the [`monitor`] module compares the
+//! blocks produced by a baker to those expected from its lottery power. The
+//! [`distribution`] module analyzes how stake is distributed between pools.
+use crate::{
+    types::{
+        hashes::{BlockHash, TransactionHash},
+        transactions::{AccountTransaction, EncodedPayload},
+        AmountFraction, BakerId, BlockItemSummary, CommissionRanges, InclusiveRange, PoolParameters,
+        RejectReason,
+    },
+    v2::{self, ChainParameters, IntoBlockIdentifier},
+};
+use concordium_base::common::types::Amount;
+
+pub mod baker;
+pub mod delegation;
+pub mod distribution;
+pub mod monitor;
+pub mod payday;
+pub mod rewards;
+
+#[derive(Debug, thiserror::Error)]
+/// A staking configuration that is not allowed by the current chain
+/// parameters, or by the state of the account.
+pub enum StakingValidationError {
+    #[error("The {name} commission {value} is outside the allowed range [{min}, {max}].")]
+    CommissionOutOfRange {
+        name:  &'static str,
+        value: AmountFraction,
+        min:   AmountFraction,
+        max:   AmountFraction,
+    },
+    #[error("The stake {stake} is below the minimum equity capital {minimum}.")]
+    InsufficientStake { stake: Amount, minimum: Amount },
+    #[error("The stake {stake} exceeds the balance {balance} of the account.")]
+    InsufficientBalance { stake: Amount, balance: Amount },
+    #[error("The account is already staking.")]
+    AlreadyStaking,
+    #[error("The account is not staking in the required role.")]
+    NotStaking,
+    #[error("The configuration does not contain any changes.")]
+    EmptyConfiguration,
+    #[error("The pool of baker {0} is not open for new delegators.")]
+    PoolClosed(BakerId),
+    #[error(
+        "Delegating {requested} to the pool of baker {baker_id} exceeds its remaining capacity \
+         {available}."
+    )]
+    PoolCapacityExceeded {
+        baker_id:  BakerId,
+        requested: Amount,
+        available: Amount,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+/// An error that can occur when managing stake.
+pub enum StakingError {
+    #[error("Invalid staking configuration: {0}")]
+    Invalid(#[from] StakingValidationError),
+    #[error("Unable to query the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Unable to send transaction: {0}")]
+    Send(#[from] v2::RPCError),
+    #[error("Transaction {hash} was rejected: {reason:?}")]
+    Rejected {
+        hash:   TransactionHash,
+        reason: RejectReason,
+    },
+    #[error("Staking is not supported in the protocol version of the chain.")]
+    UnsupportedProtocol,
+    #[error("Unexpected transaction outcome: {0}")]
+    UnexpectedOutcome(String),
+}
+
+/// Get the pool parameters in effect in the given block. This fails with
+/// [`StakingError::UnsupportedProtocol`] before protocol version 4, since
+/// there are no pools in earlier protocol versions.
+pub async fn pool_parameters(
+    client: &mut v2::Client,
+    bi: impl IntoBlockIdentifier,
+) -> Result<PoolParameters, StakingError> {
+    match client.get_block_chain_parameters(bi).await?.response {
+        ChainParameters::V0(_) => Err(StakingError::UnsupportedProtocol),
+        ChainParameters::V1(params) => Ok(params.pool_parameters),
+        ChainParameters::V2(params) => Ok(params.pool_parameters),
+    }
+}
+
+/// Check that the given commission rates are in the ranges allowed by the
+/// chain. Rates that are [`None`] are not checked.
+pub fn check_commissions(
+    bounds: &CommissionRanges,
+    transaction: Option<AmountFraction>,
+    baking: Option<AmountFraction>,
+    finalization: Option<AmountFraction>,
+) -> Result<(), StakingValidationError> {
+    let check = |name: &'static str,
+                 value: Option<AmountFraction>,
+                 range: &InclusiveRange<AmountFraction>| {
+        match value {
+            Some(value) if value < range.min || value > range.max => {
+                Err(StakingValidationError::CommissionOutOfRange {
+                    name,
+                    value,
+                    min: range.min,
+                    max: range.max,
+                })
+            }
+            _ => Ok(()),
+        }
+    };
+    check("transaction fee", transaction, &bounds.transaction)?;
+    check("baking reward", baking, &bounds.baking)?;
+    check("finalization reward", finalization, &bounds.finalization)?;
+    Ok(())
+}
+
+/// Send the transaction and wait until it is finalized. A rejected
+/// transaction results in [`StakingError::Rejected`].
+pub(crate) async fn send_and_wait(
+    client: &mut v2::Client,
+    tx: AccountTransaction<EncodedPayload>,
+) -> Result<(BlockHash, BlockItemSummary), StakingError> {
+    let hash = client.send_account_transaction(tx).await?;
+    let (bh, summary) = client.wait_until_finalized(&hash).await?;
+    if let Some(reason) = summary.is_rejected_account_transaction() {
+        return Err(StakingError::Rejected {
+            hash,
+            reason: reason.clone(),
+        });
+    }
+    Ok((bh, summary))
+}
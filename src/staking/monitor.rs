@@ -0,0 +1,316 @@
+//! Monitoring of the performance of a baker.
+//!
+//! The [`BakerMonitor`] analyzes a window of recent finalized blocks and
+//! compares the number of blocks the baker produced to the number expected
+//! from its lottery power. It also reports how often the baker signed the
+//! finalization records included in the window, and raises [`BakerAlert`]s
+//! when the baker appears to have stopped working.
+//!
+//! The expected number of blocks is the sum of the lottery power of the baker
+//! over the blocks in the window. The lottery power is queried once per epoch
+//! where the epoch is known, i.e., from protocol version 6. For earlier
+//! blocks it is queried once per genesis index, so only at the start of the
+//! window and after each protocol update within it.
+use crate::{
+    endpoints::QueryResult,
+    types::{AbsoluteBlockHeight, BakerId, Epoch, GenesisIndex},
+    v2,
+};
+
+#[derive(Debug, Clone)]
+/// The performance of a baker over a window of blocks.
+pub struct BakerPerformance {
+    pub baker_id:              BakerId,
+    /// First block of the window.
+    pub start:                 AbsoluteBlockHeight,
+    /// Last block of the window.
+    pub end:                   AbsoluteBlockHeight,
+    /// Slot time of the first block of the window.
+    pub start_time:            chrono::DateTime<chrono::Utc>,
+    /// Slot time of the last block of the window.
+    pub end_time:              chrono::DateTime<chrono::Utc>,
+    /// Number of blocks baked by the baker in the window.
+    pub blocks_baked:          u64,
+    /// Expected number of blocks baked by the baker, based on its lottery
+    /// power.
+    pub expected_blocks:       f64,
+    /// The most recent block in the window baked by the baker, and its slot
+    /// time.
+    pub last_baked:            Option<(AbsoluteBlockHeight, chrono::DateTime<chrono::Utc>)>,
+    /// The number of finalization records in the window in which the baker
+    /// was a finalizer.
+    pub finalization_records:  u64,
+    /// The number of those finalization records that the baker signed.
+    pub finalization_signed:   u64,
+    /// The lottery power of the baker at the end of the window. This is
+    /// [`None`] if the baker is not in the current committee.
+    pub current_lottery_power: Option<f64>,
+}
+
+impl BakerPerformance {
+    /// The ratio of blocks baked to the expected number of blocks. Values
+    /// below 1 mean the baker was unlucky, or not baking.
+    pub fn luck(&self) -> Option<f64> {
+        (self.expected_blocks > 0.0).then(|| self.blocks_baked as f64 / self.expected_blocks)
+    }
+
+    /// The probability of baking at most as many blocks as the baker did, if
+    /// it was working correctly. Very small values indicate that the baker is
+    /// not baking, rather than being unlucky.
+    pub fn probability_of_result(&self) -> f64 {
+        // The number of blocks is well approximated by a Poisson distribution.
+        // The terms are summed in log space, since `exp(-lambda)` underflows
+        // for large windows.
+        let lambda = self.expected_blocks;
+        let log_lambda = lambda.ln();
+        let mut log_term = -lambda;
+        let mut log_cdf = log_term;
+        for k in 1..=self.blocks_baked {
+            log_term += log_lambda - (k as f64).ln();
+            let (high, low) = if log_cdf >= log_term {
+                (log_cdf, log_term)
+            } else {
+                (log_term, log_cdf)
+            };
+            log_cdf = high + (low - high).exp().ln_1p();
+        }
+        log_cdf.exp().min(1.0)
+    }
+
+    /// The fraction of finalization records the baker signed, if it was a
+    /// finalizer in any of them.
+    pub fn finalization_rate(&self) -> Option<f64> {
+        (self.finalization_records > 0)
+            .then(|| self.finalization_signed as f64 / self.finalization_records as f64)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A problem detected by the [`BakerMonitor`].
+pub enum BakerAlert {
+    /// The baker is not in the baking committee.
+    NotInCommittee,
+    /// The baker has not baked a block for longer than the configured limit,
+    /// although it was expected to.
+    NotBaking {
+        /// When the baker last baked a block in the window, if at all.
+        last_baked:      Option<chrono::DateTime<chrono::Utc>>,
+        /// The number of blocks the baker was expected to bake in the window.
+        expected_blocks: f64,
+    },
+    /// The baker signed fewer finalization records than the configured limit.
+    MissingFinalization { rate: f64 },
+}
+
+impl std::fmt::Display for BakerAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BakerAlert::NotInCommittee => write!(f, "The baker is not in the baking committee."),
+            BakerAlert::NotBaking {
+                last_baked: Some(time),
+                expected_blocks,
+            } => write!(
+                f,
+                "The baker has not baked since {time}, expected {expected_blocks:.1} blocks."
+            ),
+            BakerAlert::NotBaking {
+                last_baked: None,
+                expected_blocks,
+            } => write!(
+                f,
+                "The baker has not baked any blocks, expected {expected_blocks:.1} blocks."
+            ),
+            BakerAlert::MissingFinalization { rate } => write!(
+                f,
+                "The baker only signed {:.1}% of finalization records.",
+                rate * 100.0
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Configuration of the monitoring of a single baker.
+pub struct BakerMonitor {
+    pub baker_id:               BakerId,
+    /// The number of blocks to analyze.
+    pub window:                 u64,
+    /// Raise [`BakerAlert::NotBaking`] if the baker has not baked for longer
+    /// than this.
+    pub max_time_without_block: chrono::Duration,
+    /// Only raise [`BakerAlert::NotBaking`] if at least this many blocks
+    /// were expected in the window, so that bakers with little stake do not
+    /// cause spurious alerts.
+    pub min_expected_blocks:    f64,
+    /// Raise [`BakerAlert::MissingFinalization`] if the baker signed a smaller
+    /// fraction of finalization records than this.
+    pub min_finalization_rate:  f64,
+}
+
+impl BakerMonitor {
+    /// Construct a monitor with default thresholds: a window of 1000 blocks,
+    /// an alert after an hour without blocks if at least 3 were expected, and
+    /// an alert if fewer than 90% of finalization records are signed.
+    pub fn new(baker_id: BakerId) -> Self {
+        Self {
+            baker_id,
+            window: 1000,
+            max_time_without_block: chrono::Duration::hours(1),
+            min_expected_blocks: 3.0,
+            min_finalization_rate: 0.9,
+        }
+    }
+
+    /// Analyze the window of blocks ending at the given height.
+    pub async fn analyze(
+        &self,
+        client: &mut v2::Client,
+        end: AbsoluteBlockHeight,
+    ) -> QueryResult<BakerPerformance> {
+        let first = end.height.saturating_sub(self.window.saturating_sub(1));
+        let start = AbsoluteBlockHeight::from(first);
+        let start_time = client.get_block_info(start).await?.response.block_slot_time;
+        let mut performance = BakerPerformance {
+            baker_id: self.baker_id,
+            start,
+            end,
+            start_time,
+            end_time:              start_time,
+            blocks_baked:          0,
+            expected_blocks:       0.0,
+            last_baked:            None,
+            finalization_records:  0,
+            finalization_signed:   0,
+            current_lottery_power: None,
+        };
+        let mut epoch: Option<(GenesisIndex, Option<Epoch>)> = None;
+        let mut lottery_power = None;
+        for height in start.height..=end.height {
+            let height = AbsoluteBlockHeight::from(height);
+            let info = client.get_block_info(height).await?.response;
+            performance.end_time = info.block_slot_time;
+            let block_epoch = Some((info.genesis_index, info.epoch));
+            if epoch != block_epoch {
+                epoch = block_epoch;
+                lottery_power = self.lottery_power(client, height).await?;
+            }
+            performance.current_lottery_power = lottery_power;
+            performance.expected_blocks += lottery_power.unwrap_or(0.0);
+            if info.block_baker == Some(self.baker_id) {
+                performance.blocks_baked += 1;
+                performance.last_baked = Some((height, info.block_slot_time));
+            }
+            if let Some(summary) = client.get_block_finalization_summary(height).await?.response {
+                if let Some(party) = summary
+                    .finalizers
+                    .iter()
+                    .find(|party| party.baker_id == self.baker_id)
+                {
+                    performance.finalization_records += 1;
+                    if party.signed {
+                        performance.finalization_signed += 1;
+                    }
+                }
+            }
+        }
+        Ok(performance)
+    }
+
+    async fn lottery_power(
+        &self,
+        client: &mut v2::Client,
+        height: AbsoluteBlockHeight,
+    ) -> QueryResult<Option<f64>> {
+        let birk = client.get_election_info(height).await?.response;
+        Ok(birk
+            .bakers
+            .iter()
+            .find(|baker| baker.baker_id == self.baker_id)
+            .map(|baker| baker.baker_lottery_power))
+    }
+
+    /// Compute the alerts for the given performance, relative to the given
+    /// current time.
+    pub fn alerts(
+        &self,
+        performance: &BakerPerformance,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<BakerAlert> {
+        let mut alerts = Vec::new();
+        if performance.current_lottery_power.is_none() {
+            alerts.push(BakerAlert::NotInCommittee);
+        }
+        let last_baked = performance.last_baked.map(|(_, time)| time);
+        let since = last_baked.unwrap_or(performance.start_time);
+        if now - since > self.max_time_without_block
+            && performance.expected_blocks >= self.min_expected_blocks
+        {
+            alerts.push(BakerAlert::NotBaking {
+                last_baked,
+                expected_blocks: performance.expected_blocks,
+            });
+        }
+        if let Some(rate) = performance.finalization_rate() {
+            if rate < self.min_finalization_rate {
+                alerts.push(BakerAlert::MissingFinalization { rate });
+            }
+        }
+        alerts
+    }
+
+    /// Analyze the window ending at the last finalized block and return the
+    /// performance together with any alerts.
+    pub async fn check(
+        &self,
+        client: &mut v2::Client,
+    ) -> QueryResult<(BakerPerformance, Vec<BakerAlert>)> {
+        let end = client.get_consensus_info().await?.last_finalized_block_height;
+        let performance = self.analyze(client, end).await?;
+        let alerts = self.alerts(&performance, chrono::Utc::now());
+        Ok((performance, alerts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn performance(blocks_baked: u64, expected_blocks: f64) -> BakerPerformance {
+        let now = chrono::Utc::now();
+        BakerPerformance {
+            baker_id: BakerId { id: 1.into() },
+            start: AbsoluteBlockHeight::from(0),
+            end: AbsoluteBlockHeight::from(0),
+            start_time: now,
+            end_time: now,
+            blocks_baked,
+            expected_blocks,
+            last_baked: None,
+            finalization_records: 0,
+            finalization_signed: 0,
+            current_lottery_power: None,
+        }
+    }
+
+    #[test]
+    fn test_probability_of_result_small() {
+        assert_eq!(performance(0, 0.0).probability_of_result(), 1.0);
+        assert_eq!(performance(3, 0.0).probability_of_result(), 1.0);
+        let p = performance(0, 2.0).probability_of_result();
+        assert!((p - (-2.0f64).exp()).abs() < 1e-12);
+        let p = performance(1, 2.0).probability_of_result();
+        assert!((p - 3.0 * (-2.0f64).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_probability_of_result_large() {
+        // `exp(-1000)` underflows, but the result is close to 1/2.
+        let p = performance(1000, 1000.0).probability_of_result();
+        assert!(p > 0.5 && p < 0.52, "{p}");
+        // About three standard deviations below the mean.
+        let p = performance(900, 1000.0).probability_of_result();
+        assert!(p > 1e-4 && p < 1e-2, "{p}");
+        let p = performance(1200, 1000.0).probability_of_result();
+        assert!(p > 0.99 && p <= 1.0, "{p}");
+    }
+}
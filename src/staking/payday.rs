@@ -0,0 +1,338 @@
+//! Attribution of the rewards paid out at paydays.
+//!
+//! At each payday the rewards accrued during the reward period are paid out to
+//! bakers and delegators, and the development charge to the foundation. These
+//! payouts are recorded as [`PaydayAccountReward`], [`PaydayPoolReward`] and
+//! [`PaydayFoundationReward`] special outcomes of the payday block. The
+//! functions in this module collect these outcomes for all paydays in a range
+//! of blocks and break them down per account and per pool.
+//!
+//! [`PaydayAccountReward`]: SpecialTransactionOutcome::PaydayAccountReward
+//! [`PaydayPoolReward`]: SpecialTransactionOutcome::PaydayPoolReward
+//! [`PaydayFoundationReward`]: SpecialTransactionOutcome::PaydayFoundationReward
+use super::rewards::{find_payday, Payday};
+use crate::{
+    endpoints::{QueryError, QueryResult, RPCError},
+    types::{AbsoluteBlockHeight, BakerId, SpecialTransactionOutcome},
+    v2,
+};
+use concordium_base::{common::types::Amount, contracts_common::AccountAddress};
+use futures::TryStreamExt;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Rewards split by their source.
+pub struct RewardBreakdown {
+    pub transaction_fees: Amount,
+    pub baking:           Amount,
+    pub finalization:     Amount,
+}
+
+impl Default for RewardBreakdown {
+    fn default() -> Self {
+        let zero = Amount::from_micro_ccd(0);
+        Self {
+            transaction_fees: zero,
+            baking:           zero,
+            finalization:     zero,
+        }
+    }
+}
+
+impl RewardBreakdown {
+    /// The sum of all the rewards, or [`None`] if it overflows.
+    pub fn total(&self) -> Option<Amount> {
+        self.transaction_fees
+            .checked_add(self.baking)?
+            .checked_add(self.finalization)
+    }
+
+    /// Add the rewards of another breakdown, or return [`None`] if any of the
+    /// sums overflows.
+    pub fn checked_add(&self, other: &RewardBreakdown) -> Option<Self> {
+        Some(Self {
+            transaction_fees: self.transaction_fees.checked_add(other.transaction_fees)?,
+            baking:           self.baking.checked_add(other.baking)?,
+            finalization:     self.finalization.checked_add(other.finalization)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The rewards paid out at a single payday.
+pub struct PaydayReport {
+    pub payday:            Payday,
+    /// Rewards paid to each account, either as a baker or as a delegator.
+    pub accounts:          BTreeMap<AccountAddress, RewardBreakdown>,
+    /// Rewards of each pool, before they are distributed to the baker and the
+    /// delegators. Passive delegation is represented by [`None`].
+    pub pools:             Vec<(Option<BakerId>, RewardBreakdown)>,
+    /// The foundation account and the development charge paid to it.
+    pub foundation_reward: Option<(AccountAddress, Amount)>,
+}
+
+impl PaydayReport {
+    /// Construct the report from the special outcomes of a payday block.
+    /// Returns [`None`] if the rewards of an account overflow, which does not
+    /// happen for the outcomes of a valid block.
+    pub fn from_outcomes<'a>(
+        payday: Payday,
+        outcomes: impl IntoIterator<Item = &'a SpecialTransactionOutcome>,
+    ) -> Option<Self> {
+        let mut report = Self {
+            payday,
+            accounts: BTreeMap::new(),
+            pools: Vec::new(),
+            foundation_reward: None,
+        };
+        for outcome in outcomes {
+            match outcome {
+                SpecialTransactionOutcome::PaydayAccountReward {
+                    account,
+                    transaction_fees,
+                    baker_reward,
+                    finalization_reward,
+                } => {
+                    let rewards = RewardBreakdown {
+                        transaction_fees: *transaction_fees,
+                        baking:           *baker_reward,
+                        finalization:     *finalization_reward,
+                    };
+                    let total = report.accounts.entry(*account).or_default();
+                    *total = total.checked_add(&rewards)?;
+                }
+                SpecialTransactionOutcome::PaydayPoolReward {
+                    pool_owner,
+                    transaction_fees,
+                    baker_reward,
+                    finalization_reward,
+                } => report.pools.push((*pool_owner, RewardBreakdown {
+                    transaction_fees: *transaction_fees,
+                    baking:           *baker_reward,
+                    finalization:     *finalization_reward,
+                })),
+                SpecialTransactionOutcome::PaydayFoundationReward {
+                    foundation_account,
+                    development_charge,
+                } => report.foundation_reward = Some((*foundation_account, *development_charge)),
+                _ => (),
+            }
+        }
+        Some(report)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Rewards paid out at all paydays in a range of blocks.
+pub struct RewardReport {
+    /// Reports for each payday, in increasing order of height.
+    pub paydays: Vec<PaydayReport>,
+}
+
+impl RewardReport {
+    /// The total rewards of each account over all the paydays, or [`None`] if
+    /// the total of an account overflows.
+    pub fn account_totals(&self) -> Option<BTreeMap<AccountAddress, RewardBreakdown>> {
+        let mut totals = BTreeMap::<AccountAddress, RewardBreakdown>::new();
+        for payday in &self.paydays {
+            for (account, breakdown) in &payday.accounts {
+                let total = totals.entry(*account).or_default();
+                *total = total.checked_add(breakdown)?;
+            }
+        }
+        Some(totals)
+    }
+
+    /// The rewards of the given account at each payday at which it received
+    /// any rewards.
+    pub fn account_rewards<'a>(
+        &'a self,
+        account: &'a AccountAddress,
+    ) -> impl Iterator<Item = (&'a Payday, &'a RewardBreakdown)> + 'a {
+        self.paydays.iter().filter_map(move |payday| {
+            payday
+                .accounts
+                .get(account)
+                .map(|breakdown| (&payday.payday, breakdown))
+        })
+    }
+}
+
+/// Collect the rewards of all paydays in the given range of heights
+/// (inclusive).
+pub async fn payday_rewards(
+    client: &mut v2::Client,
+    start: AbsoluteBlockHeight,
+    end: AbsoluteBlockHeight,
+) -> QueryResult<RewardReport> {
+    let mut paydays = Vec::new();
+    let mut height = end;
+    while height >= start {
+        let Some(payday) = find_payday(client, height).await? else {
+            break;
+        };
+        if payday.height < start {
+            break;
+        }
+        let outcomes = client
+            .get_block_special_events(payday.block_hash)
+            .await?
+            .response
+            .try_collect::<Vec<_>>()
+            .await?;
+        let payday_height = payday.height.height;
+        let report = PaydayReport::from_outcomes(payday, &outcomes).ok_or_else(|| {
+            RPCError::ParseError(anyhow::anyhow!("The rewards at height {payday_height} overflow."))
+        })?;
+        paydays.push(report);
+        if payday_height == 0 {
+            break;
+        }
+        height = AbsoluteBlockHeight::from(payday_height - 1);
+    }
+    paydays.reverse();
+    Ok(RewardReport { paydays })
+}
+
+/// Find the lowest finalized block with slot time at or after the given
+/// time. Returns [`QueryError::NotFound`] if there is no such block.
+pub async fn first_block_at_or_after(
+    client: &mut v2::Client,
+    time: chrono::DateTime<chrono::Utc>,
+) -> QueryResult<AbsoluteBlockHeight> {
+    let last = client.get_consensus_info().await?.last_finalized_block_height;
+    let last_time = client.get_block_info(last).await?.response.block_slot_time;
+    if last_time < time {
+        return Err(QueryError::NotFound);
+    }
+    let found = client
+        .find_at_lowest_height(..=last, |mut client, height| async move {
+            let info = client.get_block_info(height).await?.response;
+            Ok((info.block_slot_time >= time).then_some(height))
+        })
+        .await;
+    match found {
+        Err(e) if e.is_not_found() => Ok(last),
+        other => other,
+    }
+}
+
+/// Collect the rewards of all paydays whose block slot time is in the given
+/// time range (inclusive).
+pub async fn payday_rewards_in_time_range(
+    client: &mut v2::Client,
+    from: chrono::DateTime<chrono::Utc>,
+    to: chrono::DateTime<chrono::Utc>,
+) -> QueryResult<RewardReport> {
+    let empty = RewardReport {
+        paydays: Vec::new(),
+    };
+    let start = match first_block_at_or_after(client, from).await {
+        Ok(start) => start,
+        Err(e) if e.is_not_found() => return Ok(empty),
+        Err(e) => return Err(e),
+    };
+    let end = match first_block_at_or_after(client, to + chrono::Duration::milliseconds(1)).await {
+        Ok(after) if after.height == 0 => return Ok(empty),
+        Ok(after) => AbsoluteBlockHeight::from(after.height - 1),
+        Err(e) if e.is_not_found() => {
+            client.get_consensus_info().await?.last_finalized_block_height
+        }
+        Err(e) => return Err(e),
+    };
+    payday_rewards(client, start, end).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::hashes::HashBytes;
+
+    const BAKER: AccountAddress = AccountAddress([1u8; 32]);
+    const DELEGATOR: AccountAddress = AccountAddress([2u8; 32]);
+    const FOUNDATION: AccountAddress = AccountAddress([3u8; 32]);
+
+    fn payday(height: u64) -> Payday {
+        Payday {
+            block_hash: HashBytes::new([height as u8; 32]),
+            height:     AbsoluteBlockHeight::from(height),
+            time:       chrono::Utc::now(),
+        }
+    }
+
+    fn ccd(micro_ccd: u64) -> Amount { Amount::from_micro_ccd(micro_ccd) }
+
+    fn breakdown(transaction_fees: u64, baking: u64, finalization: u64) -> RewardBreakdown {
+        RewardBreakdown {
+            transaction_fees: ccd(transaction_fees),
+            baking:           ccd(baking),
+            finalization:     ccd(finalization),
+        }
+    }
+
+    fn account_reward(account: AccountAddress, rewards: [u64; 3]) -> SpecialTransactionOutcome {
+        SpecialTransactionOutcome::PaydayAccountReward {
+            account,
+            transaction_fees: ccd(rewards[0]),
+            baker_reward: ccd(rewards[1]),
+            finalization_reward: ccd(rewards[2]),
+        }
+    }
+
+    #[test]
+    fn test_from_outcomes() {
+        let outcomes = [
+            SpecialTransactionOutcome::PaydayFoundationReward {
+                foundation_account: FOUNDATION,
+                development_charge: ccd(7),
+            },
+            account_reward(BAKER, [1, 2, 3]),
+            account_reward(DELEGATOR, [4, 5, 0]),
+            SpecialTransactionOutcome::PaydayPoolReward {
+                pool_owner:          Some(BakerId { id: 1.into() }),
+                transaction_fees:    ccd(10),
+                baker_reward:        ccd(20),
+                finalization_reward: ccd(30),
+            },
+            SpecialTransactionOutcome::PaydayPoolReward {
+                pool_owner:          None,
+                transaction_fees:    ccd(40),
+                baker_reward:        ccd(0),
+                finalization_reward: ccd(0),
+            },
+            // An account that is paid twice has its rewards summed.
+            account_reward(DELEGATOR, [1, 1, 1]),
+        ];
+        let report = PaydayReport::from_outcomes(payday(10), &outcomes).unwrap();
+        assert_eq!(report.foundation_reward, Some((FOUNDATION, ccd(7))));
+        assert_eq!(report.accounts.len(), 2);
+        assert_eq!(report.accounts[&BAKER], breakdown(1, 2, 3));
+        assert_eq!(report.accounts[&DELEGATOR], breakdown(5, 6, 1));
+        assert_eq!(report.accounts[&DELEGATOR].total(), Some(ccd(12)));
+        assert_eq!(report.pools, [
+            (Some(BakerId { id: 1.into() }), breakdown(10, 20, 30)),
+            (None, breakdown(40, 0, 0)),
+        ]);
+
+        let later =
+            PaydayReport::from_outcomes(payday(20), &[account_reward(BAKER, [1, 1, 1])]).unwrap();
+        let rewards = RewardReport {
+            paydays: vec![report, later],
+        };
+        let totals = rewards.account_totals().unwrap();
+        assert_eq!(totals[&BAKER], breakdown(2, 3, 4));
+        assert_eq!(totals[&DELEGATOR], breakdown(5, 6, 1));
+        assert_eq!(rewards.account_rewards(&BAKER).count(), 2);
+        assert_eq!(rewards.account_rewards(&DELEGATOR).count(), 1);
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(breakdown(u64::MAX, 1, 0).total(), None);
+        let outcomes = [
+            account_reward(BAKER, [u64::MAX, 0, 0]),
+            account_reward(BAKER, [1, 0, 0]),
+        ];
+        assert!(PaydayReport::from_outcomes(payday(10), &outcomes).is_none());
+    }
+}
@@ -0,0 +1,423 @@
+//! Estimation of staking returns.
+//!
+//! Returns are computed for a baker pool or for passive delegation, both from
+//! the rewards that were actually paid out at past paydays, and projected from
+//! the current state of the chain. All returns are fractions of the stake,
+//! e.g., `0.05` is 5%. Annual returns assume that earnings are restaked.
+//!
+//! Projected returns are estimates. They assume that the pool keeps its
+//! current share of the stake, that it bakes blocks in proportion to its
+//! lottery power, and that transaction fees are the same as on average in the
+//! considered past paydays.
+use super::StakingError;
+use crate::{
+    endpoints::QueryResult,
+    types::{
+        hashes::BlockHash, AbsoluteBlockHeight, AmountFraction, BakerId, CommissionRates,
+        DelegationTarget, MintRate, RewardsOverview, SpecialTransactionOutcome,
+    },
+    v2::{self, ChainParameters, IntoBlockIdentifier},
+};
+use concordium_base::common::types::Amount;
+use futures::TryStreamExt;
+use rust_decimal::prelude::ToPrimitive;
+
+/// A block in which a payday occurred, i.e., in which rewards for the
+/// preceding reward period were paid out.
+#[derive(Debug, Clone, Copy)]
+pub struct Payday {
+    pub block_hash: BlockHash,
+    pub height:     AbsoluteBlockHeight,
+    /// The slot time of the payday block.
+    pub time:       chrono::DateTime<chrono::Utc>,
+}
+
+async fn next_payday_time(
+    client: &mut v2::Client,
+    bi: impl IntoBlockIdentifier,
+) -> QueryResult<Option<chrono::DateTime<chrono::Utc>>> {
+    match client.get_tokenomics_info(bi).await?.response {
+        RewardsOverview::V0 { .. } => Ok(None),
+        RewardsOverview::V1 {
+            next_payday_time, ..
+        } => Ok(Some(next_payday_time)),
+    }
+}
+
+/// Find the most recent payday at or before the given height. Returns
+/// [`None`] if there was no payday, e.g., because the chain was running a
+/// protocol version without paydays.
+pub async fn find_payday(
+    client: &mut v2::Client,
+    at_or_before: AbsoluteBlockHeight,
+) -> QueryResult<Option<Payday>> {
+    let Some(target) = next_payday_time(client, at_or_before).await? else {
+        return Ok(None);
+    };
+    // The time of the next payday only changes in payday blocks, so the
+    // most recent payday is the lowest block with the same next payday time.
+    let found = client
+        .find_at_lowest_height(..=at_or_before, |mut client, height| async move {
+            match next_payday_time(&mut client, height).await? {
+                Some(time) if time >= target => Ok(Some(height)),
+                _ => Ok(None),
+            }
+        })
+        .await;
+    let height = match found {
+        Ok(height) => height,
+        // The search does not test the upper bound itself.
+        Err(e) if e.is_not_found() => at_or_before,
+        Err(e) => return Err(e),
+    };
+    let is_payday = client.is_payday_block(height).await?;
+    if !is_payday.response {
+        return Ok(None);
+    }
+    let time = client.get_block_info(height).await?.response.block_slot_time;
+    Ok(Some(Payday {
+        block_hash: is_payday.block_hash,
+        height,
+        time,
+    }))
+}
+
+/// Find up to `count` most recent paydays at or before the given height, most
+/// recent first.
+pub async fn recent_paydays(
+    client: &mut v2::Client,
+    at_or_before: AbsoluteBlockHeight,
+    count: usize,
+) -> QueryResult<Vec<Payday>> {
+    let mut paydays = Vec::with_capacity(count);
+    let mut height = at_or_before;
+    while paydays.len() < count {
+        let Some(payday) = find_payday(client, height).await? else {
+            break;
+        };
+        paydays.push(payday);
+        if payday.height.height == 0 {
+            break;
+        }
+        height = AbsoluteBlockHeight::from(payday.height.height - 1);
+    }
+    Ok(paydays)
+}
+
+fn fraction(af: AmountFraction) -> f64 {
+    rust_decimal::Decimal::from(af).to_f64().unwrap_or(0.0)
+}
+
+fn mint_rate(rate: MintRate) -> f64 {
+    f64::from(rate.mantissa) * 10f64.powi(-i32::from(rate.exponent))
+}
+
+/// The amount in micro CCD, as a float for computing rates.
+fn micro_ccd_f64(amount: Amount) -> f64 { amount.micro_ccd() as f64 }
+
+/// Rewards of a pool in a reward period, split by kind, in micro CCD.
+#[derive(Debug, Clone, Copy, Default)]
+struct PoolRewards {
+    transaction_fees: f64,
+    baking:           f64,
+    finalization:     f64,
+}
+
+impl PoolRewards {
+    fn total(&self) -> f64 { self.transaction_fees + self.baking + self.finalization }
+
+    /// The return per unit of stake for delegators, and for the baker.
+    fn returns(
+        &self,
+        commissions: &CommissionRates,
+        equity: f64,
+        delegated: f64,
+    ) -> (Option<f64>, Option<f64>) {
+        let total_stake = equity + delegated;
+        if total_stake <= 0.0 {
+            return (None, None);
+        }
+        let delegator = ((1.0 - fraction(commissions.transaction)) * self.transaction_fees
+            + (1.0 - fraction(commissions.baking)) * self.baking
+            + (1.0 - fraction(commissions.finalization)) * self.finalization)
+            / total_stake;
+        let baker = (equity > 0.0).then(|| (self.total() - delegator * delegated) / equity);
+        (baker, Some(delegator))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Realized returns of a pool in a single reward period.
+pub struct PeriodReturn {
+    /// The payday at which the rewards for the period were paid.
+    pub payday:           Payday,
+    /// Length of the reward period.
+    pub period:           chrono::Duration,
+    /// Total rewards paid to the pool.
+    pub pool_reward:      Amount,
+    /// The return of the baker on its equity capital. This is [`None`] for
+    /// passive delegation.
+    pub baker_return:     Option<f64>,
+    /// The return of delegators on their stake.
+    pub delegator_return: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+/// Historical and projected returns of a pool.
+pub struct ApyEstimate {
+    pub target:                   DelegationTarget,
+    /// Returns in each of the considered past reward periods, most recent
+    /// first.
+    pub periods:                  Vec<PeriodReturn>,
+    /// Annualized return of the baker, based on the past reward periods.
+    pub historical_baker_apy:     Option<f64>,
+    /// Annualized return of delegators, based on the past reward periods.
+    pub historical_delegator_apy: Option<f64>,
+    /// Annualized return of the baker projected from the current state.
+    pub projected_baker_apy:      Option<f64>,
+    /// Annualized return of delegators projected from the current state.
+    pub projected_delegator_apy:  Option<f64>,
+}
+
+/// Annualize the given returns, assuming they are compounded.
+fn annualize(returns: impl IntoIterator<Item = (f64, chrono::Duration)>) -> Option<f64> {
+    let mut factor = 1.0f64;
+    let mut seconds = 0i64;
+    for (r, period) in returns {
+        factor *= 1.0 + r;
+        seconds += period.num_seconds();
+    }
+    if seconds <= 0 {
+        return None;
+    }
+    let year = 365.25 * 24.0 * 60.0 * 60.0;
+    Some(factor.powf(year / seconds as f64) - 1.0)
+}
+
+/// The state of the pool during the reward period that ended at the given
+/// payday: commission rates, equity capital, delegated capital, and the share
+/// of the total stake.
+async fn pool_state(
+    client: &mut v2::Client,
+    target: &DelegationTarget,
+    bi: impl IntoBlockIdentifier,
+) -> Result<Option<(CommissionRates, f64, f64, f64)>, StakingError> {
+    match target {
+        DelegationTarget::Passive => {
+            let status = client.get_passive_delegation_info(bi).await?.response;
+            let capital = micro_ccd_f64(status.current_payday_delegated_capital);
+            let total = micro_ccd_f64(status.all_pool_total_capital);
+            let share = if total > 0.0 { capital / total } else { 0.0 };
+            Ok(Some((status.commission_rates, 0.0, capital, share)))
+        }
+        DelegationTarget::Baker { baker_id } => {
+            let status = client.get_pool_info(bi, *baker_id).await?.response;
+            Ok(status.current_payday_status.map(|current| {
+                (
+                    status.pool_info.commission_rates,
+                    micro_ccd_f64(current.baker_equity_capital),
+                    micro_ccd_f64(current.delegated_capital),
+                    current.lottery_power,
+                )
+            }))
+        }
+    }
+}
+
+/// Get the rewards paid to the pool at the payday, by kind and in total.
+async fn payday_rewards(
+    client: &mut v2::Client,
+    target: &DelegationTarget,
+    payday: &Payday,
+) -> Result<(PoolRewards, Amount), StakingError> {
+    let owner: Option<BakerId> = match target {
+        DelegationTarget::Passive => None,
+        DelegationTarget::Baker { baker_id } => Some(*baker_id),
+    };
+    let events = client
+        .get_block_special_events(payday.block_hash)
+        .await?
+        .response
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(v2::QueryError::from)?;
+    let mut rewards = PoolRewards::default();
+    let mut total = 0u64;
+    for event in events {
+        if let SpecialTransactionOutcome::PaydayPoolReward {
+            pool_owner,
+            transaction_fees,
+            baker_reward,
+            finalization_reward,
+        } = event
+        {
+            if pool_owner == owner {
+                rewards.transaction_fees += micro_ccd_f64(transaction_fees);
+                rewards.baking += micro_ccd_f64(baker_reward);
+                rewards.finalization += micro_ccd_f64(finalization_reward);
+                total = total
+                    .saturating_add(transaction_fees.micro_ccd())
+                    .saturating_add(baker_reward.micro_ccd())
+                    .saturating_add(finalization_reward.micro_ccd());
+            }
+        }
+    }
+    Ok((rewards, Amount::from_micro_ccd(total)))
+}
+
+/// Estimate the returns of the given pool from the last `paydays` reward
+/// periods before the given block, and from the state of the chain in the
+/// block.
+pub async fn estimate_apy(
+    client: &mut v2::Client,
+    target: DelegationTarget,
+    bi: impl IntoBlockIdentifier,
+    paydays: usize,
+) -> Result<ApyEstimate, StakingError> {
+    let block = client.get_block_info(bi).await?.response;
+    // One more payday is needed to know the length of the oldest period.
+    let found = recent_paydays(client, block.block_height, paydays + 1).await?;
+    if found.is_empty() {
+        return Err(StakingError::UnsupportedProtocol);
+    }
+    let mut periods = Vec::with_capacity(paydays);
+    let mut fees = Vec::with_capacity(paydays);
+    for window in found.windows(2) {
+        let (payday, previous) = (window[0], window[1]);
+        let (rewards, pool_reward) = payday_rewards(client, &target, &payday).await?;
+        let before = AbsoluteBlockHeight::from(payday.height.height.saturating_sub(1));
+        let Some((commissions, equity, delegated, _)) =
+            pool_state(client, &target, before).await?
+        else {
+            continue;
+        };
+        let (baker_return, delegator_return) = rewards.returns(&commissions, equity, delegated);
+        fees.push(rewards.transaction_fees);
+        periods.push(PeriodReturn {
+            payday,
+            period: payday.time - previous.time,
+            pool_reward,
+            baker_return,
+            delegator_return,
+        });
+    }
+    let historical_baker_apy = annualize(
+        periods
+            .iter()
+            .filter_map(|p| Some((p.baker_return?, p.period))),
+    );
+    let historical_delegator_apy = annualize(
+        periods
+            .iter()
+            .filter_map(|p| Some((p.delegator_return?, p.period))),
+    );
+
+    // Project the rewards of the next payday from the current state.
+    let (projected_baker_apy, projected_delegator_apy) = {
+        let last_payday = found[0];
+        let tokenomics = client.get_tokenomics_info(block.block_hash).await?.response;
+        let RewardsOverview::V1 {
+            common,
+            next_payday_time,
+            next_payday_mint_rate,
+            ..
+        } = tokenomics
+        else {
+            return Err(StakingError::UnsupportedProtocol);
+        };
+        let (baking_fraction, finalization_fraction) =
+            match client.get_block_chain_parameters(block.block_hash).await?.response {
+                ChainParameters::V0(_) => return Err(StakingError::UnsupportedProtocol),
+                ChainParameters::V1(params) => (
+                    fraction(params.mint_distribution.baking_reward),
+                    fraction(params.mint_distribution.finalization_reward),
+                ),
+                ChainParameters::V2(params) => (
+                    fraction(params.mint_distribution.baking_reward),
+                    fraction(params.mint_distribution.finalization_reward),
+                ),
+            };
+        match pool_state(client, &target, block.block_hash).await? {
+            Some((commissions, equity, delegated, share)) => {
+                let minted = mint_rate(next_payday_mint_rate) * micro_ccd_f64(common.total_amount);
+                let average_fees = if fees.is_empty() {
+                    0.0
+                } else {
+                    fees.iter().sum::<f64>() / fees.len() as f64
+                };
+                let rewards = PoolRewards {
+                    transaction_fees: average_fees,
+                    baking:           minted * baking_fraction * share,
+                    finalization:     minted * finalization_fraction * share,
+                };
+                let (baker, delegator) = rewards.returns(&commissions, equity, delegated);
+                let period = next_payday_time - last_payday.time;
+                (
+                    baker.and_then(|r| annualize([(r, period)])),
+                    delegator.and_then(|r| annualize([(r, period)])),
+                )
+            }
+            None => (None, None),
+        }
+    };
+
+    Ok(ApyEstimate {
+        target,
+        periods,
+        historical_baker_apy,
+        historical_delegator_apy,
+        projected_baker_apy,
+        projected_delegator_apy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(percent: u32) -> CommissionRates {
+        let rate = AmountFraction::new_unchecked(percent * 1000);
+        CommissionRates {
+            finalization: rate,
+            baking:       rate,
+            transaction:  rate,
+        }
+    }
+
+    #[test]
+    fn test_annualize() {
+        let year = chrono::Duration::seconds(31_557_600);
+        let half_year = chrono::Duration::seconds(31_557_600 / 2);
+        let apy = annualize([(0.05, year)]).unwrap();
+        assert!((apy - 0.05).abs() < 1e-9);
+        // Two half years of 5% compound to 10.25%.
+        let apy = annualize([(0.05, half_year), (0.05, half_year)]).unwrap();
+        assert!((apy - 0.1025).abs() < 1e-9);
+        assert_eq!(annualize(std::iter::empty()), None);
+        assert_eq!(annualize([(0.05, chrono::Duration::zero())]), None);
+    }
+
+    #[test]
+    fn test_returns() {
+        let rewards = PoolRewards {
+            transaction_fees: 100.0,
+            baking:           700.0,
+            finalization:     200.0,
+        };
+        // With a 10% commission, delegators get 90% of the rewards in proportion
+        // to their stake, and the baker gets the rest.
+        let (baker, delegator) = rewards.returns(&rates(10), 1000.0, 9000.0);
+        let delegator = delegator.unwrap();
+        assert!((delegator - 0.09).abs() < 1e-9);
+        assert!((baker.unwrap() - (1000.0 - 0.09 * 9000.0) / 1000.0).abs() < 1e-9);
+        // Without delegators the baker gets all the rewards.
+        let (baker, delegator) = rewards.returns(&rates(10), 1000.0, 0.0);
+        assert!((baker.unwrap() - 1.0).abs() < 1e-9);
+        assert!((delegator.unwrap() - 0.9).abs() < 1e-9);
+        // Passive delegation has no baker.
+        let (baker, delegator) = rewards.returns(&rates(10), 0.0, 10000.0);
+        assert_eq!(baker, None);
+        assert!((delegator.unwrap() - 0.09).abs() < 1e-9);
+        assert_eq!(rewards.returns(&rates(10), 0.0, 0.0), (None, None));
+    }
+}
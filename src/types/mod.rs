@@ -101,6 +101,25 @@ impl<'a> EncryptedAmountDecryptionContext<'a> {
             ),
         }
     }
+
+    /// Construct the decryption context from cryptographic parameters and a
+    /// previously computed table, e.g., one obtained via
+    /// [`table`](Self::table) and stored. The table must have been
+    /// constructed for the same cryptographic parameters.
+    pub fn from_table(
+        params: &'a concordium_base::id::types::GlobalContext<EncryptedAmountsCurve>,
+        table: elgamal::BabyStepGiantStep<EncryptedAmountsCurve>,
+    ) -> Self {
+        Self { params, table }
+    }
+
+    /// The cryptographic parameters the context was constructed with.
+    pub fn params(&self) -> &'a concordium_base::id::types::GlobalContext<EncryptedAmountsCurve> {
+        self.params
+    }
+
+    /// The precomputed table used to speed up decryption.
+    pub fn table(&self) -> &elgamal::BabyStepGiantStep<EncryptedAmountsCurve> { &self.table }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -827,6 +846,14 @@ impl SpecialTransactionOutcome {
             SpecialTransactionOutcome::PaydayPoolReward { .. } => Vec::new(),
         }
     }
+
+    /// Return whether the given account, or any of its aliases, is among the
+    /// [affected addresses](Self::affected_addresses).
+    pub fn affects_account(&self, address: &AccountAddress) -> bool {
+        self.affected_addresses()
+            .iter()
+            .any(|affected| affected.is_alias(address))
+    }
 }
 
 #[derive(SerdeSerialize, SerdeDeserialize, Debug, Clone)]
@@ -1213,6 +1240,14 @@ impl BlockItemSummary {
             Vec::new()
         }
     }
+
+    /// Return whether the given account, or any of its aliases, is among the
+    /// [affected addresses](Self::affected_addresses).
+    pub fn affects_account(&self, address: &AccountAddress) -> bool {
+        self.affected_addresses()
+            .iter()
+            .any(|affected| affected.is_alias(address))
+    }
 }
 
 #[derive(Debug, Clone)]
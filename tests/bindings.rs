@@ -0,0 +1,37 @@
+//! Bindings generated by `smart_contracts::bindings` compile, and their types
+//! serialize as described by the schema they were generated from.
+//!
+//! The bindings in `bindings/counter.rs` are checked to be up to date with the
+//! generator by a unit test in `smart_contracts::bindings`.
+#[allow(dead_code)]
+mod counter {
+    include!("bindings/counter.rs");
+}
+
+use concordium_rust_sdk::smart_contracts::common::{
+    from_bytes,
+    schema::{Fields, Type},
+    to_bytes, AccountAddress, Cursor,
+};
+use counter::{CounterEvent, IncrementError, IncrementParameter};
+
+#[test]
+fn test_round_trip() {
+    let parameter = IncrementParameter { by: 5 };
+    let bytes = to_bytes(&parameter);
+    assert_eq!(from_bytes::<IncrementParameter>(&bytes), Ok(parameter));
+    let ty = Type::Struct(Fields::Named(vec![("by".into(), Type::U64)]));
+    let json = ty.to_json(&mut Cursor::new(&bytes)).unwrap();
+    assert_eq!(json, serde_json::json!({ "by": 5 }));
+
+    let event = CounterEvent::Incremented {
+        by:    1,
+        owner: AccountAddress([7u8; 32]),
+    };
+    let bytes = to_bytes(&event);
+    assert_eq!(bytes.len(), 1 + 8 + 32);
+    assert_eq!(from_bytes::<CounterEvent>(&bytes), Ok(event));
+
+    assert_eq!(to_bytes(&IncrementError::Overflow), [0]);
+    assert!(from_bytes::<IncrementError>(&[1]).is_err());
+}
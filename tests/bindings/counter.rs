@@ -0,0 +1,131 @@
+// Bindings for the `counter` contract, generated from its schema by
+// `concordium_rust_sdk::smart_contracts::bindings`. Do not edit.
+
+#[allow(unused_imports)]
+use concordium_rust_sdk::smart_contracts::common as concordium_std;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, concordium_std::Serial, concordium_std::Deserial)]
+pub struct IncrementParameter {
+    pub by: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, concordium_std::Serial, concordium_std::Deserial)]
+pub enum IncrementError {
+    Overflow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, concordium_std::Serial, concordium_std::Deserial)]
+pub enum CounterEvent {
+    Incremented {
+    by: u64,
+    owner: concordium_std::AccountAddress,
+},
+}
+
+/// Marker type of [`CounterContract`].
+#[derive(Debug, Clone, Copy)]
+pub enum CounterType {}
+
+/// A client for instances of the `counter` contract.
+#[derive(Debug, Clone)]
+pub struct CounterContract {
+    pub client: concordium_rust_sdk::contract_client::ContractClient<CounterType>,
+}
+
+impl CounterContract {
+    /// Construct a client for the instance at the address.
+    pub async fn create(
+        client: concordium_rust_sdk::v2::Client,
+        address: concordium_std::ContractAddress,
+    ) -> Result<Self, concordium_rust_sdk::contract_client::SchemaError> {
+        let client = concordium_rust_sdk::contract_client::ContractClient::create(client, address).await?;
+        Ok(Self { client })
+    }
+
+    /// Invoke the `get` entrypoint and decode its return value.
+    pub async fn view_get(
+        &mut self,
+        parameter: concordium_rust_sdk::types::smart_contracts::OwnedParameter,
+        bi: impl concordium_rust_sdk::v2::IntoBlockIdentifier,
+    ) -> Result<u64, concordium_rust_sdk::contract_client::SchemaError> {
+        let result = self
+            .client
+            .invoke_raw::<concordium_rust_sdk::contract_client::SchemaError>(
+                "get",
+                concordium_std::Amount::zero(),
+                None,
+                parameter,
+                bi,
+            )
+            .await?;
+        concordium_rust_sdk::contract_client::parse_view_result(result)
+    }
+
+    /// Invoke the `get` entrypoint without sending a transaction.
+    pub async fn dry_run_get(
+        &mut self,
+        invoker: Option<concordium_std::Address>,
+        amount: concordium_std::Amount,
+        parameter: concordium_rust_sdk::types::smart_contracts::OwnedParameter,
+        bi: impl concordium_rust_sdk::v2::IntoBlockIdentifier,
+    ) -> Result<
+        concordium_rust_sdk::types::smart_contracts::InvokeContractResult,
+        concordium_rust_sdk::contract_client::SchemaError,
+    > {
+        self.client.invoke_raw("get", amount, invoker, parameter, bi).await
+    }
+
+    /// Send a transaction to the `get` entrypoint.
+    pub async fn update_get(
+        &mut self,
+        signer: &impl concordium_rust_sdk::types::transactions::ExactSizeTransactionSigner,
+        metadata: &concordium_rust_sdk::contract_client::ContractTransactionMetadata,
+        parameter: concordium_rust_sdk::types::smart_contracts::OwnedParameter,
+    ) -> Result<concordium_rust_sdk::types::hashes::TransactionHash, concordium_rust_sdk::contract_client::SchemaError> {
+        self.client.update_raw(signer, metadata, "get", parameter).await
+    }
+
+    /// Invoke the `increment` entrypoint without sending a transaction.
+    pub async fn dry_run_increment(
+        &mut self,
+        invoker: Option<concordium_std::Address>,
+        amount: concordium_std::Amount,
+        parameter: &IncrementParameter,
+        bi: impl concordium_rust_sdk::v2::IntoBlockIdentifier,
+    ) -> Result<
+        concordium_rust_sdk::types::smart_contracts::InvokeContractResult,
+        concordium_rust_sdk::contract_client::SchemaError,
+    > {
+        let parameter =
+            concordium_rust_sdk::types::smart_contracts::OwnedParameter::from_serial(parameter)?;
+        self.client.invoke_raw("increment", amount, invoker, parameter, bi).await
+    }
+
+    /// Send a transaction to the `increment` entrypoint.
+    pub async fn update_increment(
+        &mut self,
+        signer: &impl concordium_rust_sdk::types::transactions::ExactSizeTransactionSigner,
+        metadata: &concordium_rust_sdk::contract_client::ContractTransactionMetadata,
+        parameter: &IncrementParameter,
+    ) -> Result<concordium_rust_sdk::types::hashes::TransactionHash, concordium_rust_sdk::contract_client::SchemaError> {
+        let parameter =
+            concordium_rust_sdk::types::smart_contracts::OwnedParameter::from_serial(parameter)?;
+        self.client.update_raw(signer, metadata, "increment", parameter).await
+    }
+
+    /// Decode the error returned when the `increment` entrypoint rejects.
+    pub fn decode_increment_error(
+        &self,
+        return_value: &[u8],
+    ) -> concordium_std::ParseResult<IncrementError> {
+        concordium_std::from_bytes(return_value)
+    }
+
+    /// Decode events logged by the contract.
+    pub fn decode_events(
+        &self,
+        events: &[concordium_rust_sdk::types::smart_contracts::ContractEvent],
+    ) -> concordium_std::ParseResult<Vec<CounterEvent>> {
+        events.iter().map(|event| concordium_std::from_bytes(event.as_ref())).collect()
+    }
+}